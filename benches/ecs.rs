@@ -0,0 +1,67 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use weaver::prelude::*;
+
+const ENTITIES: usize = 10_000;
+
+#[derive(Component, Default)]
+struct Velocity(Vec3);
+
+fn spawn(world: &World) {
+    for i in 0..ENTITIES {
+        let transform = Transform::from_translation(Vec3::new(i as f32, 0.0, 0.0));
+        world.spawn((transform, Velocity::default()));
+    }
+}
+
+fn query_iter(c: &mut Criterion) {
+    let world = World::new();
+    spawn(&world);
+
+    c.bench_function("query_iter", |b| {
+        b.iter(|| {
+            let query = world.query::<&Transform>();
+            let mut sum = 0.0;
+            for (_, transform) in query.iter() {
+                sum += transform.translation.x;
+            }
+            black_box(sum)
+        })
+    });
+
+    c.bench_function("query_iter_mut", |b| {
+        b.iter(|| {
+            let query = world.query::<(&mut Transform, &Velocity)>();
+            for (_, (mut transform, velocity)) in query.iter() {
+                transform.translation += velocity.0;
+            }
+        })
+    });
+
+    c.bench_function("query_par_iter_mut", |b| {
+        b.iter(|| {
+            let query = world.query::<(&mut Transform, &Velocity)>();
+            query.par_for_each(|(_, (mut transform, velocity))| {
+                transform.translation += velocity.0;
+            });
+        })
+    });
+}
+
+fn insert_remove(c: &mut Criterion) {
+    c.bench_function("insert_remove", |b| {
+        b.iter(|| {
+            let world = World::new();
+            spawn(&world);
+            let entities = world
+                .query::<&Transform>()
+                .entity_iter()
+                .collect::<Vec<_>>();
+            for entity in entities {
+                world.remove_component::<Velocity>(entity);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, query_iter, insert_remove);
+criterion_main!(benches);
@@ -0,0 +1,98 @@
+use std::sync::{atomic::AtomicU64, Arc};
+
+use weaver_ecs::{component::Res, prelude::Resource, world::World};
+use weaver_event::{Event, EventRx};
+use weaver_util::prelude::{anyhow, bail, Result};
+
+use crate::system::{run_with_ticks, SystemAccess, SystemParam};
+
+// decides whether a system runs this time around; it's a system itself, but one that only reads
+pub trait Condition: 'static + Send + Sync {
+    fn access(&self) -> SystemAccess;
+    fn evaluate(&self, world: &Arc<World>) -> Result<bool>;
+}
+
+pub trait IntoCondition<Marker>: 'static + Send + Sync {
+    fn into_condition(self) -> Result<Arc<dyn Condition>>;
+}
+
+macro_rules! impl_into_condition {
+    ($($param:ident),*) => {
+        impl<Func, $($param),*> IntoCondition<fn($($param),*) -> bool> for Func
+        where
+            Func: Fn($($param),*) -> bool + 'static + Send + Sync,
+            $($param: SystemParam + 'static + Send + Sync),*
+        {
+            #[allow(unused_parens, non_snake_case)]
+            fn into_condition(self) -> Result<Arc<dyn Condition>> {
+                struct FunctionCondition<Func, $($param: SystemParam),*> {
+                    func: Func,
+                    last_run: AtomicU64,
+                    state: ($($param::State,)*),
+                    _marker: std::marker::PhantomData<($($param),*)>,
+                }
+
+                impl<Func, $($param),*> Condition for FunctionCondition<Func, $($param),*>
+                where
+                    Func: Fn($($param),*) -> bool + 'static + Send + Sync,
+                    $($param: SystemParam + 'static + Send + Sync),*
+                {
+                    fn access(&self) -> SystemAccess {
+                        let mut access = SystemAccess::default();
+
+                        $(
+                            access.extend($param::access());
+                        )*
+
+                        access
+                    }
+
+                    fn evaluate(&self, world: &Arc<World>) -> Result<bool> {
+                        run_with_ticks(&self.last_run, world, || {
+                            let ($($param,)*) = &self.state;
+                            let ($($param),*) = ($($param::fetch(world, $param).ok_or_else(|| anyhow!("Failed to fetch condition param"))?),*);
+                            Ok((self.func)($($param),*))
+                        })
+                    }
+                }
+
+                let condition = FunctionCondition {
+                    func: self,
+                    last_run: AtomicU64::new(0),
+                    state: Default::default(),
+                    _marker: std::marker::PhantomData,
+                };
+
+                // conditions are evaluated while other systems run, and nothing they defer is applied
+                let access = condition.access();
+                if access.exclusive
+                    || !access.resources_written.is_empty()
+                    || !access.components_written.is_empty()
+                {
+                    bail!("Run conditions can only read from the world");
+                }
+
+                Ok(Arc::new(condition))
+            }
+        }
+    };
+}
+
+impl_into_condition!(A);
+impl_into_condition!(A, B);
+impl_into_condition!(A, B, C);
+impl_into_condition!(A, B, C, D);
+
+pub fn resource_exists<T: Resource>(resource: Option<Res<T>>) -> bool {
+    resource.is_some()
+}
+
+// true on the first run after the resource is inserted or mutably dereferenced
+pub fn resource_changed<T: Resource>(resource: Option<Res<T>>) -> bool {
+    resource.is_some_and(|resource| resource.is_changed())
+}
+
+// true while there are events of the kind waiting, i.e. until the next event pump
+pub fn on_event<T: Event>(events: Option<EventRx<T>>) -> bool {
+    events.is_some_and(|events| !events.is_empty())
+}
@@ -1,25 +1,39 @@
 use std::sync::Arc;
 
+use condition::IntoCondition;
+use petgraph::graph::NodeIndex;
 use plugin::Plugin;
-use rustc_hash::FxHashMap;
-use system::{FunctionSystem, SystemGraph, SystemStage};
+use schedule::{LoopPhase, Schedule, ScheduleLabel, Schedules};
+use state::{NextState, State, StateTransition, StateUpdate, States};
+use system::{FunctionSystem, LabelId, SystemGraph, SystemSet, SystemStage};
 use weaver_ecs::{
     bundle::Bundle,
-    component::{Res, ResMut, Resource},
+    component::{Component, Res, ResMut, Resource},
     entity::Entity,
+    relationship::Relationship,
     scene::Scene,
     storage::Ref,
     world::World,
 };
 use weaver_event::{Event, Events};
-use weaver_reflect::registry::{TypeRegistry, Typed};
-use weaver_util::{lock::SharedLock, prelude::Result};
+use weaver_reflect::{
+    component::{ReflectComponent, ReflectRelationship},
+    registry::{ReflectDefault, TypeRegistry, Typed},
+};
+use weaver_util::{
+    lock::SharedLock,
+    prelude::{anyhow, Result},
+};
 
+pub mod condition;
 pub mod plugin;
+pub mod schedule;
+pub mod state;
 pub mod system;
 
 pub mod prelude {
     pub use crate::plugin::Plugin;
+    pub use crate::state::{in_state, NextState, OnEnter, OnExit, OnUpdate, State, States};
     pub use crate::App;
 }
 
@@ -38,7 +52,6 @@ where
 
 pub struct App {
     world: Arc<World>,
-    systems: SharedLock<FxHashMap<SystemStage, SystemGraph>>,
     plugins: SharedLock<Vec<Box<dyn Plugin>>>,
     runner: Option<Box<dyn Runner>>,
     runtime: rayon::ThreadPool,
@@ -48,15 +61,21 @@ impl App {
     pub fn new() -> Result<Self> {
         let world = World::new();
 
-        let this = Self {
+        let mut this = Self {
             world,
-            systems: SharedLock::new(FxHashMap::default()),
             plugins: SharedLock::new(Vec::new()),
             runner: None,
             runtime: rayon::ThreadPoolBuilder::new().build().unwrap(),
         };
 
         this.insert_resource(TypeRegistry::new());
+        this.insert_resource(Schedules::with_stages());
+
+        fn clear_removed_components(world: &Arc<World>) -> Result<()> {
+            world.clear_removed_components();
+            Ok(())
+        }
+        this.add_system(clear_removed_components, SystemStage::EventPump)?;
 
         Ok(this)
     }
@@ -81,6 +100,53 @@ impl App {
             .register::<T>();
     }
 
+    // registers a component type so scenes containing it can be saved and loaded; the default
+    // also lets it be read as an item of a list
+    pub fn register_component<T: Component + Typed + Default>(&self) {
+        let mut registry = self.get_resource_mut::<TypeRegistry>().unwrap();
+        registry.register::<T>();
+        registry.register_type_data::<T, ReflectComponent>();
+        registry.register_type_data::<T, ReflectDefault>();
+    }
+
+    pub fn register_relationship<T: Relationship + Typed + Default>(&self) {
+        let mut registry = self.get_resource_mut::<TypeRegistry>().unwrap();
+        registry.register::<T>();
+        registry.register_type_data::<T, ReflectRelationship>();
+        registry.register_type_data::<T, ReflectDefault>();
+    }
+
+    // for types used as list items in components, which need a default to be read
+    pub fn register_default<T: Typed + Default>(&self) {
+        let mut registry = self.get_resource_mut::<TypeRegistry>().unwrap();
+        registry.register::<T>();
+        registry.register_type_data::<T, ReflectDefault>();
+    }
+
+    pub fn on_insert<T: Component>(
+        &self,
+        hook: impl Fn(&World, Entity) + Send + Sync + 'static,
+    ) -> &Self {
+        self.world.on_insert::<T>(hook);
+        self
+    }
+
+    pub fn on_replace<T: Component>(
+        &self,
+        hook: impl Fn(&World, Entity) + Send + Sync + 'static,
+    ) -> &Self {
+        self.world.on_replace::<T>(hook);
+        self
+    }
+
+    pub fn on_remove<T: Component>(
+        &self,
+        hook: impl Fn(&World, Entity) + Send + Sync + 'static,
+    ) -> &Self {
+        self.world.on_remove::<T>(hook);
+        self
+    }
+
     pub fn add_event<T: Event>(&mut self) -> &mut Self {
         fn update_events<T: Event>(mut events: ResMut<Events<T>>) -> Result<()> {
             events.clear();
@@ -92,6 +158,32 @@ impl App {
         self
     }
 
+    // adds `State<S>` starting out in `S::default()`, along with `NextState<S>` and the
+    // systems that run the state's `OnEnter`, `OnExit` and `OnUpdate` schedules
+    pub fn add_state<S: States + Default>(&mut self) -> Result<&mut Self> {
+        self.insert_resource(State::new(S::default()));
+        self.insert_resource(NextState::<S>::default());
+
+        let (has_transition, has_update) = {
+            let schedules = self.get_resource::<Schedules>().unwrap();
+            (
+                schedules.is_in_main_loop(&LabelId::of(StateTransition)),
+                schedules.is_in_main_loop(&LabelId::of(StateUpdate)),
+            )
+        };
+        if !has_transition {
+            self.add_schedule_before(StateTransition, SystemStage::Update)?;
+        }
+        if !has_update {
+            self.add_schedule_after(StateUpdate, SystemStage::Update)?;
+        }
+
+        self.add_system(state::enter_initial_state::<S>, SystemStage::PostInit)?;
+        self.add_system(state::apply_state_transition::<S>, StateTransition)?;
+        self.add_system(state::run_state_update::<S>, StateUpdate)?;
+        Ok(self)
+    }
+
     pub fn insert_resource<T: Resource>(&self, resource: T) -> &Self {
         self.world.insert_resource(resource);
         self
@@ -117,57 +209,109 @@ impl App {
         self.world.root_scene()
     }
 
-    pub fn add_system<M>(
+    // the schedule with the label, which is added (outside the main loop) if it doesn't exist
+    pub fn schedule(&self, label: impl ScheduleLabel) -> SharedLock<Schedule> {
+        self.get_resource_mut::<Schedules>()
+            .unwrap()
+            .get_or_insert(label)
+    }
+
+    // adds a schedule that runs right after `after` in the main loop, e.g. a `Physics`
+    // schedule after `SystemStage::Update`
+    pub fn add_schedule_after(
         &mut self,
-        system: impl FunctionSystem<M> + 'static,
-        stage: SystemStage,
+        label: impl ScheduleLabel,
+        after: impl ScheduleLabel,
     ) -> Result<&mut Self> {
-        self.systems
-            .write()
-            .entry(stage)
-            .or_default()
-            .add_system(system);
+        self.get_resource_mut::<Schedules>()
+            .unwrap()
+            .insert_after(label, after)?;
+        Ok(self)
+    }
+
+    pub fn add_schedule_before(
+        &mut self,
+        label: impl ScheduleLabel,
+        before: impl ScheduleLabel,
+    ) -> Result<&mut Self> {
+        self.get_resource_mut::<Schedules>()
+            .unwrap()
+            .insert_before(label, before)?;
         Ok(self)
     }
 
+    fn add_to_schedule(
+        &mut self,
+        label: impl ScheduleLabel,
+        f: impl FnOnce(&mut SystemGraph) -> NodeIndex,
+    ) -> SystemConfig<'_> {
+        let (schedule, node) = {
+            let schedule = self.schedule(label);
+            let mut schedule = schedule.write();
+            let node = f(schedule.systems_mut());
+            (schedule.label().clone(), node)
+        };
+        SystemConfig {
+            app: self,
+            schedule,
+            node,
+        }
+    }
+
+    pub fn add_system<M>(
+        &mut self,
+        system: impl FunctionSystem<M> + 'static,
+        schedule: impl ScheduleLabel,
+    ) -> Result<SystemConfig<'_>> {
+        Ok(self.add_to_schedule(schedule, |systems| systems.add_system(system)))
+    }
+
     pub fn add_system_before<M1, M2>(
         &mut self,
         system: impl FunctionSystem<M1> + 'static,
         before: impl FunctionSystem<M2> + 'static,
-        stage: SystemStage,
-    ) -> Result<&mut Self> {
-        self.systems
-            .write()
-            .entry(stage)
-            .or_default()
-            .add_system_before(system, before);
-        Ok(self)
+        schedule: impl ScheduleLabel,
+    ) -> Result<SystemConfig<'_>> {
+        Ok(self.add_to_schedule(schedule, |systems| {
+            systems.add_system_before(system, before)
+        }))
     }
 
     pub fn add_system_after<M1, M2>(
         &mut self,
         system: impl FunctionSystem<M1> + 'static,
         after: impl FunctionSystem<M2> + 'static,
-        stage: SystemStage,
-    ) -> Result<&mut Self> {
-        self.systems
+        schedule: impl ScheduleLabel,
+    ) -> Result<SystemConfig<'_>> {
+        Ok(self.add_to_schedule(schedule, |systems| systems.add_system_after(system, after)))
+    }
+
+    // the sets don't need any systems yet; that's only checked once the schedule first runs
+    pub fn order_sets(
+        &mut self,
+        before: impl SystemSet,
+        after: impl SystemSet,
+        schedule: impl ScheduleLabel,
+    ) -> &mut Self {
+        self.schedule(schedule)
             .write()
-            .entry(stage)
-            .or_default()
-            .add_system_after(system, after);
-        Ok(self)
+            .systems_mut()
+            .order_sets(before, after);
+        self
     }
 
-    pub fn run_systems(&self, stage: SystemStage) -> Result<()> {
-        let systems = self.systems.read();
-        if let Some(systems) = systems.get(&stage) {
-            let world = self.world.clone();
-            let (tx, rx) = crossbeam_channel::unbounded();
-            self.runtime
-                .install(move || tx.send(systems.run_concurrent(&world)).unwrap());
-            rx.recv().unwrap()?;
-        }
-        Ok(())
+    pub fn run_schedule(&self, label: impl ScheduleLabel) -> Result<()> {
+        let world = self.world.clone();
+        let label = LabelId::of(label);
+        self.runtime
+            .install(move || schedule::run_schedule(&world, &label))
+    }
+
+    // runs the schedules of a part of the main loop, in the order plugins arranged them in
+    pub fn run_phase(&self, phase: LoopPhase) -> Result<()> {
+        let world = self.world.clone();
+        self.runtime
+            .install(move || schedule::run_phase(&world, phase))
     }
 
     pub fn run(&mut self) -> Result<()> {
@@ -182,3 +326,115 @@ impl App {
         }
     }
 }
+
+// returned when a system is added, to configure that system in particular; it derefs to the
+// `App`, so adding systems can still be chained
+pub struct SystemConfig<'a> {
+    app: &'a mut App,
+    schedule: LabelId,
+    node: NodeIndex,
+}
+
+impl<'a> SystemConfig<'a> {
+    pub fn schedule(&self) -> &LabelId {
+        &self.schedule
+    }
+
+    pub fn node(&self) -> NodeIndex {
+        self.node
+    }
+
+    fn configure(self, f: impl FnOnce(&mut SystemGraph, NodeIndex) -> Result<()>) -> Result<Self> {
+        let schedule = self
+            .app
+            .get_resource::<Schedules>()
+            .unwrap()
+            .get(&self.schedule)
+            .ok_or_else(|| anyhow!("No schedule {}", self.schedule.name()))?;
+        f(schedule.write().systems_mut(), self.node)?;
+        Ok(self)
+    }
+
+    // only runs the system when `condition` holds, e.g.
+    // `app.add_system(render, SystemStage::Render)?.run_if(resource_exists::<Renderer>)?`
+    pub fn run_if<M>(self, condition: impl IntoCondition<M>) -> Result<Self> {
+        self.configure(|systems, node| systems.add_condition(node, condition))
+    }
+
+    // adds the system to `set`, within its schedule
+    pub fn in_set(self, set: impl SystemSet) -> Result<Self> {
+        self.configure(|systems, node| systems.add_to_set(node, set))
+    }
+
+    // runs the system before every system in `set`
+    pub fn before(self, set: impl SystemSet) -> Result<Self> {
+        self.configure(|systems, node| {
+            systems.add_node_before_set(node, set);
+            Ok(())
+        })
+    }
+
+    // runs the system after every system in `set`
+    pub fn after(self, set: impl SystemSet) -> Result<Self> {
+        self.configure(|systems, node| {
+            systems.add_node_after_set(node, set);
+            Ok(())
+        })
+    }
+}
+
+impl std::ops::Deref for SystemConfig<'_> {
+    type Target = App;
+
+    fn deref(&self) -> &Self::Target {
+        self.app
+    }
+}
+
+impl std::ops::DerefMut for SystemConfig<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.app
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use weaver_ecs::prelude::Resource;
+
+    use crate::condition::resource_exists;
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Runs(u32);
+
+    #[derive(Resource)]
+    struct Missing;
+
+    struct Ping;
+    impl Event for Ping {}
+
+    #[test]
+    fn test_system_config() {
+        fn count(mut runs: ResMut<Runs>) -> Result<()> {
+            runs.0 += 1;
+            Ok(())
+        }
+
+        let mut app = App::new().unwrap();
+        app.insert_resource(Runs::default());
+
+        // the event's own system is added in between, but the condition still goes to `count`
+        let mut config = app.add_system(count, SystemStage::Update).unwrap();
+        config.add_event::<Ping>();
+        config.run_if(resource_exists::<Missing>).unwrap();
+
+        app.run_schedule(SystemStage::Update).unwrap();
+        app.run_schedule(SystemStage::EventPump).unwrap();
+        assert_eq!(app.get_resource::<Runs>().unwrap().0, 0);
+
+        app.insert_resource(Missing);
+        app.run_schedule(SystemStage::Update).unwrap();
+        assert_eq!(app.get_resource::<Runs>().unwrap().0, 1);
+    }
+}
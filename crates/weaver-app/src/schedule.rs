@@ -0,0 +1,313 @@
+use std::{hash::Hash, sync::Arc};
+
+use rustc_hash::FxHashMap;
+use weaver_ecs::{prelude::Resource, world::World};
+use weaver_util::{
+    lock::SharedLock,
+    prelude::{anyhow, bail, Result},
+};
+
+use crate::system::{LabelId, SystemGraph, SystemStage};
+
+// names a schedule; usually a unit struct, or an enum with a variant per schedule
+pub trait ScheduleLabel: 'static + Send + Sync + std::fmt::Debug + Hash + Eq {}
+
+impl ScheduleLabel for SystemStage {}
+
+// a graph of systems that run together
+pub struct Schedule {
+    label: LabelId,
+    systems: SystemGraph,
+}
+
+impl Schedule {
+    pub fn new(label: impl ScheduleLabel) -> Self {
+        Self {
+            label: LabelId::of(label),
+            systems: SystemGraph::default(),
+        }
+    }
+
+    pub fn label(&self) -> &LabelId {
+        &self.label
+    }
+
+    pub fn systems(&self) -> &SystemGraph {
+        &self.systems
+    }
+
+    pub fn systems_mut(&mut self) -> &mut SystemGraph {
+        &mut self.systems
+    }
+
+    pub fn run(&mut self, world: &Arc<World>) -> Result<()> {
+        self.systems.run_concurrent(world)
+    }
+}
+
+// the parts of the main loop a runner drives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoopPhase {
+    // once, before the first frame
+    Startup,
+    // once per frame
+    Frame,
+    // once, when the app is closing
+    Shutdown,
+}
+
+// every schedule in the app, along with the order the main loop runs them in
+#[derive(Resource, Default)]
+pub struct Schedules {
+    // each behind its own lock, so a running system can run another schedule
+    schedules: FxHashMap<LabelId, SharedLock<Schedule>>,
+    startup: Vec<LabelId>,
+    frame: Vec<LabelId>,
+    shutdown: Vec<LabelId>,
+}
+
+impl Schedules {
+    // the built-in stages, in the order they've always run in
+    pub fn with_stages() -> Self {
+        let mut this = Self::default();
+        for stage in [
+            SystemStage::PreInit,
+            SystemStage::Init,
+            SystemStage::PostInit,
+        ] {
+            this.push(LoopPhase::Startup, stage);
+        }
+        for stage in [
+            SystemStage::PreUpdate,
+            SystemStage::Update,
+            SystemStage::PostUpdate,
+            SystemStage::PreUi,
+            SystemStage::Ui,
+            SystemStage::PostUi,
+            SystemStage::Extract,
+            SystemStage::PreRender,
+            SystemStage::Render,
+            SystemStage::RenderUi,
+            SystemStage::PostRender,
+            SystemStage::EventPump,
+        ] {
+            this.push(LoopPhase::Frame, stage);
+        }
+        for stage in [
+            SystemStage::PreShutdown,
+            SystemStage::Shutdown,
+            SystemStage::PostShutdown,
+        ] {
+            this.push(LoopPhase::Shutdown, stage);
+        }
+        this
+    }
+
+    pub fn get(&self, label: &LabelId) -> Option<SharedLock<Schedule>> {
+        self.schedules.get(label).cloned()
+    }
+
+    pub fn contains(&self, label: &LabelId) -> bool {
+        self.schedules.contains_key(label)
+    }
+
+    // adds an empty schedule if there isn't one yet; it only runs when asked to, unless it's
+    // also put in the main loop
+    pub fn get_or_insert(&mut self, label: impl ScheduleLabel) -> SharedLock<Schedule> {
+        self.get_or_insert_id(LabelId::of(label))
+    }
+
+    fn get_or_insert_id(&mut self, label: LabelId) -> SharedLock<Schedule> {
+        self.schedules
+            .entry(label.clone())
+            .or_insert_with(|| {
+                SharedLock::new(Schedule {
+                    label,
+                    systems: SystemGraph::default(),
+                })
+            })
+            .clone()
+    }
+
+    pub fn is_in_main_loop(&self, label: &LabelId) -> bool {
+        self.phase_of(label).is_some()
+    }
+
+    pub fn order(&self, phase: LoopPhase) -> &[LabelId] {
+        match phase {
+            LoopPhase::Startup => &self.startup,
+            LoopPhase::Frame => &self.frame,
+            LoopPhase::Shutdown => &self.shutdown,
+        }
+    }
+
+    fn order_mut(&mut self, phase: LoopPhase) -> &mut Vec<LabelId> {
+        match phase {
+            LoopPhase::Startup => &mut self.startup,
+            LoopPhase::Frame => &mut self.frame,
+            LoopPhase::Shutdown => &mut self.shutdown,
+        }
+    }
+
+    fn phase_of(&self, label: &LabelId) -> Option<(LoopPhase, usize)> {
+        [LoopPhase::Startup, LoopPhase::Frame, LoopPhase::Shutdown]
+            .into_iter()
+            .find_map(|phase| {
+                let index = self.order(phase).iter().position(|other| other == label)?;
+                Some((phase, index))
+            })
+    }
+
+    // runs the schedule at the end of `phase`
+    pub fn push(&mut self, phase: LoopPhase, label: impl ScheduleLabel) {
+        let id = LabelId::of(label);
+        self.get_or_insert_id(id.clone());
+        self.order_mut(phase).push(id);
+    }
+
+    // runs the schedule right after `after`, in the same phase
+    pub fn insert_after(
+        &mut self,
+        label: impl ScheduleLabel,
+        after: impl ScheduleLabel,
+    ) -> Result<()> {
+        self.insert_relative(label, after, 1)
+    }
+
+    // runs the schedule right before `before`, in the same phase
+    pub fn insert_before(
+        &mut self,
+        label: impl ScheduleLabel,
+        before: impl ScheduleLabel,
+    ) -> Result<()> {
+        self.insert_relative(label, before, 0)
+    }
+
+    fn insert_relative(
+        &mut self,
+        label: impl ScheduleLabel,
+        other: impl ScheduleLabel,
+        offset: usize,
+    ) -> Result<()> {
+        let id = LabelId::of(label);
+        if self.phase_of(&id).is_some() {
+            bail!("Schedule {} is already in the main loop", id.name());
+        }
+        let other = LabelId::of(other);
+        let (phase, index) = self
+            .phase_of(&other)
+            .ok_or_else(|| anyhow!("Schedule {} is not in the main loop", other.name()))?;
+        self.get_or_insert_id(id.clone());
+        self.order_mut(phase).insert(index + offset, id);
+        Ok(())
+    }
+}
+
+// runs the schedule if there is one; only the schedule itself is locked meanwhile, so its
+// systems can run other schedules, though not the one they're in
+pub fn run_schedule(world: &Arc<World>, label: &LabelId) -> Result<()> {
+    let schedule = {
+        let schedules = world
+            .get_resource::<Schedules>()
+            .ok_or_else(|| anyhow!("No Schedules resource"))?;
+        schedules.get(label)
+    };
+    match schedule {
+        Some(schedule) => schedule.write().run(world),
+        None => Ok(()),
+    }
+}
+
+// runs each schedule of the phase in order
+pub fn run_phase(world: &Arc<World>, phase: LoopPhase) -> Result<()> {
+    let order = world
+        .get_resource::<Schedules>()
+        .ok_or_else(|| anyhow!("No Schedules resource"))?
+        .order(phase)
+        .to_vec();
+    for label in &order {
+        run_schedule(world, label)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use weaver_ecs::component::ResMut;
+
+    use super::*;
+
+    #[derive(Debug, Hash, PartialEq, Eq)]
+    enum Custom {
+        Physics,
+        Network,
+        Substep,
+    }
+    impl ScheduleLabel for Custom {}
+
+    #[derive(Resource, Default)]
+    struct Log(Vec<&'static str>);
+
+    fn log(entry: &'static str) -> impl Fn(ResMut<Log>) -> Result<()> {
+        move |mut log: ResMut<Log>| {
+            log.0.push(entry);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_custom_schedules() {
+        let mut schedules = Schedules::with_stages();
+        schedules
+            .insert_after(Custom::Physics, SystemStage::Update)
+            .unwrap();
+        schedules
+            .insert_before(Custom::Network, Custom::Physics)
+            .unwrap();
+        assert!(schedules
+            .insert_after(Custom::Physics, SystemStage::Ui)
+            .is_err());
+        assert!(schedules
+            .insert_after(Custom::Substep, Custom::Substep)
+            .is_err());
+
+        let mut add = |label: &dyn Fn(&mut Schedules) -> SharedLock<Schedule>, entry| {
+            label(&mut schedules)
+                .write()
+                .systems_mut()
+                .add_system(log(entry));
+        };
+        add(&|s| s.get_or_insert(SystemStage::PostUpdate), "post update");
+        add(&|s| s.get_or_insert(Custom::Network), "network");
+        add(&|s| s.get_or_insert(Custom::Physics), "physics");
+        add(&|s| s.get_or_insert(Custom::Substep), "substep");
+        add(&|s| s.get_or_insert(SystemStage::Update), "update");
+
+        // `Substep` isn't in the main loop, so it only runs when asked to
+        schedules
+            .get_or_insert(Custom::Physics)
+            .write()
+            .systems_mut()
+            .add_system(|world: &Arc<World>| {
+                run_schedule(world, &LabelId::of(Custom::Substep))?;
+                run_schedule(world, &LabelId::of(Custom::Substep))
+            });
+
+        let world = World::new();
+        world.insert_resource(Log::default());
+        world.insert_resource(schedules);
+
+        run_phase(&world, LoopPhase::Frame).unwrap();
+        assert_eq!(
+            world.get_resource::<Log>().unwrap().0,
+            [
+                "update",
+                "network",
+                "physics",
+                "substep",
+                "substep",
+                "post update"
+            ]
+        );
+    }
+}
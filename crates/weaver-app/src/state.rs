@@ -0,0 +1,200 @@
+use std::{hash::Hash, sync::Arc};
+
+use weaver_ecs::{component::Res, prelude::Resource, world::World};
+use weaver_util::prelude::{anyhow, Result};
+
+use crate::{
+    schedule::{run_schedule, ScheduleLabel},
+    system::LabelId,
+};
+
+// a user enum of the states an app can be in, e.g. menu, loading and playing
+pub trait States: 'static + Send + Sync + std::fmt::Debug + Clone + Hash + Eq {}
+
+// the state the app is in; change it through `NextState`
+#[derive(Resource)]
+pub struct State<S: States>(S);
+
+impl<S: States> State<S> {
+    pub fn new(state: S) -> Self {
+        Self(state)
+    }
+
+    pub fn get(&self) -> &S {
+        &self.0
+    }
+}
+
+impl<S: States> std::ops::Deref for State<S> {
+    type Target = S;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+// the state to switch to at the next transition point, right before `SystemStage::Update`
+#[derive(Resource)]
+pub struct NextState<S: States>(Option<S>);
+
+impl<S: States> Default for NextState<S> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<S: States> NextState<S> {
+    pub fn set(&mut self, state: S) {
+        self.0 = Some(state);
+    }
+
+    pub fn get(&self) -> Option<&S> {
+        self.0.as_ref()
+    }
+}
+
+// runs when the state is switched to, and for the initial state at the end of startup
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OnEnter<S: States>(pub S);
+impl<S: States> ScheduleLabel for OnEnter<S> {}
+
+// runs when the state is switched away from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OnExit<S: States>(pub S);
+impl<S: States> ScheduleLabel for OnExit<S> {}
+
+// runs every frame while in the state, right after `SystemStage::Update`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OnUpdate<S: States>(pub S);
+impl<S: States> ScheduleLabel for OnUpdate<S> {}
+
+// where `NextState`s are applied, once per frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StateTransition;
+impl ScheduleLabel for StateTransition {}
+
+// where the current states' `OnUpdate` schedules are run from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StateUpdate;
+impl ScheduleLabel for StateUpdate {}
+
+fn current_state<S: States>(world: &World) -> Result<S> {
+    Ok(world
+        .get_resource::<State<S>>()
+        .ok_or_else(|| anyhow!("No State<{}> resource", std::any::type_name::<S>()))?
+        .0
+        .clone())
+}
+
+pub(crate) fn enter_initial_state<S: States>(world: &Arc<World>) -> Result<()> {
+    let state = current_state::<S>(world)?;
+    run_schedule(world, &LabelId::of(OnEnter(state)))
+}
+
+pub(crate) fn apply_state_transition<S: States>(world: &Arc<World>) -> Result<()> {
+    let Some(next) = world
+        .get_resource_mut::<NextState<S>>()
+        .and_then(|mut next| next.0.take())
+    else {
+        return Ok(());
+    };
+
+    let previous = {
+        let mut state = world
+            .get_resource_mut::<State<S>>()
+            .ok_or_else(|| anyhow!("No State<{}> resource", std::any::type_name::<S>()))?;
+        // setting the state it's already in doesn't count as a transition
+        if state.0 == next {
+            return Ok(());
+        }
+        std::mem::replace(&mut state.0, next.clone())
+    };
+
+    run_schedule(world, &LabelId::of(OnExit(previous)))?;
+    run_schedule(world, &LabelId::of(OnEnter(next)))
+}
+
+pub(crate) fn run_state_update<S: States>(world: &Arc<World>) -> Result<()> {
+    let state = current_state::<S>(world)?;
+    run_schedule(world, &LabelId::of(OnUpdate(state)))
+}
+
+// a run condition for systems that should only run in the given state
+pub fn in_state<S: States>(state: S) -> impl Fn(Option<Res<State<S>>>) -> bool {
+    move |current: Option<Res<State<S>>>| current.is_some_and(|current| current.0 == state)
+}
+
+#[cfg(test)]
+mod tests {
+    use weaver_ecs::component::ResMut;
+
+    use crate::{schedule::LoopPhase, system::SystemStage, App};
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+    enum GameState {
+        #[default]
+        Menu,
+        Playing,
+    }
+    impl States for GameState {}
+
+    #[derive(Resource, Default)]
+    struct Log(Vec<&'static str>);
+
+    fn log(entry: &'static str) -> impl Fn(ResMut<Log>) -> Result<()> {
+        move |mut log: ResMut<Log>| {
+            log.0.push(entry);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let mut app = App::new().unwrap();
+        app.insert_resource(Log::default());
+        app.add_state::<GameState>().unwrap();
+
+        app.add_system(log("enter menu"), OnEnter(GameState::Menu))
+            .unwrap()
+            .add_system(log("update menu"), OnUpdate(GameState::Menu))
+            .unwrap()
+            .add_system(log("exit menu"), OnExit(GameState::Menu))
+            .unwrap()
+            .add_system(log("enter playing"), OnEnter(GameState::Playing))
+            .unwrap()
+            .add_system(log("playing"), SystemStage::Update)
+            .unwrap()
+            .run_if(in_state(GameState::Playing))
+            .unwrap();
+
+        app.run_phase(LoopPhase::Startup).unwrap();
+        app.run_phase(LoopPhase::Frame).unwrap();
+        app.get_resource_mut::<NextState<GameState>>()
+            .unwrap()
+            .set(GameState::Playing);
+        app.run_phase(LoopPhase::Frame).unwrap();
+        // switching to the current state does nothing
+        app.get_resource_mut::<NextState<GameState>>()
+            .unwrap()
+            .set(GameState::Playing);
+        app.run_phase(LoopPhase::Frame).unwrap();
+
+        assert_eq!(
+            *app.get_resource::<State<GameState>>().unwrap().get(),
+            GameState::Playing
+        );
+        assert_eq!(
+            app.get_resource::<Log>().unwrap().0,
+            [
+                "enter menu",
+                "update menu",
+                "exit menu",
+                "enter playing",
+                "playing",
+                "playing"
+            ]
+        );
+    }
+}
@@ -1,15 +1,34 @@
-use std::{any::TypeId, collections::HashSet, sync::Arc};
+use std::{
+    any::{Any, TypeId},
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use petgraph::prelude::*;
 use rustc_hash::FxHashMap;
 use weaver_ecs::{
-    component::{Res, ResMut},
+    change::{SystemTicks, Tick},
+    commands::{CommandQueue, Commands},
+    component::{Component, Res, ResMut},
+    entity::Entity,
     prelude::{Query, Resource, World},
-    query::{QueryAccess, QueryFetch},
+    query::{QueryAccess, QueryFetch, QueryFilter, QueryState},
+    scene::{Scene, SceneQuery},
 };
 use weaver_event::{Event, EventRx, EventTx, Events};
-use weaver_util::prelude::{anyhow, Result};
+use weaver_util::{
+    lock::{ArcWrite, Lock, SharedLock},
+    prelude::{anyhow, bail, Result},
+};
+
+use crate::condition::{Condition, IntoCondition};
 
+// the schedules every app starts out with; plugins can add their own in between
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SystemStage {
     PreInit,
@@ -37,51 +56,150 @@ pub enum SystemStage {
     PostShutdown,
 }
 
+#[derive(Default)]
 pub struct SystemAccess {
     pub resources_read: Vec<TypeId>,
     pub resources_written: Vec<TypeId>,
     pub components_read: Vec<TypeId>,
     pub components_written: Vec<TypeId>,
+    // the system may touch anything through the world, so it has to run on its own
+    pub exclusive: bool,
 }
 
 impl SystemAccess {
+    pub fn exclusive() -> Self {
+        Self {
+            exclusive: true,
+            ..Default::default()
+        }
+    }
+
     pub fn extend(&mut self, other: SystemAccess) {
         self.resources_read.extend(other.resources_read);
         self.resources_written.extend(other.resources_written);
         self.components_read.extend(other.components_read);
         self.components_written.extend(other.components_written);
+        self.exclusive |= other.exclusive;
+    }
+
+    // whether the two can't run at the same time
+    pub fn conflicts_with(&self, other: &SystemAccess) -> bool {
+        fn writes_to(writes: &[TypeId], reads: &[TypeId], other_writes: &[TypeId]) -> bool {
+            writes
+                .iter()
+                .any(|id| reads.contains(id) || other_writes.contains(id))
+        }
+
+        self.exclusive
+            || other.exclusive
+            || writes_to(
+                &self.resources_written,
+                &other.resources_read,
+                &other.resources_written,
+            )
+            || writes_to(
+                &other.resources_written,
+                &self.resources_read,
+                &self.resources_written,
+            )
+            || writes_to(
+                &self.components_written,
+                &other.components_read,
+                &other.components_written,
+            )
+            || writes_to(
+                &other.components_written,
+                &self.components_read,
+                &self.components_written,
+            )
     }
 }
 
 pub trait System: 'static + Send + Sync {
     fn access(&self) -> SystemAccess;
     fn run(&self, world: &Arc<World>) -> Result<()>;
+
+    // applies anything the system deferred, at the end of its stage
+    #[allow(unused_variables)]
+    fn apply_deferred(&self, world: &Arc<World>) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub trait SystemParam {
+    // per-system state, kept for the lifetime of the system
+    type State: Default + Send + Sync + 'static;
+
     fn access() -> SystemAccess;
-    fn fetch(world: &Arc<World>) -> Option<Self>
+    fn fetch(world: &Arc<World>, state: &Self::State) -> Option<Self>
     where
         Self: Sized;
+
+    #[allow(unused_variables)]
+    fn apply(world: &Arc<World>, state: &Self::State) -> Result<()> {
+        Ok(())
+    }
 }
 
 impl<T: SystemParam> SystemParam for Option<T> {
+    type State = T::State;
+
     fn access() -> SystemAccess {
         T::access()
     }
 
-    fn fetch(world: &Arc<World>) -> Option<Self>
+    // never fails; a param that can't be fetched comes through as `None` instead
+    fn fetch(world: &Arc<World>, state: &Self::State) -> Option<Self>
     where
         Self: Sized,
     {
-        T::fetch(world).map(Some)
+        Some(T::fetch(world, state))
+    }
+
+    fn apply(world: &Arc<World>, state: &Self::State) -> Result<()> {
+        T::apply(world, state)
+    }
+}
+
+impl SystemParam for Arc<World> {
+    type State = ();
+
+    // like systems taking `&Arc<World>`, direct world access can't be tracked, so it counts as
+    // access to everything
+    fn access() -> SystemAccess {
+        SystemAccess::exclusive()
+    }
+
+    fn fetch(world: &Arc<World>, _: &()) -> Option<Self> {
+        Some(world.clone())
+    }
+}
+
+impl SystemParam for Commands {
+    type State = SharedLock<CommandQueue>;
+
+    fn access() -> SystemAccess {
+        SystemAccess::default()
+    }
+
+    fn fetch(world: &Arc<World>, state: &Self::State) -> Option<Self> {
+        Some(Commands::new(world.clone(), state.write()))
+    }
+
+    fn apply(world: &Arc<World>, state: &Self::State) -> Result<()> {
+        state.write().apply(world);
+        Ok(())
     }
 }
 
-impl<Q> SystemParam for Query<Q>
+impl<Q, F> SystemParam for Query<Q, F>
 where
-    Q: QueryFetch,
+    Q: QueryFetch + 'static,
+    F: QueryFilter + 'static,
 {
+    // matched archetypes are cached across runs
+    type State = Lock<QueryState<Q, F>>;
+
     fn access() -> SystemAccess {
         SystemAccess {
             resources_read: Vec::new(),
@@ -106,55 +224,83 @@ where
                     }
                 })
                 .collect(),
+            ..Default::default()
         }
     }
 
-    fn fetch(world: &Arc<World>) -> Option<Self> {
-        Some(Query::new(world))
+    fn fetch(world: &Arc<World>, state: &Self::State) -> Option<Self> {
+        Some(Query::from_state(world, &mut state.write()))
+    }
+}
+
+impl<Q, F> SystemParam for SceneQuery<Q, F>
+where
+    Q: QueryFetch + 'static,
+    F: QueryFilter + 'static,
+{
+    type State = Lock<QueryState<Q, F>>;
+
+    fn access() -> SystemAccess {
+        let mut access = Query::<Q, F>::access();
+        access.components_read.push(TypeId::of::<Scene>());
+        access
+    }
+
+    fn fetch(world: &Arc<World>, state: &Self::State) -> Option<Self> {
+        Some(SceneQuery::from_state(world, &mut state.write()))
     }
 }
 
 impl<T: Resource> SystemParam for Res<T> {
+    type State = ();
+
     fn access() -> SystemAccess {
         SystemAccess {
             resources_read: vec![TypeId::of::<T>()],
             resources_written: Vec::new(),
             components_read: Vec::new(),
             components_written: Vec::new(),
+            ..Default::default()
         }
     }
 
-    fn fetch(world: &Arc<World>) -> Option<Self> {
+    fn fetch(world: &Arc<World>, _: &()) -> Option<Self> {
         world.get_resource::<T>()
     }
 }
 
 impl<T: Resource> SystemParam for ResMut<T> {
+    type State = ();
+
     fn access() -> SystemAccess {
         SystemAccess {
             resources_read: Vec::new(),
             resources_written: vec![TypeId::of::<T>()],
             components_read: Vec::new(),
             components_written: Vec::new(),
+            ..Default::default()
         }
     }
 
-    fn fetch(world: &Arc<World>) -> Option<Self> {
+    fn fetch(world: &Arc<World>, _: &()) -> Option<Self> {
         world.get_resource_mut::<T>()
     }
 }
 
 impl<T: Event> SystemParam for EventTx<T> {
+    type State = ();
+
     fn access() -> SystemAccess {
         SystemAccess {
             resources_read: Vec::new(),
             resources_written: vec![TypeId::of::<Events<T>>()],
             components_read: Vec::new(),
             components_written: Vec::new(),
+            ..Default::default()
         }
     }
 
-    fn fetch(world: &Arc<World>) -> Option<Self>
+    fn fetch(world: &Arc<World>, _: &()) -> Option<Self>
     where
         Self: Sized,
     {
@@ -163,16 +309,19 @@ impl<T: Event> SystemParam for EventTx<T> {
 }
 
 impl<T: Event> SystemParam for EventRx<T> {
+    type State = ();
+
     fn access() -> SystemAccess {
         SystemAccess {
-            resources_read: Vec::new(),
-            resources_written: vec![TypeId::of::<Events<T>>()],
+            resources_read: vec![TypeId::of::<Events<T>>()],
+            resources_written: Vec::new(),
             components_read: Vec::new(),
             components_written: Vec::new(),
+            ..Default::default()
         }
     }
 
-    fn fetch(world: &Arc<World>) -> Option<Self>
+    fn fetch(world: &Arc<World>, _: &()) -> Option<Self>
     where
         Self: Sized,
     {
@@ -180,6 +329,99 @@ impl<T: Event> SystemParam for EventRx<T> {
     }
 }
 
+// runs `f` with the system's change ticks, so queries and mutations inside it see the right ticks
+pub(crate) fn run_with_ticks<R>(last_run: &AtomicU64, world: &World, f: impl FnOnce() -> R) -> R {
+    let this_run = world.increment_update_tick();
+    let last_run = Tick::new(last_run.swap(this_run.get(), Ordering::AcqRel));
+    SystemTicks::new(last_run, this_run).run_with(f)
+}
+
+// entities that had a `T` removed (or were destroyed) since the removal log was last cleared,
+// which happens once per frame alongside the event queues
+pub struct RemovedComponents<T: Component> {
+    entities: Vec<Entity>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T: Component> RemovedComponents<T> {
+    pub fn new(entities: Vec<Entity>) -> Self {
+        Self {
+            entities,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter().copied()
+    }
+}
+
+impl<T: Component> SystemParam for RemovedComponents<T> {
+    type State = ();
+
+    fn access() -> SystemAccess {
+        SystemAccess {
+            resources_read: Vec::new(),
+            resources_written: Vec::new(),
+            components_read: vec![TypeId::of::<T>()],
+            components_written: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    fn fetch(world: &Arc<World>, _: &()) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        Some(RemovedComponents::new(world.removed_components::<T>()))
+    }
+}
+
+// state private to one system, created from `T::default()` and kept between runs
+pub struct Local<T: Default + Send + Sync + 'static> {
+    value: ArcWrite<T>,
+}
+
+impl<T: Default + Send + Sync + 'static> Deref for Local<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T: Default + Send + Sync + 'static> DerefMut for Local<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+impl<T: Default + Send + Sync + 'static> SystemParam for Local<T> {
+    type State = SharedLock<T>;
+
+    fn access() -> SystemAccess {
+        SystemAccess::default()
+    }
+
+    fn fetch(_: &Arc<World>, state: &Self::State) -> Option<Self> {
+        Some(Local {
+            value: state.write(),
+        })
+    }
+}
+
 pub trait FunctionSystem<Marker>: 'static + Send + Sync {
     fn into_system(self) -> Arc<dyn System>;
 }
@@ -194,8 +436,10 @@ macro_rules! impl_function_system {
 
             #[allow(unused_parens, non_snake_case)]
             fn into_system(self) -> Arc<dyn System> {
-                struct FunctionSystemImpl<Func, $($param),*> {
+                struct FunctionSystemImpl<Func, $($param: SystemParam),*> {
                     func: Func,
+                    last_run: AtomicU64,
+                    state: ($($param::State,)*),
                     _marker: std::marker::PhantomData<($($param),*)>,
                 }
 
@@ -205,12 +449,7 @@ macro_rules! impl_function_system {
                     $($param: SystemParam + 'static + Send + Sync),*
                 {
                     fn access(&self) -> SystemAccess {
-                        let mut access = SystemAccess {
-                            resources_read: Vec::new(),
-                            resources_written: Vec::new(),
-                            components_read: Vec::new(),
-                            components_written: Vec::new(),
-                        };
+                        let mut access = SystemAccess::default();
 
                         $(
                             access.extend($param::access());
@@ -220,13 +459,26 @@ macro_rules! impl_function_system {
                     }
 
                     fn run(&self, world: &Arc<World>) -> Result<()> {
-                        let ($($param),*) = ($($param::fetch(world).ok_or_else(|| anyhow!("Failed to fetch system param"))?),*);
-                        (self.func)($($param),*)
+                        run_with_ticks(&self.last_run, world, || {
+                            let ($($param,)*) = &self.state;
+                            let ($($param),*) = ($($param::fetch(world, $param).ok_or_else(|| anyhow!("Failed to fetch system param"))?),*);
+                            (self.func)($($param),*)
+                        })
+                    }
+
+                    fn apply_deferred(&self, world: &Arc<World>) -> Result<()> {
+                        let ($($param,)*) = &self.state;
+                        $(
+                            $param::apply(world, $param)?;
+                        )*
+                        Ok(())
                     }
                 }
 
                 Arc::new(FunctionSystemImpl {
                     func: self,
+                    last_run: AtomicU64::new(0),
+                    state: Default::default(),
                     _marker: std::marker::PhantomData,
                 })
             }
@@ -250,33 +502,119 @@ where
     fn into_system(self) -> Arc<dyn System> {
         struct FunctionSystemImpl<Func> {
             func: Func,
+            last_run: AtomicU64,
         }
 
         impl<Func> System for FunctionSystemImpl<Func>
         where
             Func: Fn(&Arc<World>) -> Result<()> + 'static + Send + Sync,
         {
+            // world systems run alone, since there's no telling what they'll touch
             fn access(&self) -> SystemAccess {
-                SystemAccess {
-                    resources_read: Vec::new(),
-                    resources_written: Vec::new(),
-                    components_read: Vec::new(),
-                    components_written: Vec::new(),
-                }
+                SystemAccess::exclusive()
             }
+
             fn run(&self, world: &Arc<World>) -> Result<()> {
-                (self.func)(world)
+                run_with_ticks(&self.last_run, world, || (self.func)(world))
             }
         }
 
-        Arc::new(FunctionSystemImpl { func: self })
+        Arc::new(FunctionSystemImpl {
+            func: self,
+            last_run: AtomicU64::new(0),
+        })
+    }
+}
+
+// a named group of systems within a stage, which can be ordered against other sets and systems;
+// usually a unit struct or an enum with a variant per group
+pub trait SystemSet: 'static + Send + Sync + std::fmt::Debug + Hash + Eq {}
+
+// a label value behind a `dyn`, still comparable to other labels
+trait DynLabel: 'static + Send + Sync + std::fmt::Debug {
+    fn as_any(&self) -> &dyn Any;
+    fn dyn_eq(&self, other: &dyn DynLabel) -> bool;
+}
+
+impl<L: 'static + Send + Sync + std::fmt::Debug + Eq> DynLabel for L {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn dyn_eq(&self, other: &dyn DynLabel) -> bool {
+        other.as_any().downcast_ref::<L>() == Some(self)
+    }
+}
+
+// identifies a label value, such as a system set or a schedule, without its type
+#[derive(Debug, Clone)]
+pub struct LabelId {
+    type_id: TypeId,
+    // only used for hashing; labels are compared by value
+    hash: u64,
+    label: Arc<dyn DynLabel>,
+    // the label's `Debug` output, for errors
+    name: String,
+}
+
+impl LabelId {
+    pub fn of<L: 'static + Send + Sync + std::fmt::Debug + Hash + Eq>(label: L) -> Self {
+        let mut hasher = DefaultHasher::new();
+        label.hash(&mut hasher);
+        Self {
+            type_id: TypeId::of::<L>(),
+            hash: hasher.finish(),
+            name: format!("{label:?}"),
+            label: Arc::new(label),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PartialEq for LabelId {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && self.label.dyn_eq(&*other.label)
+    }
+}
+
+impl Eq for LabelId {}
+
+impl Hash for LabelId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.type_id.hash(state);
+        self.hash.hash(state);
+    }
+}
+
+// one side of an ordering constraint
+#[derive(Debug, Clone)]
+enum SystemRef {
+    Node(NodeIndex),
+    // every system added from the function
+    Function(TypeId, &'static str),
+    Set(LabelId),
+}
+
+impl SystemRef {
+    fn function<M, S: FunctionSystem<M>>() -> Self {
+        Self::Function(TypeId::of::<S>(), std::any::type_name::<S>())
     }
 }
 
 #[derive(Default)]
 pub struct SystemGraph {
     systems: StableDiGraph<Arc<dyn System>, ()>,
-    index_cache: FxHashMap<TypeId, NodeIndex>,
+    index_cache: FxHashMap<TypeId, Vec<NodeIndex>>,
+    // a system only runs if all of its conditions hold
+    conditions: FxHashMap<NodeIndex, Vec<Arc<dyn Condition>>>,
+    sets: FxHashMap<LabelId, Vec<NodeIndex>>,
+    // (before, after) pairs; they're only turned into edges by `build`, so they can refer to
+    // systems and sets that haven't been added yet
+    orderings: Vec<(SystemRef, SystemRef)>,
+    dirty: bool,
 }
 
 impl SystemGraph {
@@ -285,8 +623,12 @@ impl SystemGraph {
         S: FunctionSystem<M>,
     {
         let node = self.systems.add_node(system.into_system());
-        self.index_cache.insert(TypeId::of::<S>(), node);
+        self.index_cache
+            .entry(TypeId::of::<S>())
+            .or_default()
+            .push(node);
         self.resolve_dependencies(100).unwrap();
+        self.dirty = true;
         node
     }
 
@@ -295,66 +637,165 @@ impl SystemGraph {
         S1: FunctionSystem<M1>,
         S2: FunctionSystem<M2>,
     {
-        let parent = self.index_cache[&TypeId::of::<S1>()];
-        let child = self.index_cache[&TypeId::of::<S2>()];
-        self.systems.add_edge(parent, child, ());
-        self.resolve_dependencies(100).unwrap();
+        self.add_ordering(
+            SystemRef::function::<M1, S1>(),
+            SystemRef::function::<M2, S2>(),
+        );
     }
 
-    pub fn add_system_after<M1, M2, S1, S2>(&mut self, system: S1, _after: S2)
+    pub fn add_system_after<M1, M2, S1, S2>(&mut self, system: S1, _after: S2) -> NodeIndex
     where
         S1: FunctionSystem<M1>,
         S2: FunctionSystem<M2>,
     {
         let node = self.add_system(system);
-        let parent = self.index_cache[&TypeId::of::<S2>()];
-        self.systems.add_edge(parent, node, ());
-        self.resolve_dependencies(100).unwrap();
+        self.add_ordering(SystemRef::function::<M2, S2>(), SystemRef::Node(node));
+        node
     }
 
-    pub fn add_system_before<M1, M2, S1, S2>(&mut self, system: S1, _before: S2)
+    pub fn add_system_before<M1, M2, S1, S2>(&mut self, system: S1, _before: S2) -> NodeIndex
     where
         S1: FunctionSystem<M1>,
         S2: FunctionSystem<M2>,
     {
         let node = self.add_system(system);
-        let child = self.index_cache[&TypeId::of::<S2>()];
-        self.systems.add_edge(node, child, ());
-        self.resolve_dependencies(100).unwrap();
+        self.add_ordering(SystemRef::Node(node), SystemRef::function::<M2, S2>());
+        node
+    }
+
+    pub fn add_to_set(&mut self, node: NodeIndex, set: impl SystemSet) -> Result<()> {
+        if !self.systems.contains_node(node) {
+            bail!("No system to add to {set:?}");
+        }
+        let nodes = self.sets.entry(LabelId::of(set)).or_default();
+        if !nodes.contains(&node) {
+            nodes.push(node);
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn add_node_before_set(&mut self, node: NodeIndex, set: impl SystemSet) {
+        self.add_ordering(SystemRef::Node(node), SystemRef::Set(LabelId::of(set)));
+    }
+
+    pub fn add_node_after_set(&mut self, node: NodeIndex, set: impl SystemSet) {
+        self.add_ordering(SystemRef::Set(LabelId::of(set)), SystemRef::Node(node));
+    }
+
+    // every system in `before` runs before every system in `after`
+    pub fn order_sets(&mut self, before: impl SystemSet, after: impl SystemSet) {
+        self.add_ordering(
+            SystemRef::Set(LabelId::of(before)),
+            SystemRef::Set(LabelId::of(after)),
+        );
+    }
+
+    fn add_ordering(&mut self, before: SystemRef, after: SystemRef) {
+        self.orderings.push((before, after));
+        self.dirty = true;
+    }
+
+    fn resolve_ref(&self, system: &SystemRef) -> Result<Vec<NodeIndex>> {
+        match system {
+            SystemRef::Node(node) => Ok(vec![*node]),
+            SystemRef::Function(type_id, name) => match self.index_cache.get(type_id) {
+                Some(nodes) if !nodes.is_empty() => Ok(nodes.clone()),
+                _ => bail!("System {name} is used for ordering but was never added to the stage"),
+            },
+            SystemRef::Set(set) => match self.sets.get(set) {
+                Some(nodes) if !nodes.is_empty() => Ok(nodes.clone()),
+                _ => bail!(
+                    "System set {} is used for ordering but has no systems in the stage",
+                    set.name()
+                ),
+            },
+        }
+    }
+
+    // turns the ordering constraints into edges, then separates systems whose access conflicts;
+    // `run` and `run_concurrent` do this whenever something was added since
+    pub fn build(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let mut edges = Vec::new();
+        for (before, after) in &self.orderings {
+            let before = self.resolve_ref(before)?;
+            let after = self.resolve_ref(after)?;
+            for &parent in &before {
+                for &child in &after {
+                    if parent != child {
+                        edges.push((parent, child));
+                    }
+                }
+            }
+        }
+
+        self.systems.clear_edges();
+        for (parent, child) in edges {
+            self.systems.update_edge(parent, child, ());
+        }
+        if petgraph::algo::is_cyclic_directed(&self.systems) {
+            bail!("System ordering constraints form a cycle");
+        }
+        self.resolve_dependencies(100)?;
+
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn add_condition<M>(
+        &mut self,
+        node: NodeIndex,
+        condition: impl IntoCondition<M>,
+    ) -> Result<()> {
+        if !self.systems.contains_node(node) {
+            bail!("No system to add a run condition to");
+        }
+        let condition = condition.into_condition()?;
+        self.conditions.entry(node).or_default().push(condition);
+        // what the condition reads can't be written by a system running alongside it
+        self.resolve_dependencies(100)
+    }
+
+    // the system's own access along with its conditions'
+    fn access(&self, node: NodeIndex) -> SystemAccess {
+        let mut access = self.systems[node].access();
+        for condition in self.conditions.get(&node).into_iter().flatten() {
+            access.extend(condition.access());
+        }
+        access
+    }
+
+    fn should_run(&self, node: NodeIndex, world: &Arc<World>) -> Result<bool> {
+        for condition in self.conditions.get(&node).into_iter().flatten() {
+            if !condition.evaluate(world)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
     pub fn get_layers(&self) -> Vec<Vec<NodeIndex>> {
         let mut schedule = petgraph::visit::Topo::new(&self.systems);
 
-        let mut seen = HashSet::new();
-        let mut layers = Vec::new();
-        let mut current_layer = Vec::new();
+        // each system goes in the layer after the last of the systems it depends on
+        let mut depths = FxHashMap::default();
+        let mut layers: Vec<Vec<NodeIndex>> = Vec::new();
         while let Some(node) = schedule.next(&self.systems) {
-            if seen.contains(&node) {
-                continue;
-            }
-            seen.insert(node);
-            current_layer.push(node);
-            if self
+            let depth = self
                 .systems
                 .neighbors_directed(node, Direction::Incoming)
-                .count()
-                == 0
-            {
-                layers.push(current_layer);
-                current_layer = Vec::new();
-            }
-
-            for child in self.systems.neighbors_directed(node, Direction::Outgoing) {
-                if self
-                    .systems
-                    .neighbors_directed(child, Direction::Incoming)
-                    .all(|parent| seen.contains(&parent))
-                {
-                    seen.insert(child);
-                    current_layer.push(child);
-                }
+                .map(|parent| depths[&parent] + 1)
+                .max()
+                .unwrap_or(0);
+            depths.insert(node, depth);
+            if layers.len() <= depth {
+                layers.resize_with(depth + 1, Vec::new);
             }
+            layers[depth].push(node);
         }
 
         layers
@@ -368,50 +809,21 @@ impl SystemGraph {
 
         let mut try_again = false;
 
-        // only one system at a time can access a resource or component mutably
+        // only one system at a time can access a resource or component mutably, and an exclusive
+        // system can't share its layer; conflicting systems run in the order they were added
         for layer in layers {
             for i in 0..layer.len() {
                 for j in 0..i {
-                    let system_i = &self.systems[layer[i]];
-                    let system_j = &self.systems[layer[j]];
-                    let access_i = system_i.access();
-                    let access_j = system_j.access();
-
-                    for resource_i in &access_i.resources_written {
-                        if access_j.resources_read.contains(resource_i)
-                            || access_j.resources_written.contains(resource_i)
-                        {
-                            self.systems.add_edge(layer[i], layer[j], ());
-                            try_again = true;
-                        }
-                    }
-
-                    for resource_j in &access_j.resources_written {
-                        if access_i.resources_read.contains(resource_j)
-                            || access_i.resources_written.contains(resource_j)
-                        {
-                            self.systems.add_edge(layer[j], layer[i], ());
-                            try_again = true;
-                        }
-                    }
-
-                    for component_i in &access_i.components_written {
-                        if access_j.components_read.contains(component_i)
-                            || access_j.components_written.contains(component_i)
-                        {
-                            self.systems.add_edge(layer[i], layer[j], ());
-                            try_again = true;
-                        }
-                    }
-
-                    for component_j in &access_j.components_written {
-                        if access_i.components_read.contains(component_j)
-                            || access_i.components_written.contains(component_j)
-                        {
-                            self.systems.add_edge(layer[j], layer[i], ());
-                            try_again = true;
-                        }
+                    if !self.access(layer[i]).conflicts_with(&self.access(layer[j])) {
+                        continue;
                     }
+                    let (first, second) = if layer[i] < layer[j] {
+                        (layer[i], layer[j])
+                    } else {
+                        (layer[j], layer[i])
+                    };
+                    self.systems.add_edge(first, second, ());
+                    try_again = true;
                 }
             }
         }
@@ -420,41 +832,363 @@ impl SystemGraph {
             self.resolve_dependencies(depth - 1)?;
         }
 
+        // systems in one layer never depend on each other, so this would be a bug
+        if petgraph::algo::is_cyclic_directed(&self.systems) {
+            bail!("Resolving system access conflicts formed a cycle");
+        }
+
         Ok(())
     }
 
-    pub fn run(&self, world: &Arc<World>) -> Result<()> {
+    pub fn run(&mut self, world: &Arc<World>) -> Result<()> {
+        self.build()?;
+
+        let mut order = Vec::with_capacity(self.systems.node_count());
         let mut schedule = petgraph::visit::Topo::new(&self.systems);
         while let Some(node) = schedule.next(&self.systems) {
+            order.push(node);
+        }
+        self.check_scheduled(order.len())?;
+
+        for node in order {
+            if !self.should_run(node, world)? {
+                continue;
+            }
             let system = self.systems[node].clone();
             system.run(world)?;
         }
+
+        self.apply_deferred(world)
+    }
+
+    // the sync point: deferred commands are applied in system order once every system has run
+    pub fn apply_deferred(&self, world: &Arc<World>) -> Result<()> {
+        let mut schedule = petgraph::visit::Topo::new(&self.systems);
+        while let Some(node) = schedule.next(&self.systems) {
+            self.systems[node].apply_deferred(world)?;
+        }
+        Ok(())
+    }
+
+    // a node missing from the order means the graph is broken, e.g. by a cycle
+    fn check_scheduled(&self, scheduled: usize) -> Result<()> {
+        if scheduled != self.systems.node_count() {
+            bail!(
+                "Only {} of {} systems could be scheduled",
+                scheduled,
+                self.systems.node_count()
+            );
+        }
         Ok(())
     }
 
-    pub fn run_concurrent(&self, world: &Arc<World>) -> Result<()> {
+    pub fn run_concurrent(&mut self, world: &Arc<World>) -> Result<()> {
+        self.build()?;
+
         let layers = self.get_layers();
+        self.check_scheduled(layers.iter().map(Vec::len).sum())?;
 
         // run each layer concurrently
         for layer in layers {
-            let mut rxs = Vec::new();
-
+            // every condition in the layer is checked before any of its systems start
+            let mut nodes = Vec::with_capacity(layer.len());
             for node in layer {
-                let (tx, rx) = crossbeam_channel::unbounded();
-                rxs.push(rx);
-                let world = world.clone();
-                let system = self.systems[node].clone();
-                rayon::spawn(move || {
-                    let result = system.run(&world);
-                    tx.send(result).unwrap();
-                });
+                if self.should_run(node, world)? {
+                    nodes.push(node);
+                }
             }
 
-            for rx in rxs {
-                rx.recv()??;
+            // the scope waits by running systems itself, so this works from within a system
+            // running another schedule, even with a single worker thread
+            let results = Lock::new(Vec::with_capacity(nodes.len()));
+            rayon::scope(|scope| {
+                for node in nodes {
+                    let system = self.systems[node].clone();
+                    let results = &results;
+                    scope.spawn(move |_| {
+                        let result = system.run(world);
+                        results.write().push(result);
+                    });
+                }
+            });
+
+            for result in results.write().drain(..) {
+                result?;
             }
         }
 
-        Ok(())
+        self.apply_deferred(world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use weaver_ecs::prelude::{Component, Resource};
+
+    use super::*;
+
+    #[derive(Component)]
+    struct Marker;
+
+    #[derive(Resource, Default)]
+    struct Counter(u32);
+
+    #[test]
+    fn test_commands_applied_after_stage() {
+        let world = World::new();
+        let entity = world.create_entity();
+
+        fn insert(entity: Entity) -> impl Fn(Commands) -> Result<()> {
+            move |mut commands: Commands| {
+                commands.insert(entity, Marker);
+                Ok(())
+            }
+        }
+
+        let mut graph = SystemGraph::default();
+        let system = insert(entity);
+        graph.add_system(system);
+
+        let node = graph.systems.node_indices().next().unwrap();
+        graph.systems[node].run(&world).unwrap();
+        assert!(!world.has_component::<Marker>(entity));
+
+        graph.apply_deferred(&world).unwrap();
+        assert!(world.has_component::<Marker>(entity));
+    }
+
+    #[test]
+    fn test_conflicting_writes_both_run() {
+        #[derive(Resource, Default)]
+        struct Runs(Vec<&'static str>);
+
+        fn a(mut counter: ResMut<Counter>, mut runs: ResMut<Runs>) -> Result<()> {
+            counter.0 += 1;
+            runs.0.push("a");
+            Ok(())
+        }
+
+        fn b(mut counter: ResMut<Counter>, mut runs: ResMut<Runs>) -> Result<()> {
+            counter.0 += 10;
+            runs.0.push("b");
+            Ok(())
+        }
+
+        let world = World::new();
+        world.insert_resource(Counter::default());
+        world.insert_resource(Runs::default());
+
+        let mut graph = SystemGraph::default();
+        graph.add_system(a);
+        graph.add_system(b);
+        graph.run_concurrent(&world).unwrap();
+
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 11);
+        assert_eq!(world.get_resource::<Runs>().unwrap().0, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_unscheduled_systems_fail() {
+        fn noop(_: Res<Counter>) -> Result<()> {
+            Ok(())
+        }
+
+        let mut graph = SystemGraph::default();
+        let a = graph.add_system(noop);
+        let b = graph.add_system(noop);
+        graph.build().unwrap();
+
+        // a cycle the checks in `build` would have refused leaves both out of every layer
+        graph.systems.add_edge(a, b, ());
+        graph.systems.add_edge(b, a, ());
+        let scheduled = graph.get_layers().iter().map(Vec::len).sum();
+        assert!(graph.check_scheduled(scheduled).is_err());
+    }
+
+    #[test]
+    fn test_local_state() {
+        let world = World::new();
+        world.insert_resource(Counter::default());
+
+        fn count(mut runs: Local<u32>, mut counter: ResMut<Counter>) -> Result<()> {
+            *runs += 1;
+            counter.0 = *runs * 10;
+            Ok(())
+        }
+
+        let mut graph = SystemGraph::default();
+        graph.add_system(count);
+        graph.run(&world).unwrap();
+        graph.run(&world).unwrap();
+
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 20);
+    }
+
+    #[test]
+    fn test_run_conditions() {
+        use crate::condition::{resource_changed, resource_exists};
+
+        #[derive(Resource, Default)]
+        struct Runs(u32);
+
+        fn count(mut runs: ResMut<Runs>) -> Result<()> {
+            runs.0 += 1;
+            Ok(())
+        }
+
+        // without the resource this gets `None`, rather than failing the whole stage
+        fn optional(_: Option<Res<Counter>>) -> Result<()> {
+            Ok(())
+        }
+
+        fn writes_counter(mut counter: ResMut<Counter>) -> bool {
+            counter.0 += 1;
+            true
+        }
+
+        let world = World::new();
+        world.insert_resource(Runs::default());
+
+        let mut graph = SystemGraph::default();
+        let node = graph.add_system(count);
+        graph
+            .add_condition(node, resource_exists::<Counter>)
+            .unwrap();
+        graph
+            .add_condition(node, resource_changed::<Counter>)
+            .unwrap();
+        graph.add_system(optional);
+        assert!(graph.add_condition(node, writes_counter).is_err());
+
+        // the system's params would fetch fine, but the resource its conditions look at is missing
+        graph.run_concurrent(&world).unwrap();
+        assert_eq!(world.get_resource::<Runs>().unwrap().0, 0);
+
+        world.insert_resource(Counter::default());
+        graph.run_concurrent(&world).unwrap();
+        assert_eq!(world.get_resource::<Runs>().unwrap().0, 1);
+
+        // unchanged since the conditions last saw it
+        graph.run_concurrent(&world).unwrap();
+        assert_eq!(world.get_resource::<Runs>().unwrap().0, 1);
+
+        world.get_resource_mut::<Counter>().unwrap().0 += 1;
+        graph.run_concurrent(&world).unwrap();
+        assert_eq!(world.get_resource::<Runs>().unwrap().0, 2);
+    }
+
+    #[test]
+    fn test_system_sets() {
+        #[derive(Debug, Hash, PartialEq, Eq)]
+        enum Phase {
+            Simulate,
+            Present,
+        }
+        impl SystemSet for Phase {}
+
+        #[derive(Resource, Default)]
+        struct Log(Vec<&'static str>);
+
+        fn log(entry: &'static str) -> impl Fn(ResMut<Log>) -> Result<()> {
+            move |mut log: ResMut<Log>| {
+                log.0.push(entry);
+                Ok(())
+            }
+        }
+
+        let world = World::new();
+        world.insert_resource(Log::default());
+
+        // closures can't be named for ordering, but their sets can, even before they exist
+        let mut graph = SystemGraph::default();
+        graph.order_sets(Phase::Simulate, Phase::Present);
+        let present = graph.add_system(log("present"));
+        graph.add_to_set(present, Phase::Present).unwrap();
+        let late = graph.add_system(log("late"));
+        graph.add_node_after_set(late, Phase::Present);
+        let simulate = graph.add_system(log("simulate"));
+        graph.add_to_set(simulate, Phase::Simulate).unwrap();
+
+        graph.run_concurrent(&world).unwrap();
+        assert_eq!(
+            world.get_resource::<Log>().unwrap().0,
+            ["simulate", "present", "late"]
+        );
+
+        let mut graph = SystemGraph::default();
+        let node = graph.add_system(log("orphan"));
+        graph.add_node_before_set(node, Phase::Simulate);
+        let err = graph.run_concurrent(&world).unwrap_err();
+        assert!(err.to_string().contains("Simulate"));
+
+        let mut graph = SystemGraph::default();
+        let a = graph.add_system(log("a"));
+        let b = graph.add_system(log("b"));
+        graph.add_to_set(a, Phase::Simulate).unwrap();
+        graph.add_to_set(b, Phase::Present).unwrap();
+        graph.order_sets(Phase::Simulate, Phase::Present);
+        graph.order_sets(Phase::Present, Phase::Simulate);
+        assert!(graph.run_concurrent(&world).is_err());
+    }
+
+    #[test]
+    fn test_label_ids() {
+        // every value hashes the same, so only comparing the values tells them apart
+        #[derive(Debug, PartialEq, Eq)]
+        enum Colliding {
+            A,
+            B,
+        }
+        impl Hash for Colliding {
+            fn hash<H: Hasher>(&self, _state: &mut H) {}
+        }
+
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Other;
+
+        assert_eq!(LabelId::of(Colliding::A), LabelId::of(Colliding::A));
+        assert_ne!(LabelId::of(Colliding::A), LabelId::of(Colliding::B));
+        assert_ne!(LabelId::of(Other), LabelId::of(Colliding::A));
+
+        let labels = [Colliding::A, Colliding::B, Colliding::A]
+            .map(LabelId::of)
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(labels.len(), 2);
+    }
+
+    #[test]
+    fn test_exclusive_system_runs_alone() {
+        fn read_a(_: Res<Counter>) -> Result<()> {
+            Ok(())
+        }
+        fn read_b(_: Res<Counter>) -> Result<()> {
+            Ok(())
+        }
+        fn exclusive(_: &Arc<World>) -> Result<()> {
+            Ok(())
+        }
+        fn with_world(_: Arc<World>, _: Res<Counter>) -> Result<()> {
+            Ok(())
+        }
+
+        let mut graph = SystemGraph::default();
+        graph.add_system(read_a);
+        graph.add_system(exclusive);
+        graph.add_system(read_b);
+        graph.add_system(with_world);
+
+        // both the `&Arc<World>` system and the one taking `Arc<World>` get a layer to themselves
+        let layers = graph.get_layers();
+        let exclusive_systems = graph
+            .systems
+            .node_indices()
+            .filter(|node| graph.systems[*node].access().exclusive)
+            .collect::<Vec<_>>();
+        assert_eq!(exclusive_systems.len(), 2);
+        for node in exclusive_systems {
+            let layer = layers.iter().find(|layer| layer.contains(&node)).unwrap();
+            assert_eq!(layer.len(), 1);
+        }
     }
 }
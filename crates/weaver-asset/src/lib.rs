@@ -132,6 +132,13 @@ impl Assets {
             .and_then(|asset| (**asset).downcast_ref())
     }
 
+    // whether the handle points at an asset of its type, for handles whose type isn't known
+    pub fn is_loaded(&self, handle: UntypedHandle) -> bool {
+        self.storage
+            .get(handle.id)
+            .is_some_and(|asset| (*(**asset).as_any()).type_id() == handle.type_id)
+    }
+
     pub fn get_mut<T: Asset>(&mut self, handle: Handle<T>) -> Option<&mut T> {
         self.storage
             .get_mut(handle.id)
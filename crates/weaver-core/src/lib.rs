@@ -1,13 +1,16 @@
 use color::Color;
 use mesh::Mesh;
+use name::Name;
 use transform::Transform;
-use weaver_app::{plugin::Plugin, App};
+use weaver_app::{plugin::Plugin, system::SystemStage, App};
 use weaver_util::prelude::Result;
 
 pub mod color;
 pub mod geometry;
 pub mod input;
 pub mod mesh;
+pub mod name;
+pub mod prefab;
 pub mod texture;
 pub mod time;
 pub mod transform;
@@ -17,6 +20,8 @@ pub mod prelude {
     pub use crate::geometry::*;
     pub use crate::input::*;
     pub use crate::mesh::*;
+    pub use crate::name::*;
+    pub use crate::prefab::*;
     pub use crate::texture::*;
     pub use crate::time::*;
     pub use crate::transform::*;
@@ -28,7 +33,10 @@ pub struct CoreTypesPlugin;
 
 impl Plugin for CoreTypesPlugin {
     fn build(&self, app: &mut App) -> Result<()> {
-        app.register_type::<Transform>();
+        app.register_component::<Transform>();
+        app.register_component::<Name>();
+        name::index_names(app.world());
+        app.add_system(name::reindex_names, SystemStage::PostUpdate)?;
         app.register_type::<Color>();
         app.register_type::<Mesh>();
         app.register_type::<geometry::Plane>();
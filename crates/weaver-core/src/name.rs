@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use weaver_ecs::{
+    component::ResMut,
+    entity::Entity,
+    node::Node,
+    prelude::{Component, Resource},
+    query::{Changed, Query},
+    scene::Scene,
+    world::World,
+};
+use weaver_reflect::prelude::Reflect;
+use weaver_util::prelude::Result;
+
+// a human-readable name for an entity; inserting a new one keeps `NameIndex` in sync right
+// away, while names changed in place are picked up by `reindex_names` once per frame
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Reflect, Component)]
+pub struct Name {
+    name: String,
+}
+
+impl Name {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::ops::Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.name
+    }
+}
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl From<&str> for Name {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for Name {
+    fn from(name: String) -> Self {
+        Self::new(name)
+    }
+}
+
+// every named entity by name, kept up to date by `Name`'s component hooks and `reindex_names`
+#[derive(Resource, Default)]
+pub struct NameIndex {
+    // in the order the names were given
+    entities: HashMap<String, Vec<Entity>>,
+    names: HashMap<Entity, String>,
+}
+
+impl NameIndex {
+    pub fn get(&self, name: &str) -> &[Entity] {
+        self.entities
+            .get(name)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    // the entity given the name first, if any
+    pub fn get_first(&self, name: &str) -> Option<Entity> {
+        self.get(name).first().copied()
+    }
+
+    pub fn name_of(&self, entity: Entity) -> Option<&str> {
+        self.names.get(&entity).map(String::as_str)
+    }
+
+    // the entity's name along with its id, or just the id, for logs and tools
+    pub fn describe(&self, entity: Entity) -> String {
+        match self.name_of(entity) {
+            Some(name) => format!("{name} ({}v{})", entity.id(), entity.generation()),
+            None => format!("{}v{}", entity.id(), entity.generation()),
+        }
+    }
+
+    // finds a node below the scene root by the names along the way, e.g. "Player/Weapon/Muzzle"
+    pub fn find_path(&self, scene: &Scene, path: &str) -> Option<Node> {
+        self.find_path_from(scene, scene.root(), path)
+    }
+
+    // like `find_path`, relative to `from`; when several nodes match, the one named first wins
+    pub fn find_path_from(&self, scene: &Scene, from: Node, path: &str) -> Option<Node> {
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        let (last, ancestors) = segments.split_last()?;
+
+        // start from the nodes with the last name and walk up, rather than searching down
+        self.get(last).iter().find_map(|entity| {
+            let node = scene.find_node(*entity)?;
+            let mut current = node;
+            for segment in ancestors.iter().rev() {
+                current = scene.parent_of(current)?;
+                if self.name_of(current.entity()) != Some(*segment) {
+                    return None;
+                }
+            }
+            (scene.parent_of(current)? == from).then_some(node)
+        })
+    }
+
+    fn insert(&mut self, entity: Entity, name: &str) {
+        // keeps the entity's place among the others with the name
+        if self.name_of(entity) == Some(name) {
+            return;
+        }
+        self.remove(entity);
+        self.entities
+            .entry(name.to_owned())
+            .or_default()
+            .push(entity);
+        self.names.insert(entity, name.to_owned());
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        let Some(name) = self.names.remove(&entity) else {
+            return;
+        };
+        if let Some(entities) = self.entities.get_mut(&name) {
+            entities.retain(|other| *other != entity);
+            if entities.is_empty() {
+                self.entities.remove(&name);
+            }
+        }
+    }
+}
+
+// adds a `NameIndex` to the world, along with the hooks that maintain it
+pub fn index_names(world: &World) {
+    world.insert_resource(NameIndex::default());
+
+    fn update(world: &World, entity: Entity) {
+        let Some(name) = world.get_component::<Name>(entity) else {
+            return;
+        };
+        let mut index = world.get_resource_mut::<NameIndex>().unwrap();
+        index.insert(entity, &name);
+    }
+
+    world.on_insert::<Name>(update);
+    world.on_replace::<Name>(update);
+    world.on_remove::<Name>(|world, entity| {
+        let mut index = world.get_resource_mut::<NameIndex>().unwrap();
+        index.remove(entity);
+    });
+}
+
+// re-indexes names changed in place, e.g. through `Query<&mut Name>` or reflection
+pub fn reindex_names(
+    names: Query<&Name, Changed<Name>>,
+    mut index: ResMut<NameIndex>,
+) -> Result<()> {
+    for (entity, name) in names.iter() {
+        index.insert(entity, &name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use weaver_app::{system::SystemStage, App};
+
+    use crate::CoreTypesPlugin;
+
+    use super::*;
+
+    #[test]
+    fn test_name_index() {
+        let world = World::new();
+        index_names(&world);
+        let scene = world.root_scene();
+
+        let player = scene.spawn(Name::new("Player"));
+        let weapon = scene.spawn_child(player, Name::new("Weapon")).unwrap();
+        let muzzle = scene.spawn_child(weapon, Name::new("Muzzle")).unwrap();
+        // the same name further down doesn't match the path
+        let stray = scene.spawn_child(muzzle, Name::new("Muzzle")).unwrap();
+
+        let index = world.get_resource::<NameIndex>().unwrap();
+        assert_eq!(index.get("Muzzle"), [muzzle.entity(), stray.entity()]);
+        assert_eq!(
+            index.find_path(&scene, "Player/Weapon/Muzzle"),
+            Some(muzzle)
+        );
+        assert_eq!(index.find_path_from(&scene, weapon, "Muzzle"), Some(muzzle));
+        assert_eq!(index.find_path(&scene, "Weapon/Muzzle"), None);
+        assert_eq!(index.find_path(&scene, "Player/Muzzle"), None);
+        drop(index);
+
+        world.insert_component(weapon.entity(), Name::new("Gun"));
+        world.remove_component::<Name>(stray.entity());
+        world.destroy_entity(player.entity());
+
+        let index = world.get_resource::<NameIndex>().unwrap();
+        assert_eq!(index.name_of(weapon.entity()), Some("Gun"));
+        assert!(index.get("Weapon").is_empty());
+        assert_eq!(index.get("Muzzle"), [muzzle.entity()]);
+        assert_eq!(index.get_first("Player"), None);
+        assert_eq!(index.find_path_from(&scene, weapon, "Muzzle"), Some(muzzle));
+    }
+
+    #[test]
+    fn test_rename_in_place() {
+        let mut app = App::new().unwrap();
+        app.add_plugin(CoreTypesPlugin).unwrap();
+        let first = app.spawn(Name::new("Crate"));
+        let second = app.spawn(Name::new("Crate"));
+        app.run_schedule(SystemStage::PostUpdate).unwrap();
+
+        *app.world().get_component_mut::<Name>(first).unwrap() = Name::new("Barrel");
+        app.run_schedule(SystemStage::PostUpdate).unwrap();
+
+        let index = app.get_resource::<NameIndex>().unwrap();
+        assert_eq!(index.get("Crate"), [second]);
+        assert_eq!(index.get_first("Barrel"), Some(first));
+        assert_eq!(index.name_of(first), Some("Barrel"));
+    }
+}
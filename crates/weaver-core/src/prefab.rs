@@ -0,0 +1,517 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::Path,
+};
+
+use serde_json::Value as Json;
+use weaver_asset::{prelude::Asset, Assets, Handle};
+use weaver_ecs::{entity::Entity, node::Node, prelude::Component, scene::Scene, world::World};
+use weaver_reflect::{
+    registry::TypeRegistry,
+    scene::{deserialize_component, deserialize_scene_file, SceneFile},
+    serialize::apply_json,
+    Reflect,
+};
+use weaver_util::prelude::{anyhow, bail, Result};
+
+// a subtree of nodes with a single root, stored in the same format as saved scenes
+pub struct Prefab {
+    file: SceneFile,
+}
+
+impl Prefab {
+    pub fn from_json(source: &str) -> Result<Self> {
+        Self::from_file(serde_json::from_str(source)?)
+    }
+
+    pub fn from_file(file: SceneFile) -> Result<Self> {
+        let roots = file
+            .nodes
+            .iter()
+            .filter(|node| node.parent.is_none())
+            .count();
+        if roots != 1 {
+            bail!("A prefab needs exactly one root node, found {roots}");
+        }
+        Ok(Self { file })
+    }
+
+    pub fn file(&self) -> &SceneFile {
+        &self.file
+    }
+
+    // existing instances only see changes made here once they're reapplied
+    pub fn file_mut(&mut self) -> &mut SceneFile {
+        &mut self.file
+    }
+
+    fn root_id(&self) -> u64 {
+        root_id(&self.file).unwrap()
+    }
+}
+
+fn root_id(file: &SceneFile) -> Option<u64> {
+    Some(file.nodes.iter().find(|node| node.parent.is_none())?.id)
+}
+
+impl Asset for Prefab {
+    fn load(_assets: &mut Assets, path: &Path) -> Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PrefabOverride {
+    // the node's id in the prefab file
+    pub node: u64,
+    pub component: String,
+    // a dot-separated path of struct fields, e.g. "translation.x"
+    pub field: String,
+    pub value: Json,
+}
+
+// field values that differ from the prefab for one instance; they can't refer to entities
+#[derive(Debug, Clone, Default)]
+pub struct PrefabOverrides {
+    overrides: Vec<PrefabOverride>,
+}
+
+impl PrefabOverrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // later overrides of the same field win
+    pub fn set(
+        mut self,
+        node: u64,
+        component: impl Into<String>,
+        field: impl Into<String>,
+        value: impl Into<Json>,
+    ) -> Self {
+        self.overrides.push(PrefabOverride {
+            node,
+            component: component.into(),
+            field: field.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PrefabOverride> + '_ {
+        self.overrides.iter()
+    }
+
+    fn validate(&self, file: &SceneFile) -> Result<()> {
+        for entry in &self.overrides {
+            let node = file
+                .nodes
+                .iter()
+                .find(|node| node.id == entry.node)
+                .ok_or_else(|| anyhow!("The prefab has no node {}", entry.node))?;
+            if !node.components.contains_key(&entry.component) {
+                bail!("Node {} has no {} to override", entry.node, entry.component);
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(
+        &self,
+        node: u64,
+        type_name: &str,
+        component: &mut dyn Reflect,
+        registry: &TypeRegistry,
+    ) -> Result<()> {
+        let overrides = self
+            .overrides
+            .iter()
+            .filter(|entry| entry.node == node && entry.component == type_name);
+        for entry in overrides {
+            let field = component
+                .field_path_mut(&entry.field)
+                .ok_or_else(|| anyhow!("{type_name} has no field {}", entry.field))?;
+            apply_json(field, &entry.value, registry, &|_| None)?;
+        }
+        Ok(())
+    }
+}
+
+// sits on the root node of every prefab instance, linking it back to its prefab
+#[derive(Component)]
+pub struct PrefabInstance {
+    prefab: Handle<Prefab>,
+    overrides: PrefabOverrides,
+    // prefab node id -> the entity it was instantiated as
+    entities: HashMap<u64, Entity>,
+}
+
+impl PrefabInstance {
+    pub fn prefab(&self) -> Handle<Prefab> {
+        self.prefab
+    }
+
+    pub fn overrides(&self) -> &PrefabOverrides {
+        &self.overrides
+    }
+
+    pub fn entity(&self, node: u64) -> Option<Entity> {
+        self.entities.get(&node).copied()
+    }
+}
+
+pub trait ScenePrefabExt {
+    // spawns a copy of the prefab under `parent` and returns its root node
+    fn instantiate(&self, prefab: Handle<Prefab>, parent: Node) -> Result<Node>;
+
+    fn instantiate_with(
+        &self,
+        prefab: Handle<Prefab>,
+        parent: Node,
+        overrides: PrefabOverrides,
+    ) -> Result<Node>;
+
+    // brings an instance's components back in line with its prefab, keeping its overrides;
+    // nodes added to the prefab since are spawned, nodes removed from the instance stay removed
+    fn reapply_prefab(&self, instance: Node) -> Result<()>;
+}
+
+fn prefab_file(world: &World, prefab: Handle<Prefab>) -> Result<SceneFile> {
+    let assets = world
+        .get_resource::<Assets>()
+        .ok_or_else(|| anyhow!("No Assets resource"))?;
+    let prefab = assets
+        .get(prefab)
+        .ok_or_else(|| anyhow!("Prefab {} is not loaded", prefab.id()))?;
+    Ok(prefab.file.clone())
+}
+
+impl ScenePrefabExt for Scene {
+    fn instantiate(&self, prefab: Handle<Prefab>, parent: Node) -> Result<Node> {
+        self.instantiate_with(prefab, parent, PrefabOverrides::new())
+    }
+
+    fn instantiate_with(
+        &self,
+        prefab: Handle<Prefab>,
+        parent: Node,
+        overrides: PrefabOverrides,
+    ) -> Result<Node> {
+        let world = self.world();
+        // the assets are unlocked again before anything is spawned, in case a hook needs them
+        let file = prefab_file(world, prefab)?;
+        overrides.validate(&file)?;
+
+        let registry = world
+            .get_resource::<TypeRegistry>()
+            .ok_or_else(|| anyhow!("No TypeRegistry resource"))?;
+        let loaded = deserialize_scene_file(
+            world,
+            &registry,
+            &file,
+            &mut |node, type_name, component| {
+                overrides.apply(node, type_name, component, &registry)
+            },
+        );
+        // unlocked before anything is attached or inserted, in case a hook needs the registry
+        drop(registry);
+        let nodes = loaded?.spawn(self, parent)?;
+
+        let entities = file
+            .nodes
+            .iter()
+            .zip(&nodes)
+            .map(|(entry, node)| (entry.id, node.entity()))
+            .collect::<HashMap<_, _>>();
+        let root_id = Prefab::from_file(file)?.root_id();
+        let root = self
+            .find_node(entities[&root_id])
+            .ok_or_else(|| anyhow!("The prefab root wasn't added to the scene"))?;
+
+        world.insert_component(
+            root.entity(),
+            PrefabInstance {
+                prefab,
+                overrides,
+                entities,
+            },
+        );
+
+        Ok(root)
+    }
+
+    fn reapply_prefab(&self, instance: Node) -> Result<()> {
+        let world = self.world();
+        let (prefab, overrides, mut entities) = {
+            let instance = world
+                .get_component::<PrefabInstance>(instance.entity())
+                .ok_or_else(|| anyhow!("{:?} is not a prefab instance", instance.entity()))?;
+            (
+                instance.prefab,
+                instance.overrides.clone(),
+                instance.entities.clone(),
+            )
+        };
+        let file = prefab_file(world, prefab)?;
+        overrides.validate(&file)?;
+
+        // entities for new nodes are made up front so references to them can be resolved
+        let mut new_nodes = Vec::new();
+        for entry in &file.nodes {
+            if let Entry::Vacant(slot) = entities.entry(entry.id) {
+                let entity = world.create_entity();
+                slot.insert(entity);
+                new_nodes.push((entry, entity));
+            }
+        }
+
+        let registry = world
+            .get_resource::<TypeRegistry>()
+            .ok_or_else(|| anyhow!("No TypeRegistry resource"))?;
+        let id_to_entity = |id: u64| entities.get(&id).copied();
+
+        // everything is deserialized before the instance is touched, so a bad prefab leaves
+        // it as it was
+        let mut components = Vec::new();
+        let mut result = Ok(());
+        for entry in &file.nodes {
+            let entity = entities[&entry.id];
+            for (type_name, json) in &entry.components {
+                let component = deserialize_component(&registry, type_name, json, &id_to_entity)
+                    .and_then(|(reflect_component, mut component)| {
+                        overrides.apply(entry.id, type_name, &mut *component, &registry)?;
+                        Ok((entity, reflect_component.clone(), component))
+                    });
+                match component {
+                    Ok(component) => components.push(component),
+                    Err(err) => result = Err(err),
+                }
+            }
+        }
+        // unlocked before anything is attached or inserted, in case a hook needs the registry
+        drop(registry);
+        if let Err(err) = result {
+            for (_, entity) in new_nodes {
+                world.destroy_entity(entity);
+            }
+            return Err(err);
+        }
+
+        // a node may be listed before its parent, so new nodes are attached walking down from
+        // the prefab root, with siblings in file order
+        let mut children = HashMap::<u64, Vec<u64>>::new();
+        for entry in &file.nodes {
+            if let Some(parent) = entry.parent {
+                children.entry(parent).or_default().push(entry.id);
+            }
+        }
+        let mut new_nodes = new_nodes
+            .into_iter()
+            .map(|(entry, entity)| (entry.id, (entry, entity)))
+            .collect::<HashMap<_, _>>();
+        let mut stack = root_id(&file).into_iter().collect::<Vec<_>>();
+        while let Some(id) = stack.pop() {
+            if let Some(children) = children.get(&id) {
+                stack.extend(children.iter().rev());
+            }
+            let Some((entry, entity)) = new_nodes.remove(&id) else {
+                continue;
+            };
+
+            let parent = entry
+                .parent
+                .and_then(|parent| entities.get(&parent))
+                .and_then(|parent| self.find_node(*parent));
+            match parent {
+                Some(parent) => {
+                    self.add_child(parent, entity)?;
+                }
+                // its parent was removed from the instance, so it goes too
+                None => {
+                    world.destroy_entity(entity);
+                    entities.remove(&entry.id);
+                }
+            }
+        }
+        // anything left isn't below the root, so there's nowhere to attach it
+        for (id, (_, entity)) in new_nodes {
+            world.destroy_entity(entity);
+            entities.remove(&id);
+        }
+
+        for (entity, reflect_component, component) in components {
+            if world.is_alive(entity) {
+                reflect_component.insert(world, entity, component)?;
+            }
+        }
+
+        if let Some(mut instance) = world.get_component_mut::<PrefabInstance>(instance.entity()) {
+            instance.entities = entities;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use weaver_reflect::component::ReflectComponent;
+
+    use crate::{name::Name, transform::Transform};
+
+    use super::*;
+
+    const TURRET: &str = r#"{
+        "nodes": [
+            { "id": 0, "parent": null, "components": {
+                "Name": { "name": "Turret" },
+                "Transform": { "translation": { "x": 0.0, "y": 1.0, "z": 0.0 } }
+            } },
+            { "id": 1, "parent": 0, "components": { "Name": { "name": "Muzzle" } } }
+        ]
+    }"#;
+
+    fn world_with_turret() -> (std::sync::Arc<World>, Handle<Prefab>) {
+        let world = World::new();
+        let mut registry = TypeRegistry::new();
+        registry.register::<Name>();
+        registry.register_type_data::<Name, ReflectComponent>();
+        registry.register::<Transform>();
+        registry.register_type_data::<Transform, ReflectComponent>();
+        world.insert_resource(registry);
+
+        let mut assets = Assets::new();
+        let turret = assets.insert(Prefab::from_json(TURRET).unwrap());
+        world.insert_resource(assets);
+        (world, turret)
+    }
+
+    fn name_of(world: &World, node: Node) -> String {
+        world
+            .get_component::<Name>(node.entity())
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_prefab_instances() {
+        let (world, turret) = world_with_turret();
+        let scene = world.root_scene();
+
+        let plain = scene.instantiate(turret, scene.root()).unwrap();
+        let moved = scene
+            .instantiate_with(
+                turret,
+                plain,
+                PrefabOverrides::new().set(0, "Transform", "translation.x", 5.0),
+            )
+            .unwrap();
+        assert!(scene
+            .instantiate_with(
+                turret,
+                plain,
+                PrefabOverrides::new().set(0, "Transform", "rotation.v", 5.0),
+            )
+            .is_err());
+
+        assert_eq!(scene.children_of(plain).len(), 2);
+        assert_eq!(scene.parent_of(moved), Some(plain));
+        let muzzle = scene.children_of(moved)[0];
+        assert_eq!(name_of(&world, muzzle), "Muzzle");
+        let translation = world
+            .get_component::<Transform>(moved.entity())
+            .unwrap()
+            .translation;
+        assert_eq!(translation.to_array(), [5.0, 1.0, 0.0]);
+
+        // edit the prefab, then bring one of the instances up to date
+        {
+            let mut assets = world.get_resource_mut::<Assets>().unwrap();
+            let file = assets.get_mut(turret).unwrap().file_mut();
+            file.nodes[0].components.insert(
+                "Transform".to_owned(),
+                json!({ "translation": { "x": 0.0, "y": 2.0, "z": 0.0 } }),
+            );
+            file.nodes[1]
+                .components
+                .insert("Name".to_owned(), json!({ "name": "Barrel" }));
+            file.nodes.push(
+                serde_json::from_value(json!({
+                    "id": 2, "parent": 1, "components": { "Name": { "name": "Flash" } }
+                }))
+                .unwrap(),
+            );
+        }
+        scene.reapply_prefab(moved).unwrap();
+
+        assert_eq!(name_of(&world, muzzle), "Barrel");
+        let translation = world
+            .get_component::<Transform>(moved.entity())
+            .unwrap()
+            .translation;
+        assert_eq!(translation.to_array(), [5.0, 2.0, 0.0]);
+        let flash = scene.children_of(muzzle);
+        assert_eq!(flash.len(), 1);
+        assert_eq!(name_of(&world, flash[0]), "Flash");
+        let instance = world
+            .get_component::<PrefabInstance>(moved.entity())
+            .unwrap();
+        assert_eq!(instance.entity(2), Some(flash[0].entity()));
+        drop(instance);
+
+        // the other instance keeps the old version until it's reapplied too
+        assert_eq!(name_of(&world, scene.children_of(plain)[0]), "Muzzle");
+    }
+
+    #[test]
+    fn test_reapply_prefab_child_listed_first() {
+        let (world, turret) = world_with_turret();
+        let scene = world.root_scene();
+        let instance = scene.instantiate(turret, scene.root()).unwrap();
+        let muzzle = scene.children_of(instance)[0];
+
+        {
+            let mut assets = world.get_resource_mut::<Assets>().unwrap();
+            let file = assets.get_mut(turret).unwrap().file_mut();
+            for node in [
+                json!({ "id": 3, "parent": 2, "components": { "Name": { "name": "Spark" } } }),
+                json!({ "id": 2, "parent": 1, "components": { "Name": { "name": "Flash" } } }),
+            ] {
+                file.nodes.push(serde_json::from_value(node).unwrap());
+            }
+        }
+        scene.reapply_prefab(instance).unwrap();
+
+        let flash = scene.children_of(muzzle);
+        assert_eq!(flash.len(), 1);
+        assert_eq!(name_of(&world, flash[0]), "Flash");
+        let spark = scene.children_of(flash[0]);
+        assert_eq!(spark.len(), 1);
+        assert_eq!(name_of(&world, spark[0]), "Spark");
+        let prefab_instance = world
+            .get_component::<PrefabInstance>(instance.entity())
+            .unwrap();
+        assert_eq!(prefab_instance.entity(3), Some(spark[0].entity()));
+        drop(prefab_instance);
+
+        // new instances of the edited prefab accept the same order
+        let fresh = scene.instantiate(turret, scene.root()).unwrap();
+        let muzzle = scene.children_of(fresh)[0];
+        let flash = scene.children_of(muzzle)[0];
+        assert_eq!(name_of(&world, flash), "Flash");
+        assert_eq!(name_of(&world, scene.children_of(flash)[0]), "Spark");
+    }
+
+    #[test]
+    fn test_hooks_can_lock_registry() {
+        let (world, turret) = world_with_turret();
+        world.on_insert::<Name>(|world, _| {
+            drop(world.get_resource_mut::<TypeRegistry>().unwrap());
+        });
+
+        let scene = world.root_scene();
+        let instance = scene.instantiate(turret, scene.root()).unwrap();
+        scene.reapply_prefab(instance).unwrap();
+    }
+}
@@ -1,6 +1,13 @@
-use weaver_app::{plugin::Plugin, system::SystemStage, App};
-use weaver_ecs::{component::ResMut, prelude::Resource};
-use weaver_util::prelude::Result;
+use std::sync::Arc;
+
+use weaver_app::{
+    plugin::Plugin,
+    schedule::{run_schedule, ScheduleLabel},
+    system::{LabelId, SystemStage},
+    App,
+};
+use weaver_ecs::{component::ResMut, prelude::Resource, world::World};
+use weaver_util::prelude::{anyhow, Result};
 
 #[derive(Resource)]
 pub struct Time {
@@ -30,17 +37,146 @@ impl Time {
     }
 }
 
+// runs zero or more times a frame, `FixedTime::step` apart in simulated time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FixedUpdate;
+impl ScheduleLabel for FixedUpdate {}
+
+// runs `FixedUpdate` as many times as the frame calls for, between `PreUpdate` and `Update`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RunFixedUpdate;
+impl ScheduleLabel for RunFixedUpdate {}
+
+#[derive(Resource)]
+pub struct FixedTime {
+    step: f32,
+    // frames longer than this many steps are simulated more slowly than real time, so the
+    // simulation can't fall further and further behind
+    max_substeps: u32,
+    accumulator: f32,
+    steps: u32,
+    alpha: f32,
+}
+
+impl FixedTime {
+    pub fn new(step: f32, max_substeps: u32) -> Self {
+        Self {
+            step,
+            max_substeps,
+            accumulator: 0.0,
+            steps: 0,
+            alpha: 0.0,
+        }
+    }
+
+    pub fn from_hz(hz: f32) -> Self {
+        Self::new(1.0 / hz, 5)
+    }
+
+    pub fn with_max_substeps(mut self, max_substeps: u32) -> Self {
+        self.max_substeps = max_substeps;
+        self
+    }
+
+    // the time each run of `FixedUpdate` simulates, in seconds
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    pub fn max_substeps(&self) -> u32 {
+        self.max_substeps
+    }
+
+    // how many times `FixedUpdate` ran this frame
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    // how far into the next step the frame is, from 0 to 1; for rendering somewhere between the
+    // last two simulated states
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    // adds a frame's worth of time, returning how many steps to run for it
+    pub fn accumulate(&mut self, delta_time: f32) -> u32 {
+        self.accumulator += delta_time;
+        let mut steps = (self.accumulator / self.step) as u32;
+        self.accumulator -= steps as f32 * self.step;
+        if steps > self.max_substeps {
+            // the time that doesn't fit is dropped rather than caught up on later
+            steps = self.max_substeps;
+        }
+        self.steps = steps;
+        self.alpha = (self.accumulator / self.step).clamp(0.0, 1.0);
+        steps
+    }
+}
+
+impl Default for FixedTime {
+    fn default() -> Self {
+        Self::from_hz(60.0)
+    }
+}
+
 pub struct TimePlugin;
 
 impl Plugin for TimePlugin {
     fn build(&self, app: &mut App) -> Result<()> {
         app.world().insert_resource(Time::new());
+        // a `FixedTime` inserted before the plugin is kept, to configure the step
+        if !app.world().has_resource::<FixedTime>() {
+            app.world().insert_resource(FixedTime::default());
+        }
         app.add_system(update_time, SystemStage::PreUpdate)?;
+        app.add_schedule_after(RunFixedUpdate, SystemStage::PreUpdate)?;
+        app.add_system(run_fixed_update, RunFixedUpdate)?;
+        app.schedule(FixedUpdate);
         Ok(())
     }
 }
 
+fn run_fixed_update(world: &Arc<World>) -> Result<()> {
+    let steps = {
+        let time = world
+            .get_resource::<Time>()
+            .ok_or_else(|| anyhow!("No Time resource"))?;
+        let mut fixed_time = world
+            .get_resource_mut::<FixedTime>()
+            .ok_or_else(|| anyhow!("No FixedTime resource"))?;
+        fixed_time.accumulate(time.delta_time)
+    };
+
+    let label = LabelId::of(FixedUpdate);
+    for _ in 0..steps {
+        run_schedule(world, &label)?;
+    }
+    Ok(())
+}
+
 fn update_time(mut time: ResMut<Time>) -> Result<()> {
     time.update();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_time_accumulator() {
+        let mut fixed_time = FixedTime::new(0.25, 3);
+
+        assert_eq!(fixed_time.accumulate(0.125), 0);
+        assert_eq!(fixed_time.alpha(), 0.5);
+        assert_eq!(fixed_time.accumulate(0.5), 2);
+        assert_eq!(fixed_time.steps(), 2);
+        assert_eq!(fixed_time.alpha(), 0.5);
+
+        // a long frame runs the most steps allowed and drops the rest
+        assert_eq!(fixed_time.accumulate(2.0), 3);
+        assert_eq!(fixed_time.alpha(), 0.5);
+        assert_eq!(fixed_time.accumulate(0.125), 1);
+        assert_eq!(fixed_time.alpha(), 0.0);
+    }
+}
@@ -1 +1,2 @@
 pub mod frame_time;
+pub mod world_stats;
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use weaver_app::{plugin::Plugin, system::SystemStage, App};
+use weaver_ecs::{
+    prelude::{Resource, WorldStats},
+    world::World,
+};
+use weaver_util::prelude::Result;
+
+#[derive(Resource)]
+pub struct WorldStatsDiagnostics {
+    pub log_interval: Option<std::time::Duration>,
+    pub last_log: std::time::Instant,
+}
+
+// keeps a `WorldStats` resource up to date, refreshed once per frame after the update stages
+pub struct WorldStatsPlugin {
+    // logs a summary this often, if set
+    pub log_interval: Option<std::time::Duration>,
+}
+
+impl Plugin for WorldStatsPlugin {
+    fn build(&self, app: &mut App) -> Result<()> {
+        app.world().insert_resource(WorldStats::default());
+        app.world().insert_resource(WorldStatsDiagnostics {
+            log_interval: self.log_interval,
+            last_log: std::time::Instant::now(),
+        });
+        app.add_system(update_world_stats, SystemStage::PostUpdate)?;
+
+        Ok(())
+    }
+}
+
+fn update_world_stats(world: &Arc<World>) -> Result<()> {
+    let stats = world.stats();
+
+    let mut diagnostics = world.get_resource_mut::<WorldStatsDiagnostics>().unwrap();
+    let now = std::time::Instant::now();
+    if diagnostics
+        .log_interval
+        .is_some_and(|interval| now.duration_since(diagnostics.last_log) >= interval)
+    {
+        diagnostics.last_log = now;
+
+        log::info!(
+            "Entities: {}, archetypes: {} ({} empty), components: {:.2}KiB, resources: {:.2}KiB",
+            stats.entities,
+            stats.archetypes.len(),
+            stats.empty_archetypes(),
+            stats.component_bytes() as f32 / 1024.0,
+            stats.resource_bytes() as f32 / 1024.0
+        );
+    }
+    drop(diagnostics);
+
+    *world.get_resource_mut::<WorldStats>().unwrap() = stats;
+
+    Ok(())
+}
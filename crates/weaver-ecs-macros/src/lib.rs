@@ -29,3 +29,38 @@ pub fn derive_resource(input: TokenStream) -> TokenStream {
 
     expanded.into()
 }
+
+// every field has to be a component or another bundle; nested bundles are flattened
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let name = &input.ident;
+
+    let syn::Data::Struct(data) = &input.data else {
+        return syn::Error::new_spanned(name, "Only structs can be bundles")
+            .to_compile_error()
+            .into();
+    };
+    let syn::Fields::Named(fields) = &data.fields else {
+        return syn::Error::new_spanned(name, "Only structs with named fields can be bundles")
+            .to_compile_error()
+            .into();
+    };
+
+    let field_names = fields.named.iter().map(|field| &field.ident);
+
+    let expanded = quote! {
+        impl #impl_generics weaver_ecs::prelude::Bundle for #name #ty_generics #where_clause {
+            fn into_components(self) -> Vec<Box<dyn weaver_ecs::prelude::Component>> {
+                let mut components = Vec::new();
+                #(
+                    components.extend(weaver_ecs::prelude::Bundle::into_components(self.#field_names));
+                )*
+                components
+            }
+        }
+    };
+
+    expanded.into()
+}
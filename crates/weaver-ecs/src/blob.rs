@@ -0,0 +1,257 @@
+use std::{
+    alloc::{self, Layout},
+    ptr::NonNull,
+};
+
+// a type-erased vector of values sharing the same layout and drop function
+pub struct BlobVec {
+    item_layout: Layout,
+    drop: Option<unsafe fn(*mut u8)>,
+    data: NonNull<u8>,
+    len: usize,
+    capacity: usize,
+}
+
+// SAFETY: only component types, which are Send + Sync, are stored in a BlobVec
+unsafe impl Send for BlobVec {}
+unsafe impl Sync for BlobVec {}
+
+impl BlobVec {
+    pub fn new(item_layout: Layout, drop: Option<unsafe fn(*mut u8)>) -> Self {
+        let item_layout = item_layout.pad_to_align();
+        let capacity = if item_layout.size() == 0 {
+            usize::MAX
+        } else {
+            0
+        };
+
+        Self {
+            item_layout,
+            drop,
+            data: dangling(item_layout.align()),
+            len: 0,
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let available = self.capacity - self.len;
+        if available < additional {
+            let required = self.len.checked_add(additional).expect("capacity overflow");
+            self.grow_to(required.max(self.capacity * 2).max(4));
+        }
+    }
+
+    fn grow_to(&mut self, new_capacity: usize) {
+        debug_assert!(self.item_layout.size() != 0);
+
+        let new_layout = self.array_layout(new_capacity);
+        let new_data = if self.capacity == 0 {
+            // SAFETY: the layout has a non-zero size
+            unsafe { alloc::alloc(new_layout) }
+        } else {
+            // SAFETY: data was allocated with the layout for the current capacity
+            unsafe {
+                alloc::realloc(
+                    self.data.as_ptr(),
+                    self.array_layout(self.capacity),
+                    new_layout.size(),
+                )
+            }
+        };
+
+        self.data = NonNull::new(new_data).unwrap_or_else(|| alloc::handle_alloc_error(new_layout));
+        self.capacity = new_capacity;
+    }
+
+    fn array_layout(&self, capacity: usize) -> Layout {
+        let size = self
+            .item_layout
+            .size()
+            .checked_mul(capacity)
+            .expect("capacity overflow");
+        Layout::from_size_align(size, self.item_layout.align()).expect("invalid array layout")
+    }
+
+    // SAFETY: `index` must be less than `len`
+    pub unsafe fn get_unchecked(&self, index: usize) -> *mut u8 {
+        debug_assert!(index < self.len);
+        self.data.as_ptr().add(index * self.item_layout.size())
+    }
+
+    // bytes currently allocated, used or not
+    pub fn allocated_bytes(&self) -> usize {
+        if self.item_layout.size() == 0 {
+            0
+        } else {
+            self.capacity * self.item_layout.size()
+        }
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.data.as_ptr()
+    }
+
+    // moves the value behind `value` into the vector
+    // SAFETY: `value` must point to a valid value of the item type, which must not be used or dropped afterwards
+    pub unsafe fn push(&mut self, value: *const u8) {
+        self.reserve(1);
+        let index = self.len;
+        self.len += 1;
+        std::ptr::copy_nonoverlapping(value, self.get_unchecked(index), self.item_layout.size());
+    }
+
+    // drops the value at `index` and moves `value` into its place
+    // SAFETY: `index` must be less than `len`, and `value` must be valid as in `push`
+    pub unsafe fn replace_unchecked(&mut self, index: usize, value: *const u8) {
+        let ptr = self.get_unchecked(index);
+        if let Some(drop) = self.drop {
+            drop(ptr);
+        }
+        std::ptr::copy_nonoverlapping(value, ptr, self.item_layout.size());
+    }
+
+    // fills the hole at `index` with the last value without dropping anything
+    // SAFETY: `index` must be less than `len`, and the value at `index` must already have been moved out
+    pub unsafe fn swap_remove_and_forget_unchecked(&mut self, index: usize) {
+        let last = self.len - 1;
+        if index != last {
+            std::ptr::copy_nonoverlapping(
+                self.get_unchecked(last),
+                self.get_unchecked(index),
+                self.item_layout.size(),
+            );
+        }
+        self.len = last;
+    }
+
+    // SAFETY: `index` must be less than `len`
+    pub unsafe fn swap_remove_and_drop_unchecked(&mut self, index: usize) {
+        let last = self.len - 1;
+        if index != last {
+            std::ptr::swap_nonoverlapping(
+                self.get_unchecked(index),
+                self.get_unchecked(last),
+                self.item_layout.size(),
+            );
+        }
+        self.len = last;
+        if let Some(drop) = self.drop {
+            drop(self.data.as_ptr().add(last * self.item_layout.size()));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let len = self.len;
+        // set the length first so a panicking drop can't cause a double drop
+        self.len = 0;
+        if let Some(drop) = self.drop {
+            for index in 0..len {
+                // SAFETY: every index below the old length holds a valid value
+                unsafe { drop(self.data.as_ptr().add(index * self.item_layout.size())) };
+            }
+        }
+    }
+}
+
+impl Drop for BlobVec {
+    fn drop(&mut self) {
+        self.clear();
+        if self.item_layout.size() != 0 && self.capacity != 0 {
+            // SAFETY: data was allocated with the layout for the current capacity
+            unsafe { alloc::dealloc(self.data.as_ptr(), self.array_layout(self.capacity)) };
+        }
+    }
+}
+
+fn dangling(align: usize) -> NonNull<u8> {
+    // SAFETY: alignments are never zero
+    unsafe { NonNull::new_unchecked(align as *mut u8) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+
+    unsafe fn drop_ptr<T>(ptr: *mut u8) {
+        ptr.cast::<T>().drop_in_place();
+    }
+
+    unsafe fn push<T>(blob: &mut BlobVec, value: T) {
+        let value = std::mem::ManuallyDrop::new(value);
+        blob.push(&*value as *const T as *const u8);
+    }
+
+    #[test]
+    fn test_push_get() {
+        let mut blob = BlobVec::new(Layout::new::<u64>(), None);
+
+        for i in 0..100u64 {
+            unsafe { push(&mut blob, i) };
+        }
+
+        assert_eq!(blob.len(), 100);
+        for i in 0..100 {
+            assert_eq!(unsafe { *blob.get_unchecked(i).cast::<u64>() }, i as u64);
+        }
+    }
+
+    #[test]
+    fn test_swap_remove() {
+        let mut blob = BlobVec::new(Layout::new::<u32>(), None);
+
+        for i in 0..4u32 {
+            unsafe { push(&mut blob, i) };
+        }
+
+        let removed = unsafe { blob.get_unchecked(1).cast::<u32>().read() };
+        unsafe { blob.swap_remove_and_forget_unchecked(1) };
+        assert_eq!(removed, 1);
+        assert_eq!(blob.len(), 3);
+        assert_eq!(unsafe { *blob.get_unchecked(1).cast::<u32>() }, 3);
+
+        unsafe { blob.swap_remove_and_drop_unchecked(0) };
+        assert_eq!(blob.len(), 2);
+        assert_eq!(unsafe { *blob.get_unchecked(0).cast::<u32>() }, 2);
+    }
+
+    #[test]
+    fn test_drop() {
+        let value = Rc::new(());
+        let mut blob = BlobVec::new(Layout::new::<Rc<()>>(), Some(drop_ptr::<Rc<()>>));
+
+        for _ in 0..3 {
+            unsafe { push(&mut blob, value.clone()) };
+        }
+        assert_eq!(Rc::strong_count(&value), 4);
+
+        unsafe { blob.swap_remove_and_drop_unchecked(0) };
+        assert_eq!(Rc::strong_count(&value), 3);
+
+        let replacement = std::mem::ManuallyDrop::new(value.clone());
+        unsafe { blob.replace_unchecked(0, &*replacement as *const Rc<()> as *const u8) };
+        assert_eq!(Rc::strong_count(&value), 3);
+
+        drop(blob);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_zero_sized() {
+        let mut blob = BlobVec::new(Layout::new::<()>(), None);
+
+        for _ in 0..10 {
+            unsafe { push(&mut blob, ()) };
+        }
+
+        assert_eq!(blob.len(), 10);
+        unsafe { blob.swap_remove_and_drop_unchecked(3) };
+        assert_eq!(blob.len(), 9);
+    }
+}
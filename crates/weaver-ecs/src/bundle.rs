@@ -33,13 +33,16 @@ impl<T: Component> Bundle for Vec<T> {
     }
 }
 
+// tuples can hold components or other bundles, which are flattened in order
 macro_rules! impl_bundle_tuple {
     ($($name:ident),*) => {
         #[allow(non_snake_case)]
-        impl<$($name: Component),*> Bundle for ($($name,)*) {
+        impl<$($name: Bundle),*> Bundle for ($($name,)*) {
             fn into_components(self) -> Vec<Box<dyn Component>> {
                 let ($($name,)*) = self;
-                vec![$(Box::new($name) as Box<dyn Component>,)*]
+                let mut components = Vec::new();
+                $(components.extend($name.into_components());)*
+                components
             }
         }
     };
@@ -53,3 +56,61 @@ impl_bundle_tuple!(A, B, C, D, E);
 impl_bundle_tuple!(A, B, C, D, E, F);
 impl_bundle_tuple!(A, B, C, D, E, F, G);
 impl_bundle_tuple!(A, B, C, D, E, F, G, H);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H, I);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, N);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+
+#[cfg(test)]
+mod tests {
+    use crate::{self as weaver_ecs, prelude::World};
+    use weaver_ecs_macros::{Bundle, Component};
+
+    use super::*;
+
+    #[derive(Component)]
+    struct Position(f32);
+
+    #[derive(Component)]
+    struct Velocity(f32);
+
+    #[derive(Component)]
+    struct Player;
+
+    #[derive(Bundle)]
+    struct Body {
+        position: Position,
+        velocity: Velocity,
+    }
+
+    #[derive(Bundle)]
+    struct PlayerBundle {
+        body: Body,
+        player: Player,
+    }
+
+    #[test]
+    fn test_nested_bundles() {
+        let world = World::new();
+
+        let bundle = PlayerBundle {
+            body: Body {
+                position: Position(1.0),
+                velocity: Velocity(2.0),
+            },
+            player: Player,
+        };
+        let entity = world.spawn(bundle);
+        assert_eq!(world.get_component::<Position>(entity).unwrap().0, 1.0);
+        assert_eq!(world.get_component::<Velocity>(entity).unwrap().0, 2.0);
+        assert!(world.has_component::<Player>(entity));
+
+        // tuples of bundles are flattened too
+        let bundle = ((Position(3.0), (Velocity(4.0),)), Player);
+        assert_eq!(bundle.into_components().len(), 3);
+    }
+}
@@ -1,3 +1,5 @@
+use std::cell::Cell;
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Tick {
     tick: u64,
@@ -32,3 +34,71 @@ impl std::fmt::Display for Tick {
         write!(f, "{}", self.tick)
     }
 }
+
+impl Tick {
+    pub fn is_newer_than(&self, last_run: Tick, this_run: Tick) -> bool {
+        self.tick > last_run.tick && self.tick <= this_run.tick
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: Tick,
+    pub changed: Tick,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: Tick) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+
+    pub fn is_added(&self, last_run: Tick, this_run: Tick) -> bool {
+        self.added.is_newer_than(last_run, this_run)
+    }
+
+    pub fn is_changed(&self, last_run: Tick, this_run: Tick) -> bool {
+        self.changed.is_newer_than(last_run, this_run)
+    }
+
+    pub fn set_changed(&mut self, tick: Tick) {
+        self.changed = tick;
+    }
+}
+
+thread_local! {
+    static CURRENT_SYSTEM_TICKS: Cell<Option<SystemTicks>> = const { Cell::new(None) };
+}
+
+// ticks of the system currently running on this thread
+// a component counts as added/changed if its tick lies in (last_run, this_run]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemTicks {
+    pub last_run: Tick,
+    pub this_run: Tick,
+}
+
+impl SystemTicks {
+    pub fn new(last_run: Tick, this_run: Tick) -> Self {
+        Self { last_run, this_run }
+    }
+
+    pub fn current() -> Option<Self> {
+        CURRENT_SYSTEM_TICKS.with(|ticks| ticks.get())
+    }
+
+    pub fn run_with<R>(self, f: impl FnOnce() -> R) -> R {
+        struct Restore(Option<SystemTicks>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT_SYSTEM_TICKS.with(|ticks| ticks.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CURRENT_SYSTEM_TICKS.with(|ticks| ticks.replace(Some(self))));
+        f()
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use weaver_util::lock::ArcWrite;
+
+use crate::prelude::{Bundle, Component, Entity, Relationship, World};
+
+pub trait Command: Send + Sync + 'static {
+    fn apply(self: Box<Self>, world: &Arc<World>);
+}
+
+impl<F> Command for F
+where
+    F: FnOnce(&Arc<World>) + Send + Sync + 'static,
+{
+    fn apply(self: Box<Self>, world: &Arc<World>) {
+        (*self)(world)
+    }
+}
+
+// structural changes recorded by a system, applied in order at the end of its stage
+#[derive(Default)]
+pub struct CommandQueue {
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl CommandQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<C: Command>(&mut self, command: C) {
+        self.commands.push(Box::new(command));
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn apply(&mut self, world: &Arc<World>) {
+        for command in self.commands.drain(..) {
+            command.apply(world);
+        }
+    }
+}
+
+pub struct Commands {
+    world: Arc<World>,
+    queue: ArcWrite<CommandQueue>,
+}
+
+impl Commands {
+    pub fn new(world: Arc<World>, queue: ArcWrite<CommandQueue>) -> Self {
+        Self { world, queue }
+    }
+
+    pub fn add<C: Command>(&mut self, command: C) -> &mut Self {
+        self.queue.push(command);
+        self
+    }
+
+    // the entity is allocated right away so it can be referred to by later commands
+    pub fn spawn<T: Bundle>(&mut self, bundle: T) -> Entity {
+        let entity = self.world.create_entity();
+        self.insert(entity, bundle);
+        entity
+    }
+
+    pub fn insert<T: Bundle>(&mut self, entity: Entity, bundle: T) -> &mut Self {
+        let components = bundle.into_components();
+        self.add(move |world: &Arc<World>| world.insert_components(entity, components))
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> &mut Self {
+        self.add(move |world: &Arc<World>| {
+            world.remove_component::<T>(entity);
+        })
+    }
+
+    pub fn despawn(&mut self, entity: Entity) -> &mut Self {
+        self.add(move |world: &Arc<World>| world.destroy_entity(entity))
+    }
+
+    // spawns an entity and adds it as a node of the root scene
+    pub fn spawn_node<T: Bundle>(&mut self, bundle: T) -> Entity {
+        let entity = self.spawn(bundle);
+        self.add(move |world: &Arc<World>| {
+            world.root_scene().add_node(entity);
+        });
+        entity
+    }
+
+    pub fn add_relationship<T: Relationship>(
+        &mut self,
+        from: Entity,
+        to: Entity,
+        weight: T,
+    ) -> &mut Self {
+        self.add(move |world: &Arc<World>| {
+            let scene = world.root_scene();
+            match (scene.find_node(from), scene.find_node(to)) {
+                (Some(from), Some(to)) => scene.add_relationship(from, to, weight),
+                _ => log::warn!("Tried to relate entities that are not in the root scene"),
+            }
+        })
+    }
+
+    // removes the entity's node from the root scene and destroys the entity
+    pub fn despawn_node(&mut self, entity: Entity) -> &mut Self {
+        self.add(move |world: &Arc<World>| {
+            let scene = world.root_scene();
+            if let Some(node) = scene.find_node(entity) {
+                scene.remove_node(node);
+            }
+            drop(scene);
+            world.destroy_entity(entity);
+        })
+    }
+
+    // destroys the entity along with every descendant of its node in the root scene
+    pub fn despawn_recursive(&mut self, entity: Entity) -> &mut Self {
+        self.add(move |world: &Arc<World>| {
+            let scene = world.root_scene();
+            match scene.find_node(entity) {
+                Some(node) => scene.despawn_recursive(node),
+                None => {
+                    drop(scene);
+                    world.destroy_entity(entity);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use weaver_util::lock::SharedLock;
+
+    use crate as weaver_ecs;
+    use weaver_ecs_macros::Component;
+
+    use super::*;
+
+    #[derive(Component)]
+    struct Position(f32);
+
+    #[derive(Component)]
+    struct Velocity;
+
+    #[test]
+    fn test_commands() {
+        let world = World::new();
+        let queue = SharedLock::new(CommandQueue::new());
+
+        let existing = world.spawn((Position(0.0), Velocity));
+
+        let mut commands = Commands::new(world.clone(), queue.write());
+        let spawned = commands.spawn(Position(1.0));
+        commands.remove::<Velocity>(existing);
+        commands.insert(existing, Position(2.0));
+        let node = commands.spawn_node(Velocity);
+        drop(commands);
+
+        // nothing happens until the queue is applied
+        assert!(world.is_alive(spawned));
+        assert!(!world.has_component::<Position>(spawned));
+        assert!(world.has_component::<Velocity>(existing));
+
+        queue.write().apply(&world);
+        assert!(queue.read().is_empty());
+
+        assert_eq!(world.get_component::<Position>(spawned).unwrap().0, 1.0);
+        assert_eq!(world.get_component::<Position>(existing).unwrap().0, 2.0);
+        assert!(!world.has_component::<Velocity>(existing));
+        assert!(world.root_scene().find_node(node).is_some());
+
+        let mut commands = Commands::new(world.clone(), queue.write());
+        commands.despawn(spawned).despawn_node(node);
+        drop(commands);
+        queue.write().apply(&world);
+
+        assert!(!world.is_alive(spawned));
+        assert!(!world.is_alive(node));
+        assert!(world.root_scene().find_node(node).is_none());
+
+        let parent = world.root_scene().spawn(Velocity);
+        let child = world
+            .root_scene()
+            .spawn_child(parent, Position(3.0))
+            .unwrap();
+        let mut commands = Commands::new(world.clone(), queue.write());
+        commands.despawn_recursive(parent.entity());
+        drop(commands);
+        queue.write().apply(&world);
+
+        assert!(!world.is_alive(parent.entity()));
+        assert!(!world.is_alive(child.entity()));
+    }
+}
@@ -1,6 +1,11 @@
 use std::{
+    alloc::Layout,
     any::TypeId,
     ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
 use weaver_util::{
@@ -9,24 +14,202 @@ use weaver_util::{
     TypeIdMap,
 };
 
-pub trait Component: DowncastSync {}
+use crate::change::{ComponentTicks, SystemTicks, Tick};
+
+pub trait Component: DowncastSync + ComponentInfoOf {}
 impl_downcast!(sync Component);
 
-pub trait Resource: DowncastSync {}
+// identifies a kind of component in storage; Rust types are keyed by their `TypeId`, while
+// components defined at runtime get an index handed out by the world they're registered with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ComponentId {
+    Static(TypeId),
+    Dynamic(u32),
+}
+
+impl ComponentId {
+    pub fn of<T: Component>() -> Self {
+        Self::Static(TypeId::of::<T>())
+    }
+
+    pub fn type_id(&self) -> Option<TypeId> {
+        match self {
+            Self::Static(type_id) => Some(*type_id),
+            Self::Dynamic(_) => None,
+        }
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        matches!(self, Self::Dynamic(_))
+    }
+}
+
+impl From<TypeId> for ComponentId {
+    fn from(type_id: TypeId) -> Self {
+        Self::Static(type_id)
+    }
+}
+
+// everything storage needs to keep a component type-erased
+#[derive(Clone, Copy)]
+pub struct ComponentInfo {
+    id: ComponentId,
+    // the Rust type actually stored, which differs from `id` for dynamic components
+    type_id: TypeId,
+    type_name: &'static str,
+    layout: Layout,
+    drop: Option<unsafe fn(*mut u8)>,
+    into_box: unsafe fn(*mut u8) -> Box<dyn Component>,
+}
+
+impl ComponentInfo {
+    pub fn of<T: Component>() -> Self {
+        unsafe fn drop_ptr<T>(ptr: *mut u8) {
+            ptr.cast::<T>().drop_in_place();
+        }
+
+        unsafe fn into_box<T: Component>(ptr: *mut u8) -> Box<dyn Component> {
+            Box::new(ptr.cast::<T>().read())
+        }
+
+        Self {
+            id: ComponentId::of::<T>(),
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            layout: Layout::new::<T>(),
+            drop: std::mem::needs_drop::<T>().then_some(drop_ptr::<T> as unsafe fn(*mut u8)),
+            into_box: into_box::<T>,
+        }
+    }
+
+    // a runtime-defined component's fields, stored inline as plain bytes; no Rust type is
+    // stored, so columns read these values out through their `DynamicComponentInfo` instead
+    pub(crate) fn dynamic(id: ComponentId, layout: Layout) -> Self {
+        unsafe fn into_box(_: *mut u8) -> Box<dyn Component> {
+            unreachable!("dynamic components are read out of their columns by layout")
+        }
+
+        Self {
+            id,
+            type_id: TypeId::of::<[u8]>(),
+            type_name: "dynamic component",
+            layout,
+            drop: None,
+            into_box,
+        }
+    }
+
+    // the same storage, keyed by another id
+    pub(crate) fn with_id(self, id: ComponentId) -> Self {
+        Self { id, ..self }
+    }
+
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn drop(&self) -> Option<unsafe fn(*mut u8)> {
+        self.drop
+    }
+
+    // SAFETY: `ptr` must point to a valid value of this component type, which is moved out
+    pub(crate) unsafe fn read_boxed(&self, ptr: *mut u8) -> Box<dyn Component> {
+        (self.into_box)(ptr)
+    }
+}
+
+// lets a `dyn Component` describe its concrete type
+pub trait ComponentInfoOf {
+    fn component_info(&self) -> ComponentInfo;
+}
+
+impl<T: Component> ComponentInfoOf for T {
+    fn component_info(&self) -> ComponentInfo {
+        ComponentInfo::of::<T>()
+    }
+}
+
+pub trait Resource: DowncastSync {
+    fn resource_type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
 impl_downcast!(sync Resource);
 
+// when a resource was inserted and last mutably dereferenced, shared between its `ResMut`s
+#[derive(Debug, Default)]
+pub struct ResourceTicks {
+    added: AtomicU64,
+    changed: AtomicU64,
+}
+
+impl ResourceTicks {
+    pub fn new(tick: Tick) -> Self {
+        Self {
+            added: AtomicU64::new(tick.get()),
+            changed: AtomicU64::new(tick.get()),
+        }
+    }
+
+    pub fn get(&self) -> ComponentTicks {
+        ComponentTicks {
+            added: Tick::new(self.added.load(Ordering::Acquire)),
+            changed: Tick::new(self.changed.load(Ordering::Acquire)),
+        }
+    }
+
+    pub fn set_changed(&self, tick: Tick) {
+        self.changed.store(tick.get(), Ordering::Release);
+    }
+}
+
 pub struct Res<T: Resource> {
     value: ArcRead<Box<dyn Resource>>,
+    ticks: ComponentTicks,
+    // the ticks of the system that fetched it, for `is_added` and `is_changed`
+    system_ticks: SystemTicks,
     _marker: std::marker::PhantomData<T>,
 }
 
 impl<T: Resource> Res<T> {
-    pub fn new(value: ArcRead<Box<dyn Resource>>) -> Self {
+    pub fn new(
+        value: ArcRead<Box<dyn Resource>>,
+        ticks: ComponentTicks,
+        system_ticks: SystemTicks,
+    ) -> Self {
         Self {
             value,
+            ticks,
+            system_ticks,
             _marker: std::marker::PhantomData,
         }
     }
+
+    pub fn ticks(&self) -> ComponentTicks {
+        self.ticks
+    }
+
+    pub fn is_added(&self) -> bool {
+        self.ticks
+            .is_added(self.system_ticks.last_run, self.system_ticks.this_run)
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.ticks
+            .is_changed(self.system_ticks.last_run, self.system_ticks.this_run)
+    }
 }
 
 impl<T> Deref for Res<T>
@@ -44,16 +227,28 @@ where
 
 pub struct ResMut<T: Resource> {
     value: ArcWrite<Box<dyn Resource>>,
+    ticks: Arc<ResourceTicks>,
+    change_tick: Tick,
     _marker: std::marker::PhantomData<T>,
 }
 
 impl<T: Resource> ResMut<T> {
-    pub fn new(value: ArcWrite<Box<dyn Resource>>) -> Self {
+    pub fn new(
+        value: ArcWrite<Box<dyn Resource>>,
+        ticks: Arc<ResourceTicks>,
+        change_tick: Tick,
+    ) -> Self {
         Self {
             value,
+            ticks,
+            change_tick,
             _marker: std::marker::PhantomData,
         }
     }
+
+    pub fn ticks(&self) -> ComponentTicks {
+        self.ticks.get()
+    }
 }
 
 impl<T> Deref for ResMut<T>
@@ -74,38 +269,58 @@ where
     T: Resource,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        self.ticks.set_changed(self.change_tick);
         (**self.value)
             .downcast_mut()
             .expect("Failed to downcast resource")
     }
 }
 
+struct ResourceEntry {
+    value: SharedLock<Box<dyn Resource>>,
+    ticks: Arc<ResourceTicks>,
+}
+
 #[derive(Default)]
 pub struct Resources {
-    resources: TypeIdMap<SharedLock<Box<dyn Resource>>>,
+    resources: TypeIdMap<ResourceEntry>,
 }
 
 impl Resources {
-    pub fn insert<T: Resource>(&mut self, resource: T) {
-        self.resources
-            .insert(TypeId::of::<T>(), SharedLock::new(Box::new(resource)));
+    pub fn insert<T: Resource>(&mut self, resource: T, tick: Tick) {
+        self.resources.insert(
+            TypeId::of::<T>(),
+            ResourceEntry {
+                value: SharedLock::new(Box::new(resource)),
+                ticks: Arc::new(ResourceTicks::new(tick)),
+            },
+        );
     }
 
-    pub fn get<T: Resource>(&self) -> Option<Res<T>> {
-        self.resources
-            .get(&TypeId::of::<T>())
-            .map(|resource| Res::new(resource.read()))
+    pub fn get<T: Resource>(&self, system_ticks: SystemTicks) -> Option<Res<T>> {
+        self.resources.get(&TypeId::of::<T>()).map(|resource| {
+            // locked first, so the ticks can't be changed by a writer in between
+            let value = resource.value.read();
+            Res::new(value, resource.ticks.get(), system_ticks)
+        })
     }
 
-    pub fn get_mut<T: Resource>(&self) -> Option<ResMut<T>> {
+    pub fn get_mut<T: Resource>(&self, change_tick: Tick) -> Option<ResMut<T>> {
+        self.resources.get(&TypeId::of::<T>()).map(|resource| {
+            ResMut::new(resource.value.write(), resource.ticks.clone(), change_tick)
+        })
+    }
+
+    pub fn get_ticks<T: Resource>(&self) -> Option<ComponentTicks> {
         self.resources
             .get(&TypeId::of::<T>())
-            .map(|resource| ResMut::new(resource.write()))
+            .map(|resource| resource.ticks.get())
     }
 
     pub fn remove<T: Resource>(&mut self) -> Option<T> {
         self.resources.remove(&TypeId::of::<T>()).map(|resource| {
             *resource
+                .value
                 .into_inner()
                 .unwrap()
                 .downcast()
@@ -117,4 +332,8 @@ impl Resources {
     pub fn contains<T: Resource>(&self) -> bool {
         self.resources.contains_key(&TypeId::of::<T>())
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SharedLock<Box<dyn Resource>>> + '_ {
+        self.resources.values().map(|resource| &resource.value)
+    }
 }
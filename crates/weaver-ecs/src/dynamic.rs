@@ -0,0 +1,668 @@
+use std::{
+    alloc::{self, Layout},
+    any::TypeId,
+    ptr::NonNull,
+    sync::Arc,
+};
+
+use crate::{
+    change::Tick,
+    component::{Component, ComponentId},
+    entity::Entity,
+    query::QueryAccess,
+    storage::{Archetype, SharedColumnRead, SharedColumnWrite},
+    world::World,
+};
+
+// one named field of a dynamic component, at a fixed offset into its data
+#[derive(Clone, Debug)]
+pub struct DynamicField {
+    name: String,
+    type_id: TypeId,
+    type_name: &'static str,
+    layout: Layout,
+    offset: usize,
+    // writes the field's default value
+    init: unsafe fn(*mut u8),
+}
+
+impl DynamicField {
+    // fields are plain data, so dynamic components never need to run drop code
+    pub fn new<T: Copy + Default + Send + Sync + 'static>(name: impl Into<String>) -> Self {
+        unsafe fn init<T: Default>(ptr: *mut u8) {
+            ptr.cast::<T>().write(T::default());
+        }
+
+        Self {
+            name: name.into(),
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            layout: Layout::new::<T>(),
+            offset: 0,
+            init: init::<T>,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl PartialEq for DynamicField {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.type_id == other.type_id && self.offset == other.offset
+    }
+}
+
+impl Eq for DynamicField {}
+
+// describes a component type that only exists at runtime, e.g. one declared by a script
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynamicComponentInfo {
+    name: String,
+    layout: Layout,
+    fields: Vec<DynamicField>,
+}
+
+impl DynamicComponentInfo {
+    // opaque, zero-initialized bytes, for users that do their own layout
+    pub fn from_layout(name: impl Into<String>, layout: Layout) -> Self {
+        Self {
+            name: name.into(),
+            layout: layout.pad_to_align(),
+            fields: Vec::new(),
+        }
+    }
+
+    // fields are laid out in order, like a `#[repr(C)]` struct
+    pub fn from_fields(
+        name: impl Into<String>,
+        fields: impl IntoIterator<Item = DynamicField>,
+    ) -> Self {
+        let name = name.into();
+        let mut layout = Layout::new::<()>();
+        let mut laid_out: Vec<DynamicField> = Vec::new();
+        for mut field in fields {
+            assert!(
+                laid_out.iter().all(|other| other.name != field.name),
+                "{} has more than one field named {}",
+                name,
+                field.name
+            );
+            let (extended, offset) = layout
+                .extend(field.layout)
+                .expect("dynamic component is too large");
+            layout = extended;
+            field.offset = offset;
+            laid_out.push(field);
+        }
+
+        Self {
+            name,
+            layout: layout.pad_to_align(),
+            fields: laid_out,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn fields(&self) -> &[DynamicField] {
+        &self.fields
+    }
+
+    pub fn field(&self, name: &str) -> Option<&DynamicField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+// a dynamic component's fields, laid out by its info; the bytes are either owned by a
+// `DynamicComponent` or borrowed from an archetype column by `DynamicRef` and `DynamicMut`
+pub struct DynamicValue {
+    id: ComponentId,
+    info: Arc<DynamicComponentInfo>,
+    data: NonNull<u8>,
+}
+
+// SAFETY: the data is either plain bytes or fields that are Send + Sync
+unsafe impl Send for DynamicValue {}
+unsafe impl Sync for DynamicValue {}
+
+impl DynamicValue {
+    // SAFETY: `data` must point to a value laid out by `info`, valid for as long as this is
+    pub(crate) unsafe fn new(
+        id: ComponentId,
+        info: Arc<DynamicComponentInfo>,
+        data: NonNull<u8>,
+    ) -> Self {
+        Self { id, info, data }
+    }
+
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    pub fn info(&self) -> &Arc<DynamicComponentInfo> {
+        &self.info
+    }
+
+    pub fn name(&self) -> &str {
+        self.info.name()
+    }
+
+    // only components without fields expose their bytes, since fields may hold padding or
+    // values that not every bit pattern is valid for
+    pub fn bytes(&self) -> Option<&[u8]> {
+        // SAFETY: the bytes are initialized, and nothing but bytes is written to them
+        self.info.fields.is_empty().then(|| unsafe {
+            std::slice::from_raw_parts(self.data.as_ptr(), self.info.layout.size())
+        })
+    }
+
+    // `None` if there's no such field, or it isn't a T
+    pub fn field<T: 'static>(&self, name: &str) -> Option<&T> {
+        // SAFETY: the field holds a valid T at its offset
+        Some(unsafe { &*self.field_ptr::<T>(name)? })
+    }
+
+    fn bytes_ptr(&self) -> Option<*mut [u8]> {
+        self.info.fields.is_empty().then(|| {
+            std::ptr::slice_from_raw_parts_mut(self.data.as_ptr(), self.info.layout.size())
+        })
+    }
+
+    fn field_ptr<T: 'static>(&self, name: &str) -> Option<*mut T> {
+        let field = self
+            .info
+            .field(name)
+            .filter(|field| field.type_id == TypeId::of::<T>())?;
+        // SAFETY: the field lies within the data
+        Some(unsafe { self.data.as_ptr().add(field.offset).cast::<T>() })
+    }
+}
+
+impl std::fmt::Debug for DynamicValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DynamicValue")
+            .field("id", &self.id)
+            .field("name", &self.info.name)
+            .finish_non_exhaustive()
+    }
+}
+
+// an owned value of a dynamic component, for building one up before it's inserted or after
+// it's removed; in storage, its bytes are copied into the column registered for its id
+pub struct DynamicComponent {
+    value: DynamicValue,
+}
+
+impl Component for DynamicComponent {}
+
+impl DynamicComponent {
+    // every field starts at its default value; see `World::new_dynamic`
+    pub(crate) fn new(id: ComponentId, info: Arc<DynamicComponentInfo>) -> Self {
+        let data = Self::alloc(info.layout);
+        for field in &info.fields {
+            // SAFETY: the field lies within the allocation and is suitably aligned
+            unsafe { (field.init)(data.as_ptr().add(field.offset)) };
+        }
+
+        Self {
+            value: DynamicValue { id, info, data },
+        }
+    }
+
+    // SAFETY: `ptr` must point to a value laid out by `info`
+    pub(crate) unsafe fn copy_from(
+        id: ComponentId,
+        info: Arc<DynamicComponentInfo>,
+        ptr: *const u8,
+    ) -> Self {
+        let data = Self::alloc(info.layout);
+        std::ptr::copy_nonoverlapping(ptr, data.as_ptr(), info.layout.size());
+        Self {
+            value: DynamicValue { id, info, data },
+        }
+    }
+
+    fn alloc(layout: Layout) -> NonNull<u8> {
+        if layout.size() == 0 {
+            // SAFETY: alignments are never zero
+            return unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+        }
+
+        // SAFETY: the layout has a non-zero size
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout))
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.value.data.as_ptr()
+    }
+
+    pub fn bytes_mut(&mut self) -> Option<&mut [u8]> {
+        // SAFETY: see `DynamicValue::bytes`, and the value owns its bytes
+        Some(unsafe { &mut *self.value.bytes_ptr()? })
+    }
+
+    pub fn field_mut<T: 'static>(&mut self, name: &str) -> Option<&mut T> {
+        // SAFETY: see `DynamicValue::field`, and the value owns its bytes
+        Some(unsafe { &mut *self.value.field_ptr::<T>(name)? })
+    }
+}
+
+impl std::ops::Deref for DynamicComponent {
+    type Target = DynamicValue;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl Clone for DynamicComponent {
+    fn clone(&self) -> Self {
+        // SAFETY: the bytes are laid out by the value's own info
+        unsafe { Self::copy_from(self.id, self.info.clone(), self.as_ptr()) }
+    }
+}
+
+impl Drop for DynamicComponent {
+    fn drop(&mut self) {
+        if self.info.layout.size() != 0 {
+            // SAFETY: allocated in `alloc` with this layout
+            unsafe { alloc::dealloc(self.value.data.as_ptr(), self.info.layout) };
+        }
+    }
+}
+
+impl std::fmt::Debug for DynamicComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DynamicComponent")
+            .field("id", &self.id)
+            .field("name", &self.info.name)
+            .finish_non_exhaustive()
+    }
+}
+
+// a dynamic component read in place, from the row its entity has in the column
+pub struct DynamicRef {
+    entity: Entity,
+    value: DynamicValue,
+    _column: SharedColumnRead,
+}
+
+impl DynamicRef {
+    pub fn new(entity: Entity, row: usize, column: SharedColumnRead) -> Self {
+        let value = column
+            .dynamic_value(row)
+            .expect("not a dynamic component column, or row out of bounds");
+        Self {
+            entity,
+            value,
+            _column: column,
+        }
+    }
+
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+}
+
+impl std::ops::Deref for DynamicRef {
+    type Target = DynamicValue;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+// a dynamic component written in place; like `Mut`, several may share one write lock as long as
+// each points at a different row, and writing marks the component as changed
+pub struct DynamicMut {
+    entity: Entity,
+    row: usize,
+    value: DynamicValue,
+    column: SharedColumnWrite,
+    change_tick: Tick,
+}
+
+impl DynamicMut {
+    pub fn new(entity: Entity, row: usize, column: SharedColumnWrite, change_tick: Tick) -> Self {
+        let value = column
+            .dynamic_value(row)
+            .expect("not a dynamic component column, or row out of bounds");
+        assert!(
+            column.borrow_row(row),
+            "{} of {:?} is already mutably borrowed",
+            value.name(),
+            entity
+        );
+        Self {
+            entity,
+            row,
+            value,
+            column,
+            change_tick,
+        }
+    }
+
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    pub fn bytes_mut(&mut self) -> Option<&mut [u8]> {
+        let bytes = self.value.bytes_ptr()?;
+        self.set_changed();
+        // SAFETY: see `DynamicValue::bytes`; no other `DynamicMut` points at this row, as
+        // checked in `new`
+        Some(unsafe { &mut *bytes })
+    }
+
+    pub fn field_mut<T: 'static>(&mut self, name: &str) -> Option<&mut T> {
+        let field = self.value.field_ptr::<T>(name)?;
+        self.set_changed();
+        // SAFETY: see `bytes_mut` and `DynamicValue::field`
+        Some(unsafe { &mut *field })
+    }
+
+    fn set_changed(&self) {
+        // SAFETY: the row was checked in `new`, and is borrowed by this `DynamicMut` alone
+        unsafe { (*self.column.ticks_ptr(self.row)).set_changed(self.change_tick) };
+    }
+}
+
+impl std::ops::Deref for DynamicMut {
+    type Target = DynamicValue;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl Drop for DynamicMut {
+    fn drop(&mut self) {
+        self.column.release_row(self.row);
+    }
+}
+
+pub enum DynamicFetch {
+    Ref(DynamicRef),
+    Mut(DynamicMut),
+}
+
+impl DynamicFetch {
+    // `None` if the component was only fetched for reading
+    pub fn get_mut(&mut self) -> Option<&mut DynamicMut> {
+        match self {
+            Self::Ref(_) => None,
+            Self::Mut(component) => Some(component),
+        }
+    }
+}
+
+impl std::ops::Deref for DynamicFetch {
+    type Target = DynamicValue;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Ref(component) => component,
+            Self::Mut(component) => component,
+        }
+    }
+}
+
+enum DynamicColumn {
+    Read(SharedColumnRead),
+    Write(SharedColumnWrite),
+}
+
+// a query built at runtime out of component ids; any component can be filtered on, but only
+// dynamic ones can be fetched, since nothing else knows their types
+#[derive(Default)]
+pub struct DynamicQuery {
+    fetch: Vec<(ComponentId, QueryAccess)>,
+    with: Vec<ComponentId>,
+    without: Vec<ComponentId>,
+}
+
+impl DynamicQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(mut self, id: ComponentId) -> Self {
+        self.push_fetch(id, QueryAccess::ReadOnly);
+        self
+    }
+
+    pub fn write(mut self, id: ComponentId) -> Self {
+        self.push_fetch(id, QueryAccess::ReadWrite);
+        self
+    }
+
+    pub fn with(mut self, id: ComponentId) -> Self {
+        self.with.push(id);
+        self
+    }
+
+    pub fn without(mut self, id: ComponentId) -> Self {
+        self.without.push(id);
+        self
+    }
+
+    fn push_fetch(&mut self, id: ComponentId, access: QueryAccess) {
+        assert!(
+            id.is_dynamic(),
+            "Only dynamic components can be fetched by id"
+        );
+        assert!(
+            self.fetch.iter().all(|(other, _)| *other != id),
+            "{:?} is fetched more than once",
+            id
+        );
+        self.fetch.push((id, access));
+    }
+
+    pub fn matches(&self, archetype: &Archetype) -> bool {
+        self.fetch
+            .iter()
+            .all(|(id, _)| archetype.contains_component_by_id(*id))
+            && archetype.contains_all(&self.with)
+            && !archetype.contains_any(&self.without)
+    }
+
+    pub fn entities(&self, world: &World) -> Vec<Entity> {
+        let storage = world.storage().read();
+        storage
+            .archetype_iter()
+            .filter(|archetype| self.matches(archetype))
+            .flat_map(|archetype| archetype.entity_iter())
+            .collect()
+    }
+
+    // the fetched components come in the order they were added to the query
+    pub fn iter(&self, world: &World) -> impl Iterator<Item = (Entity, Vec<DynamicFetch>)> {
+        let change_tick = world.change_tick();
+        let storage = world.storage().read();
+
+        let mut locked = Vec::new();
+        for archetype in storage.archetype_iter() {
+            if !self.matches(archetype) {
+                continue;
+            }
+
+            let columns = self
+                .fetch
+                .iter()
+                .map(|(id, access)| match access {
+                    QueryAccess::ReadOnly => {
+                        DynamicColumn::Read(archetype.lock_column_by_id(*id).unwrap())
+                    }
+                    QueryAccess::ReadWrite => {
+                        DynamicColumn::Write(archetype.lock_column_by_id_mut(*id).unwrap())
+                    }
+                })
+                .collect::<Vec<_>>();
+            locked.push((columns, archetype.entities().to_vec()));
+        }
+        drop(storage);
+
+        locked.into_iter().flat_map(move |(columns, entities)| {
+            entities.into_iter().enumerate().map(move |(row, entity)| {
+                let fetched = columns
+                    .iter()
+                    .map(|column| match column {
+                        DynamicColumn::Read(column) => {
+                            DynamicFetch::Ref(DynamicRef::new(entity, row, column.clone()))
+                        }
+                        DynamicColumn::Write(column) => DynamicFetch::Mut(DynamicMut::new(
+                            entity,
+                            row,
+                            column.clone(),
+                            change_tick,
+                        )),
+                    })
+                    .collect();
+                (entity, fetched)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use weaver_ecs_macros::Component;
+
+    use super::*;
+    use crate as weaver_ecs;
+
+    #[derive(Component)]
+    struct Enemy;
+
+    fn player_info() -> DynamicComponentInfo {
+        DynamicComponentInfo::from_fields(
+            "Player",
+            [
+                DynamicField::new::<f32>("speed"),
+                DynamicField::new::<i64>("health"),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_dynamic_components() {
+        let world = World::new();
+        let player = world.register_dynamic_component(player_info()).unwrap();
+        let info = world.dynamic_component_info(player).unwrap();
+        assert_eq!(info.field("health").unwrap().offset(), 8);
+        assert_eq!(info.layout().size(), 16);
+
+        // registering the same layout again is fine, a different one isn't
+        assert_eq!(
+            world.register_dynamic_component(player_info()).unwrap(),
+            player
+        );
+        assert!(world
+            .register_dynamic_component(DynamicComponentInfo::from_fields(
+                "Player",
+                [DynamicField::new::<f32>("speed")],
+            ))
+            .is_err());
+        let marker = world
+            .register_dynamic_component(DynamicComponentInfo::from_layout(
+                "Marker",
+                Layout::new::<()>(),
+            ))
+            .unwrap();
+        assert_ne!(marker, player);
+
+        let mut component = world.new_dynamic(player).unwrap();
+        assert_eq!(component.field::<i64>("health"), Some(&0));
+        assert_eq!(component.field::<f32>("health"), None);
+        *component.field_mut::<f32>("speed").unwrap() = 1.0;
+        *component.field_mut::<i64>("health").unwrap() = 100;
+
+        let first = world.spawn(Enemy);
+        let second = world.create_entity();
+        world.insert_dynamic(first, component.clone()).unwrap();
+        world.insert_dynamic(second, component).unwrap();
+        world
+            .insert_dynamic(first, world.new_dynamic(marker).unwrap())
+            .unwrap();
+
+        let fetched = world.get_dynamic(first, player).unwrap();
+        assert_eq!(fetched.field::<i64>("health"), Some(&100));
+        drop(fetched);
+        // the fields sit in the column itself rather than behind a pointer per value
+        let storage = world.storage().read();
+        let archetype = storage.get_archetype(second).unwrap();
+        let column = archetype.get_column_by_id(player).unwrap();
+        assert_eq!(column.info().layout(), info.layout());
+        drop(column);
+        drop(storage);
+        *world
+            .get_dynamic_mut(second, player)
+            .unwrap()
+            .field_mut::<f32>("speed")
+            .unwrap() = 2.0;
+        assert!(world
+            .get_dynamic(first, ComponentId::of::<Enemy>())
+            .is_none());
+
+        let query = DynamicQuery::new().write(player).without(marker);
+        assert_eq!(query.entities(&world), vec![second]);
+        for (_, mut components) in query.iter(&world) {
+            *components[0]
+                .get_mut()
+                .unwrap()
+                .field_mut::<i64>("health")
+                .unwrap() -= 10;
+        }
+        assert_eq!(
+            world
+                .get_dynamic(second, player)
+                .unwrap()
+                .field::<i64>("health"),
+            Some(&90)
+        );
+
+        // static components can be filtered on by id
+        let query = DynamicQuery::new()
+            .read(player)
+            .with(ComponentId::of::<Enemy>());
+        assert_eq!(query.entities(&world), vec![first]);
+
+        let removed = world.remove_dynamic(first, player).unwrap();
+        assert_eq!(removed.field::<f32>("speed"), Some(&1.0));
+        assert_eq!(
+            world
+                .get_dynamic(second, player)
+                .unwrap()
+                .field::<f32>("speed"),
+            Some(&2.0)
+        );
+        assert!(!world.has_component_by_id(first, player));
+        assert!(world.has_component_by_id(first, marker));
+    }
+}
@@ -37,3 +37,133 @@ impl Entity {
         Self::from_u64(value as u64)
     }
 }
+
+#[derive(Clone, Copy, Default)]
+struct EntityMeta {
+    generation: u32,
+    alive: bool,
+}
+
+// hands out entity ids, recycling freed ones with a bumped generation so stale handles can be detected
+#[derive(Default)]
+pub struct Entities {
+    meta: Vec<EntityMeta>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alloc(&mut self) -> Entity {
+        self.len += 1;
+
+        if let Some(id) = self.free.pop() {
+            let meta = &mut self.meta[id as usize];
+            meta.alive = true;
+            Entity::new(id, meta.generation)
+        } else {
+            let id = u32::try_from(self.meta.len()).expect("too many entities");
+            self.meta.push(EntityMeta {
+                generation: 0,
+                alive: true,
+            });
+            Entity::new(id, 0)
+        }
+    }
+
+    pub fn reserve(&mut self, count: usize) -> Vec<Entity> {
+        self.meta.reserve(count.saturating_sub(self.free.len()));
+        (0..count).map(|_| self.alloc()).collect()
+    }
+
+    // returns false if the entity was already freed or its handle is stale
+    pub fn free(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        let meta = &mut self.meta[entity.id() as usize];
+        meta.alive = false;
+        meta.generation = meta.generation.wrapping_add(1);
+        self.free.push(entity.id());
+        self.len -= 1;
+
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.meta
+            .get(entity.id() as usize)
+            .is_some_and(|meta| meta.alive && meta.generation == entity.generation())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.meta
+            .iter()
+            .enumerate()
+            .filter(|(_, meta)| meta.alive)
+            .map(|(id, meta)| Entity::new(id as u32, meta.generation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recycle() {
+        let mut entities = Entities::new();
+
+        let entity1 = entities.alloc();
+        let entity2 = entities.alloc();
+        assert!(entities.is_alive(entity1));
+        assert!(entities.is_alive(entity2));
+
+        assert!(entities.free(entity1));
+        assert!(!entities.is_alive(entity1));
+
+        // freeing twice is rejected, so the id is only handed out once
+        assert!(!entities.free(entity1));
+
+        let entity3 = entities.alloc();
+        assert_eq!(entity3.id(), entity1.id());
+        assert_eq!(entity3.generation(), entity1.generation() + 1);
+        assert!(!entities.is_alive(entity1));
+        assert!(entities.is_alive(entity3));
+
+        let entity4 = entities.alloc();
+        assert_ne!(entity4.id(), entity3.id());
+        assert_eq!(entities.len(), 3);
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut entities = Entities::new();
+
+        let first = entities.alloc();
+        entities.free(first);
+
+        let reserved = entities.reserve(3);
+        assert_eq!(reserved.len(), 3);
+        assert!(reserved.iter().all(|entity| entities.is_alive(*entity)));
+        assert_eq!(
+            reserved
+                .iter()
+                .filter(|entity| entity.id() == first.id())
+                .count(),
+            1
+        );
+        assert_eq!(entities.iter().count(), 3);
+    }
+}
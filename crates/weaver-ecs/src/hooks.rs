@@ -0,0 +1,75 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{component::ComponentId, entity::Entity, world::World};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ComponentEvent {
+    // the entity didn't have the component before
+    Insert,
+    // the component was overwritten with a new value
+    Replace,
+    // the component was removed, or its entity destroyed; the value is already gone
+    Remove,
+}
+
+// recorded by `Storage` for components that have hooks, and run by `World` once the
+// storage lock is released
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LifecycleEvent {
+    pub event: ComponentEvent,
+    pub id: ComponentId,
+    pub entity: Entity,
+}
+
+pub type ComponentHook = Arc<dyn Fn(&World, Entity) + Send + Sync>;
+
+#[derive(Default)]
+pub struct ComponentHooks {
+    hooks: HashMap<(ComponentId, ComponentEvent), Vec<ComponentHook>>,
+    // only called for their own entity, and dropped along with it
+    observers: HashMap<Entity, Vec<(ComponentId, ComponentEvent, ComponentHook)>>,
+}
+
+impl ComponentHooks {
+    pub fn add_hook(&mut self, id: ComponentId, event: ComponentEvent, hook: ComponentHook) {
+        self.hooks.entry((id, event)).or_default().push(hook);
+    }
+
+    pub fn add_observer(
+        &mut self,
+        entity: Entity,
+        id: ComponentId,
+        event: ComponentEvent,
+        observer: ComponentHook,
+    ) {
+        self.observers
+            .entry(entity)
+            .or_default()
+            .push((id, event, observer));
+    }
+
+    pub fn remove_observers(&mut self, entity: Entity) {
+        self.observers.remove(&entity);
+    }
+
+    // hooks for the component come first, then the entity's observers, each in the order
+    // they were added
+    pub fn hooks_for(&self, event: &LifecycleEvent) -> Vec<ComponentHook> {
+        let hooks = self
+            .hooks
+            .get(&(event.id, event.event))
+            .into_iter()
+            .flatten()
+            .cloned();
+
+        let observers = self
+            .observers
+            .get(&event.entity)
+            .into_iter()
+            .flatten()
+            .filter(|(id, kind, _)| *id == event.id && *kind == event.event)
+            .map(|(_, _, observer)| observer.clone());
+
+        hooks.chain(observers).collect()
+    }
+}
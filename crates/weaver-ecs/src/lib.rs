@@ -1,25 +1,34 @@
 #![allow(clippy::multiple_bound_locations)] // downcast-rs thing
 
+mod blob;
 pub mod bundle;
 pub mod change;
+pub mod commands;
 pub mod component;
+pub mod dynamic;
 pub mod entity;
+pub mod hooks;
 pub mod node;
 pub mod query;
 pub mod relationship;
 pub mod scene;
+pub mod stats;
 pub mod storage;
 pub mod world;
 
 pub mod prelude {
     pub use crate::bundle::*;
     pub use crate::change::*;
+    pub use crate::commands::*;
     pub use crate::component::*;
+    pub use crate::dynamic::*;
     pub use crate::entity::*;
+    pub use crate::hooks::*;
     pub use crate::node::*;
     pub use crate::query::*;
     pub use crate::relationship::*;
     pub use crate::scene::*;
+    pub use crate::stats::*;
     pub use crate::storage::*;
     pub use crate::world::*;
     pub use weaver_ecs_macros::*;
@@ -1,11 +1,13 @@
 use std::{any::TypeId, marker::PhantomData, sync::Arc};
 
-use crate::prelude::Archetype;
+use weaver_util::prelude::{anyhow, bail, Result};
+
+use crate::prelude::{Archetype, ArchetypeId, Storage, SystemTicks, Tick};
 
 use super::{
     component::Component,
     entity::Entity,
-    storage::{Mut, Ref},
+    storage::{Mut, Ref, SharedColumnRead, SharedColumnWrite},
     world::World,
 };
 
@@ -14,90 +16,171 @@ pub enum QueryAccess {
     ReadWrite,
 }
 
-pub trait QueryFetchParam {
-    type Item: Component;
+pub trait QueryFetch {
     type Fetch<'a>;
-    fn type_id() -> TypeId;
-    fn access() -> QueryAccess;
-    fn fetch<'a>(world: &World, entity: Entity) -> Option<Self::Fetch<'a>>;
+    // the columns of a single archetype, locked once for every row fetched from it
+    type Columns;
+
+    fn access() -> Vec<(TypeId, QueryAccess)>;
+    fn test_archetype(archetype: &Archetype) -> bool;
+
+    // only called for archetypes that passed `test_archetype`
+    fn lock_columns(archetype: &Archetype) -> Option<Self::Columns>;
+
+    // `row` must be in bounds of the locked columns and fetched at most once per lock
+    fn fetch<'a>(
+        columns: &Self::Columns,
+        entity: Entity,
+        row: usize,
+        change_tick: Tick,
+    ) -> Self::Fetch<'a>;
 }
 
-impl<T: Component> QueryFetchParam for &T {
-    type Item = T;
+impl<T: Component> QueryFetch for &T {
     type Fetch<'a> = Ref<T>;
+    type Columns = SharedColumnRead;
 
-    fn type_id() -> TypeId {
-        TypeId::of::<T>()
+    fn access() -> Vec<(TypeId, QueryAccess)> {
+        vec![(TypeId::of::<T>(), QueryAccess::ReadOnly)]
     }
 
-    fn access() -> QueryAccess {
-        QueryAccess::ReadOnly
+    fn test_archetype(archetype: &Archetype) -> bool {
+        archetype.contains_component::<T>()
+    }
+
+    fn lock_columns(archetype: &Archetype) -> Option<Self::Columns> {
+        archetype.lock_column::<T>()
     }
 
-    fn fetch<'a>(world: &World, entity: Entity) -> Option<Self::Fetch<'a>> {
-        world.get_component::<T>(entity)
+    fn fetch<'a>(columns: &Self::Columns, entity: Entity, row: usize, _: Tick) -> Self::Fetch<'a> {
+        Ref::new(entity, row, columns.clone())
     }
 }
 
-impl<T: Component> QueryFetchParam for &mut T {
-    type Item = T;
+impl<T: Component> QueryFetch for &mut T {
     type Fetch<'a> = Mut<T>;
+    type Columns = SharedColumnWrite;
 
-    fn type_id() -> TypeId {
-        TypeId::of::<T>()
+    fn access() -> Vec<(TypeId, QueryAccess)> {
+        vec![(TypeId::of::<T>(), QueryAccess::ReadWrite)]
+    }
+
+    fn test_archetype(archetype: &Archetype) -> bool {
+        archetype.contains_component::<T>()
     }
 
-    fn access() -> QueryAccess {
-        QueryAccess::ReadWrite
+    fn lock_columns(archetype: &Archetype) -> Option<Self::Columns> {
+        archetype.lock_column_mut::<T>()
     }
 
-    fn fetch<'a>(world: &World, entity: Entity) -> Option<Self::Fetch<'a>> {
-        world.get_component_mut::<T>(entity)
+    fn fetch<'a>(
+        columns: &Self::Columns,
+        entity: Entity,
+        row: usize,
+        change_tick: Tick,
+    ) -> Self::Fetch<'a> {
+        Mut::new(entity, row, columns.clone(), change_tick)
     }
 }
 
-pub trait QueryFetch {
-    type Fetch<'a>;
-    fn access() -> &'static [(TypeId, QueryAccess)];
-    fn fetch<'a>(world: &World, entity: Entity) -> Option<Self::Fetch<'a>>;
-    fn test_archetype(archetype: &Archetype) -> bool;
+// matches every entity, yielding `None` where the inner fetch doesn't match
+impl<Q: QueryFetch> QueryFetch for Option<Q> {
+    type Fetch<'a> = Option<Q::Fetch<'a>>;
+    type Columns = Option<Q::Columns>;
+
+    fn access() -> Vec<(TypeId, QueryAccess)> {
+        Q::access()
+    }
+
+    fn test_archetype(_: &Archetype) -> bool {
+        true
+    }
+
+    fn lock_columns(archetype: &Archetype) -> Option<Self::Columns> {
+        if Q::test_archetype(archetype) {
+            Some(Q::lock_columns(archetype))
+        } else {
+            Some(None)
+        }
+    }
+
+    fn fetch<'a>(
+        columns: &Self::Columns,
+        entity: Entity,
+        row: usize,
+        change_tick: Tick,
+    ) -> Self::Fetch<'a> {
+        columns
+            .as_ref()
+            .map(|columns| Q::fetch(columns, entity, row, change_tick))
+    }
 }
 
-impl<T> QueryFetch for T
-where
-    T: QueryFetchParam,
-{
-    type Fetch<'a> = T::Fetch<'a>;
+impl QueryFetch for Entity {
+    type Fetch<'a> = Entity;
+    type Columns = ();
+
+    fn access() -> Vec<(TypeId, QueryAccess)> {
+        Vec::new()
+    }
 
-    fn access() -> &'static [(TypeId, QueryAccess)] {
-        static ACCESS: std::sync::OnceLock<Vec<(TypeId, QueryAccess)>> = std::sync::OnceLock::new();
-        ACCESS.get_or_init(|| vec![(T::type_id(), T::access())])
+    fn test_archetype(_: &Archetype) -> bool {
+        true
     }
 
-    fn fetch<'a>(world: &World, entity: Entity) -> Option<Self::Fetch<'a>> {
-        <T as QueryFetchParam>::fetch(world, entity)
+    fn lock_columns(_: &Archetype) -> Option<Self::Columns> {
+        Some(())
     }
 
-    fn test_archetype(archetype: &Archetype) -> bool {
-        archetype.contains_component_by_type_id(TypeId::of::<T::Item>())
+    fn fetch<'a>(_: &Self::Columns, entity: Entity, _: usize, _: Tick) -> Self::Fetch<'a> {
+        entity
+    }
+}
+
+// whether the entity has a `T`, without borrowing it
+pub struct Has<T: Component>(PhantomData<T>);
+
+impl<T: Component> QueryFetch for Has<T> {
+    type Fetch<'a> = bool;
+    type Columns = bool;
+
+    fn access() -> Vec<(TypeId, QueryAccess)> {
+        Vec::new()
+    }
+
+    fn test_archetype(_: &Archetype) -> bool {
+        true
+    }
+
+    fn lock_columns(archetype: &Archetype) -> Option<Self::Columns> {
+        Some(archetype.contains_component::<T>())
+    }
+
+    fn fetch<'a>(columns: &Self::Columns, _: Entity, _: usize, _: Tick) -> Self::Fetch<'a> {
+        *columns
     }
 }
 
+// fetches that never hand out mutable access, so the same entity can be fetched twice at once
+pub trait ReadOnlyQueryFetch: QueryFetch {}
+
+impl<T: Component> ReadOnlyQueryFetch for &T {}
+impl<Q: ReadOnlyQueryFetch> ReadOnlyQueryFetch for Option<Q> {}
+impl ReadOnlyQueryFetch for Entity {}
+impl<T: Component> ReadOnlyQueryFetch for Has<T> {}
+
 macro_rules! impl_query_fetch {
     ($($param:ident),*) => {
-        impl<$($param: QueryFetchParam),*> QueryFetch for ($($param,)*) {
+        impl<$($param: QueryFetch),*> QueryFetch for ($($param,)*) {
             type Fetch<'a> = ($($param::Fetch<'a>,)*);
+            type Columns = ($($param::Columns,)*);
 
-            fn access() -> &'static [(TypeId, QueryAccess)] {
-                static ACCESS: std::sync::OnceLock<Vec<(TypeId, QueryAccess)>> = std::sync::OnceLock::new();
-                ACCESS.get_or_init(|| vec![$(($param::type_id(), $param::access()),)*])
-            }
-
-            #[allow(non_snake_case)]
-            fn fetch<'a>(world: &World, entity: Entity) -> Option<Self::Fetch<'a>> {
-                let ($($param,)*) = ($($param::fetch(world, entity)?,)*);
-                Some(($($param,)*))
-
+            fn access() -> Vec<(TypeId, QueryAccess)> {
+                let mut access = Vec::new();
+                $(
+                    access.extend($param::access());
+                )*
+                access
             }
 
             fn test_archetype(archetype: &Archetype) -> bool {
@@ -106,7 +189,24 @@ macro_rules! impl_query_fetch {
                 )*
                 true
             }
+
+            fn lock_columns(archetype: &Archetype) -> Option<Self::Columns> {
+                Some(($($param::lock_columns(archetype)?,)*))
+            }
+
+            #[allow(non_snake_case)]
+            fn fetch<'a>(
+                columns: &Self::Columns,
+                entity: Entity,
+                row: usize,
+                change_tick: Tick,
+            ) -> Self::Fetch<'a> {
+                let ($($param,)*) = columns;
+                ($($param::fetch($param, entity, row, change_tick),)*)
+            }
         }
+
+        impl<$($param: ReadOnlyQueryFetch),*> ReadOnlyQueryFetch for ($($param,)*) {}
     };
 }
 
@@ -120,28 +220,89 @@ impl_query_fetch!(A, B, C, D, E, F, G);
 impl_query_fetch!(A, B, C, D, E, F, G, H);
 
 pub trait QueryFilter {
+    // whatever the row tests of a single archetype need, gathered once per archetype
+    type State;
+
     fn test_archetype(archetype: &Archetype) -> bool;
+
+    // only called for archetypes that passed `test_archetype`
+    fn prepare(archetype: &Archetype) -> Self::State;
+
+    fn test_row(state: &Self::State, row: usize, ticks: SystemTicks) -> bool;
 }
 
 impl QueryFilter for () {
+    type State = ();
+
     fn test_archetype(_: &Archetype) -> bool {
         true
     }
+
+    fn prepare(_: &Archetype) -> Self::State {}
+
+    fn test_row(_: &Self::State, _: usize, _: SystemTicks) -> bool {
+        true
+    }
 }
 
 macro_rules! impl_query_filter {
     ($($param:ident),*) => {
-        impl<$($param: QueryFetchParam),*> QueryFilter for ($($param,)*) {
+        impl<$($param: QueryFilter),*> QueryFilter for ($($param,)*) {
+            type State = ($($param::State,)*);
+
             fn test_archetype(archetype: &Archetype) -> bool {
                 $(
                     $param::test_archetype(archetype) &&
                 )*
                 true
             }
+
+            fn prepare(archetype: &Archetype) -> Self::State {
+                ($($param::prepare(archetype),)*)
+            }
+
+            #[allow(non_snake_case)]
+            fn test_row(state: &Self::State, row: usize, ticks: SystemTicks) -> bool {
+                let ($($param,)*) = state;
+                $(
+                    $param::test_row($param, row, ticks) &&
+                )*
+                true
+            }
+        }
+
+        impl<$($param: QueryFilter),*> QueryFilter for Or<($($param,)*)> {
+            // `None` for the filters that rejected the archetype
+            type State = ($(Option<$param::State>,)*);
+
+            fn test_archetype(archetype: &Archetype) -> bool {
+                $(
+                    $param::test_archetype(archetype) ||
+                )*
+                false
+            }
+
+            fn prepare(archetype: &Archetype) -> Self::State {
+                ($($param::test_archetype(archetype).then(|| $param::prepare(archetype)),)*)
+            }
+
+            #[allow(non_snake_case)]
+            fn test_row(state: &Self::State, row: usize, ticks: SystemTicks) -> bool {
+                let ($($param,)*) = state;
+                $(
+                    $param
+                        .as_ref()
+                        .is_some_and(|state| $param::test_row(state, row, ticks)) ||
+                )*
+                false
+            }
         }
     };
 }
 
+// matches if any of the filters in the tuple match
+pub struct Or<T>(PhantomData<T>);
+
 impl_query_filter!(A);
 impl_query_filter!(A, B);
 impl_query_filter!(A, B, C);
@@ -151,79 +312,608 @@ impl_query_filter!(A, B, C, D, E, F);
 impl_query_filter!(A, B, C, D, E, F, G);
 impl_query_filter!(A, B, C, D, E, F, G, H);
 
+pub struct Not<F: QueryFilter>(PhantomData<F>);
+
+impl<F: QueryFilter> QueryFilter for Not<F> {
+    // `None` if F rejected the archetype
+    type State = Option<F::State>;
+
+    // an archetype F rejects matches every row, otherwise each row has to be checked
+    fn test_archetype(_: &Archetype) -> bool {
+        true
+    }
+
+    fn prepare(archetype: &Archetype) -> Self::State {
+        F::test_archetype(archetype).then(|| F::prepare(archetype))
+    }
+
+    fn test_row(state: &Self::State, row: usize, ticks: SystemTicks) -> bool {
+        !state
+            .as_ref()
+            .is_some_and(|state| F::test_row(state, row, ticks))
+    }
+}
+
 pub struct With<T: Component>(PhantomData<T>);
 
 impl<T: Component> QueryFilter for With<T> {
+    type State = ();
+
     fn test_archetype(archetype: &Archetype) -> bool {
-        archetype.contains_component_by_type_id(TypeId::of::<T>())
+        archetype.contains_component::<T>()
+    }
+
+    fn prepare(_: &Archetype) -> Self::State {}
+
+    fn test_row(_: &Self::State, _: usize, _: SystemTicks) -> bool {
+        true
     }
 }
 
 pub struct Without<T: Component>(PhantomData<T>);
 
 impl<T: Component> QueryFilter for Without<T> {
+    type State = ();
+
     fn test_archetype(archetype: &Archetype) -> bool {
-        !archetype.contains_component_by_type_id(TypeId::of::<T>())
+        !archetype.contains_component::<T>()
+    }
+
+    fn prepare(_: &Archetype) -> Self::State {}
+
+    fn test_row(_: &Self::State, _: usize, _: SystemTicks) -> bool {
+        true
     }
 }
 
-pub struct Query<Q, F = ()>
+pub struct Added<T: Component>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for Added<T> {
+    type State = Option<SharedColumnRead>;
+
+    fn test_archetype(archetype: &Archetype) -> bool {
+        archetype.contains_component::<T>()
+    }
+
+    fn prepare(archetype: &Archetype) -> Self::State {
+        archetype.lock_column::<T>()
+    }
+
+    fn test_row(state: &Self::State, row: usize, ticks: SystemTicks) -> bool {
+        state
+            .as_ref()
+            .and_then(|column| column.get_ticks(row))
+            .is_some_and(|component_ticks| component_ticks.is_added(ticks.last_run, ticks.this_run))
+    }
+}
+
+pub struct Changed<T: Component>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for Changed<T> {
+    type State = Option<SharedColumnRead>;
+
+    fn test_archetype(archetype: &Archetype) -> bool {
+        archetype.contains_component::<T>()
+    }
+
+    fn prepare(archetype: &Archetype) -> Self::State {
+        archetype.lock_column::<T>()
+    }
+
+    fn test_row(state: &Self::State, row: usize, ticks: SystemTicks) -> bool {
+        state
+            .as_ref()
+            .and_then(|column| column.get_ticks(row))
+            .is_some_and(|component_ticks| {
+                component_ticks.is_changed(ticks.last_run, ticks.this_run)
+            })
+    }
+}
+
+// the archetypes matched by a query, kept up to date by only testing archetypes created
+// since the last update
+pub struct QueryState<Q, F = ()>
 where
-    Q: QueryFetch + ?Sized,
-    F: QueryFilter + ?Sized,
+    Q: QueryFetch,
+    F: QueryFilter,
 {
-    world: Arc<World>,
-    entities: Box<[Entity]>,
-    _fetch: PhantomData<Q>,
-    _filter: PhantomData<F>,
+    archetypes: Vec<ArchetypeId>,
+    archetypes_seen: usize,
+    _marker: PhantomData<fn() -> (Q, F)>,
 }
 
-impl<Q, F> Query<Q, F>
+impl<Q, F> QueryState<Q, F>
 where
-    Q: QueryFetch + ?Sized,
-    F: QueryFilter + ?Sized,
+    Q: QueryFetch,
+    F: QueryFilter,
 {
-    pub fn new(world: &Arc<World>) -> Self {
-        let mut entities = Vec::new();
-        let storage = world.storage().read();
+    pub fn new() -> Self {
+        Self {
+            archetypes: Vec::new(),
+            archetypes_seen: 0,
+            _marker: PhantomData,
+        }
+    }
 
-        for archetype in storage.archetype_iter() {
+    pub fn update(&mut self, storage: &Storage) {
+        let archetypes = storage.archetypes();
+        for archetype in &archetypes[self.archetypes_seen..] {
             if Q::test_archetype(archetype) && F::test_archetype(archetype) {
-                entities.extend(archetype.entity_iter());
+                self.archetypes.push(archetype.id());
             }
         }
+        self.archetypes_seen = archetypes.len();
+    }
 
-        drop(storage);
+    // in creation order, which is also ascending id order
+    pub fn matched_archetypes(&self) -> &[ArchetypeId] {
+        &self.archetypes
+    }
+}
+
+impl<Q, F> Default for QueryState<Q, F>
+where
+    Q: QueryFetch,
+    F: QueryFilter,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// an archetype's locked columns along with its matching rows
+type LockedArchetype<Q> = (<Q as QueryFetch>::Columns, Vec<(Entity, usize)>);
+
+pub struct Query<Q, F = ()>
+where
+    Q: QueryFetch,
+    F: QueryFilter,
+{
+    world: Arc<World>,
+    archetypes: Box<[ArchetypeId]>,
+    ticks: SystemTicks,
+    _marker: PhantomData<fn() -> (Q, F)>,
+}
+
+impl<Q, F> Query<Q, F>
+where
+    Q: QueryFetch,
+    F: QueryFilter,
+{
+    pub fn new(world: &Arc<World>) -> Self {
+        Self::from_state(world, &mut QueryState::new())
+    }
+
+    pub fn from_state(world: &Arc<World>, state: &mut QueryState<Q, F>) -> Self {
+        state.update(&world.storage().read());
 
         Self {
             world: world.clone(),
-            entities: entities.into_boxed_slice(),
-            _fetch: PhantomData,
-            _filter: PhantomData,
+            archetypes: state.matched_archetypes().into(),
+            ticks: world.system_ticks(),
+            _marker: PhantomData,
+        }
+    }
+
+    fn matching_rows(&self, archetype: &Archetype) -> Vec<usize> {
+        // the filter state is dropped before any column is locked for fetching
+        let state = F::prepare(archetype);
+        (0..archetype.len())
+            .filter(|row| F::test_row(&state, *row, self.ticks))
+            .collect()
+    }
+
+    fn matched_archetypes<'s>(
+        &'s self,
+        storage: &'s Storage,
+    ) -> impl Iterator<Item = &'s Archetype> + 's {
+        self.archetypes
+            .iter()
+            .filter_map(|id| storage.get_archetype_by_id(*id))
+            .filter(|archetype| !archetype.is_empty())
+    }
+
+    // locks the fetched columns of every matched archetype up front, so the storage
+    // doesn't have to stay locked while iterating
+    fn lock_archetypes(&self) -> Vec<LockedArchetype<Q>> {
+        let storage = self.world.storage().read();
+        let mut locked = Vec::new();
+
+        for archetype in self.matched_archetypes(&storage) {
+            let rows = self.matching_rows(archetype);
+            if rows.is_empty() {
+                continue;
+            }
+
+            let Some(columns) = Q::lock_columns(archetype) else {
+                continue;
+            };
+
+            let entities = archetype.entities();
+            locked.push((
+                columns,
+                rows.into_iter().map(|row| (entities[row], row)).collect(),
+            ));
         }
+
+        locked
     }
 
     pub fn entity_iter(&self) -> impl Iterator<Item = Entity> + '_ {
-        self.entities.iter().copied()
+        let storage = self.world.storage().read();
+        let mut entities = Vec::new();
+
+        for archetype in self.matched_archetypes(&storage) {
+            let archetype_entities = archetype.entities();
+            entities.extend(
+                self.matching_rows(archetype)
+                    .into_iter()
+                    .map(|row| archetype_entities[row]),
+            );
+        }
+
+        entities.into_iter()
+    }
+
+    pub fn len(&self) -> usize {
+        let storage = self.world.storage().read();
+        self.matched_archetypes(&storage)
+            .map(|archetype| self.matching_rows(archetype).len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (Entity, Q::Fetch<'_>)> + '_ {
-        self.entities
+        let change_tick = self.ticks.this_run;
+        self.lock_archetypes()
+            .into_iter()
+            .flat_map(move |(columns, rows)| {
+                rows.into_iter().map(move |(entity, row)| {
+                    (entity, Q::fetch(&columns, entity, row, change_tick))
+                })
+            })
+    }
+
+    pub fn par_iter(&self) -> QueryParIter<'_, Q, F> {
+        QueryParIter {
+            query: self,
+            batch_size: None,
+        }
+    }
+
+    pub fn par_for_each<Func>(&self, f: Func)
+    where
+        Func: Fn((Entity, Q::Fetch<'_>)) + Send + Sync,
+        Q::Columns: Sync,
+    {
+        self.par_iter().for_each(f)
+    }
+
+    // every unordered combination of K distinct matched entities; it's only an `Iterator` for
+    // read-only fetches, mutable ones go through `QueryCombinationIter::fetch_next`
+    pub fn iter_combinations<const K: usize>(&self) -> QueryCombinationIter<'_, Q, K> {
+        let locked = self.lock_archetypes();
+        let rows = locked
+            .iter()
+            .enumerate()
+            .flat_map(|(index, (_, rows))| {
+                rows.iter().map(move |(entity, row)| (index, *entity, *row))
+            })
+            .collect::<Vec<_>>();
+
+        QueryCombinationIter {
+            locked,
+            rows,
+            indices: [0; K],
+            current: None,
+            state: CombinationState::Start,
+            change_tick: self.ticks.this_run,
+            _marker: PhantomData,
+        }
+    }
+
+    // entities may be listed more than once, since nothing is fetched mutably
+    pub fn get_many<const N: usize>(&self, entities: [Entity; N]) -> Option<[Q::Fetch<'_>; N]>
+    where
+        Q: ReadOnlyQueryFetch,
+    {
+        self.fetch_many(entities)
+    }
+
+    // the results keep the query mutably borrowed, so two of them can't point at the same rows
+    pub fn get_many_mut<const N: usize>(
+        &mut self,
+        entities: [Entity; N],
+    ) -> Result<QueryItems<'_, [Q::Fetch<'_>; N]>> {
+        for (i, entity) in entities.iter().enumerate() {
+            if entities[..i].contains(entity) {
+                bail!("{:?} was requested more than once", entity);
+            }
+        }
+
+        let items = self
+            .fetch_many(entities)
+            .ok_or_else(|| anyhow!("Not every entity in {:?} matches the query", entities))?;
+        Ok(QueryItems(items, PhantomData))
+    }
+
+    fn fetch_many<const N: usize>(&self, entities: [Entity; N]) -> Option<[Q::Fetch<'_>; N]> {
+        let fetched = self
+            .fetch_each(&entities)
+            .into_iter()
+            .collect::<Option<Vec<_>>>()?;
+        fetched.try_into().ok()
+    }
+
+    // `None` for the entities that don't match; each archetype's columns are locked once,
+    // however many of the entities live in it
+    pub(crate) fn fetch_each(&self, entities: &[Entity]) -> Vec<Option<Q::Fetch<'_>>> {
+        let storage = self.world.storage().read();
+
+        let locations = entities
+            .iter()
+            .map(|entity| {
+                let location = storage.get_location(*entity)?;
+                self.archetypes
+                    .binary_search_by_key(&location.archetype_id.index(), |id| id.index())
+                    .ok()?;
+
+                let archetype = storage.get_archetype_by_id(location.archetype_id)?;
+                F::test_row(&F::prepare(archetype), location.row, self.ticks).then_some(location)
+            })
+            .collect::<Vec<_>>();
+
+        // only lock for fetching once every filter has released its columns
+        let mut locked: Vec<(ArchetypeId, Option<Q::Columns>)> = Vec::new();
+        let columns = locations
+            .iter()
+            .map(|location| {
+                let location = location.as_ref()?;
+                let index = match locked
+                    .iter()
+                    .position(|(id, _)| *id == location.archetype_id)
+                {
+                    Some(index) => index,
+                    None => {
+                        let archetype = storage.get_archetype_by_id(location.archetype_id)?;
+                        locked.push((location.archetype_id, Q::lock_columns(archetype)));
+                        locked.len() - 1
+                    }
+                };
+                Some(index)
+            })
+            .collect::<Vec<_>>();
+
+        drop(storage);
+
+        entities
             .iter()
-            .filter_map(move |entity| Some((*entity, Q::fetch(&self.world, *entity)?)))
+            .zip(locations)
+            .zip(columns)
+            .map(|((entity, location), index)| {
+                let columns = locked[index?].1.as_ref()?;
+                Some(Q::fetch(
+                    columns,
+                    *entity,
+                    location?.row,
+                    self.ticks.this_run,
+                ))
+            })
+            .collect()
     }
 
     pub fn get(&self, entity: Entity) -> Option<Q::Fetch<'_>> {
-        Q::fetch(&self.world, entity)
+        let storage = self.world.storage().read();
+        let location = storage.get_location(entity)?;
+
+        self.archetypes
+            .binary_search_by_key(&location.archetype_id.index(), |id| id.index())
+            .ok()?;
+
+        let archetype = storage.get_archetype_by_id(location.archetype_id)?;
+        if !F::test_row(&F::prepare(archetype), location.row, self.ticks) {
+            return None;
+        }
+
+        let columns = Q::lock_columns(archetype)?;
+        Some(Q::fetch(
+            &columns,
+            entity,
+            location.row,
+            self.ticks.this_run,
+        ))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CombinationState {
+    Start,
+    Running,
+    Done,
+}
+
+// fetched items that hold on to a mutable borrow of the query they came from; the fetches
+// themselves only check for aliasing at runtime
+pub struct QueryItems<'q, T>(T, PhantomData<&'q mut ()>);
+
+impl<T> std::ops::Deref for QueryItems<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for QueryItems<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+// every item of a combination points at a different row
+pub struct QueryCombinationIter<'q, Q, const K: usize>
+where
+    Q: QueryFetch,
+{
+    locked: Vec<LockedArchetype<Q>>,
+    // (index into `locked`, entity, row) for every matched row
+    rows: Vec<(usize, Entity, usize)>,
+    indices: [usize; K],
+    // the combination last lent out by `fetch_next`
+    current: Option<[(Entity, Q::Fetch<'q>); K]>,
+    state: CombinationState,
+    change_tick: Tick,
+    _marker: PhantomData<&'q ()>,
+}
+
+impl<'q, Q, const K: usize> QueryCombinationIter<'q, Q, K>
+where
+    Q: QueryFetch,
+{
+    fn advance(&mut self) -> bool {
+        let n = self.rows.len();
+        if K == 0 || K > n {
+            return false;
+        }
+
+        if self.state == CombinationState::Start {
+            for (i, index) in self.indices.iter_mut().enumerate() {
+                *index = i;
+            }
+            return true;
+        }
+
+        // bump the rightmost index that still has room, then reset the ones after it
+        let Some(i) = (0..K).rev().find(|&i| self.indices[i] < n - K + i) else {
+            return false;
+        };
+        self.indices[i] += 1;
+        for j in i + 1..K {
+            self.indices[j] = self.indices[j - 1] + 1;
+        }
+        true
+    }
+
+    fn fetch_combination<'a>(&mut self) -> Option<[(Entity, Q::Fetch<'a>); K]> {
+        if self.state == CombinationState::Done {
+            return None;
+        }
+
+        if !self.advance() {
+            self.state = CombinationState::Done;
+            return None;
+        }
+        self.state = CombinationState::Running;
+
+        Some(std::array::from_fn(|i| {
+            let (index, entity, row) = self.rows[self.indices[i]];
+            let fetch = Q::fetch(&self.locked[index].0, entity, row, self.change_tick);
+            (entity, fetch)
+        }))
+    }
+
+    // lends out the next combination until the following call, which drops it before fetching
+    // again, so mutable fetches of the same row are never alive at once:
+    // `while let Some([(_, a), (_, b)]) = combinations.fetch_next() { .. }`
+    pub fn fetch_next(&mut self) -> Option<&mut [(Entity, Q::Fetch<'q>); K]> {
+        self.current = None;
+        self.current = self.fetch_combination();
+        self.current.as_mut()
+    }
+}
+
+impl<'q, Q, const K: usize> Iterator for QueryCombinationIter<'q, Q, K>
+where
+    Q: ReadOnlyQueryFetch,
+{
+    type Item = [(Entity, Q::Fetch<'q>); K];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fetch_combination()
+    }
+}
+
+// runs over the matched rows in batches on the rayon pool of the calling thread,
+// which for systems is the App's pool
+pub struct QueryParIter<'q, Q, F>
+where
+    Q: QueryFetch,
+    F: QueryFilter,
+{
+    query: &'q Query<Q, F>,
+    batch_size: Option<usize>,
+}
+
+impl<'q, Q, F> QueryParIter<'q, Q, F>
+where
+    Q: QueryFetch,
+    F: QueryFilter,
+{
+    // rows per task; by default the rows are spread over a few tasks per thread
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size.max(1));
+        self
+    }
+
+    pub fn for_each<Func>(self, f: Func)
+    where
+        Func: Fn((Entity, Q::Fetch<'_>)) + Send + Sync,
+        Q::Columns: Sync,
+    {
+        // the locks are held by this thread until every batch has finished
+        let locked = self.query.lock_archetypes();
+        let change_tick = self.query.ticks.this_run;
+
+        let batch_size = self.batch_size.unwrap_or_else(|| {
+            let rows = locked.iter().map(|(_, rows)| rows.len()).sum::<usize>();
+            (rows / (rayon::current_num_threads() * 4)).max(1)
+        });
+
+        let f = &f;
+        rayon::scope(|scope| {
+            for (columns, rows) in &locked {
+                // batches never share a row, so each `Mut` stays unique
+                for batch in rows.chunks(batch_size) {
+                    scope.spawn(move |_| {
+                        for &(entity, row) in batch {
+                            f((entity, Q::fetch(columns, entity, row, change_tick)));
+                        }
+                    });
+                }
+            }
+        });
     }
 }
 
+// two live `get_many_mut` results over the same rows are rejected by the borrow checker
+#[cfg(doctest)]
+#[doc = r#"
+```compile_fail,E0499
+use weaver_ecs::prelude::*;
+
+#[derive(Component)]
+struct Position(f32);
+
+let world = World::new();
+let entity = world.spawn(Position(0.0));
+let mut query = world.query::<&mut Position>();
+let a = query.get_many_mut([entity]).unwrap();
+let b = query.get_many_mut([entity]).unwrap();
+drop((a, b));
+```
+"#]
+pub struct GetManyMutOverlap;
+
 #[cfg(test)]
 mod tests {
     use crate as weaver_ecs;
     use weaver_ecs_macros::Component;
 
     use super::*;
+    use crate::prelude::Tick;
 
     #[derive(Debug, Default, PartialEq, Component)]
     struct Position {
@@ -281,4 +971,302 @@ mod tests {
         assert_eq!(*position, Position { x: 0.0, y: 0.0 });
         assert_eq!(*velocity, Velocity { x: 1.0, y: 1.0 });
     }
+
+    #[test]
+    fn query_added_changed() {
+        let world = World::new();
+        let entity1 = world.create_entity();
+        let entity2 = world.create_entity();
+
+        world.insert_component(entity1, Position { x: 0.0, y: 0.0 });
+        world.insert_component(entity2, Position { x: 0.0, y: 0.0 });
+
+        let reader_first_run = world.increment_update_tick();
+        SystemTicks::new(Tick::default(), reader_first_run).run_with(|| {
+            let added = world.query_filtered::<&Position, Added<Position>>();
+            assert_eq!(added.len(), 2);
+        });
+
+        let writer_run = world.increment_update_tick();
+        SystemTicks::new(Tick::default(), writer_run).run_with(|| {
+            // reading through Mut does not mark the component as changed
+            let position = world.get_component_mut::<Position>(entity1).unwrap();
+            assert_eq!(position.x, 0.0);
+            drop(position);
+
+            world.get_component_mut::<Position>(entity2).unwrap().x = 1.0;
+        });
+
+        let reader_second_run = world.increment_update_tick();
+        SystemTicks::new(reader_first_run, reader_second_run).run_with(|| {
+            let added = world.query_filtered::<&Position, Added<Position>>();
+            assert!(added.is_empty());
+
+            let changed = world.query_filtered::<&Position, Changed<Position>>();
+            let entities = changed.entity_iter().collect::<Vec<_>>();
+            assert_eq!(entities, vec![entity2]);
+        });
+
+        let reader_third_run = world.increment_update_tick();
+        SystemTicks::new(reader_second_run, reader_third_run).run_with(|| {
+            let changed = world.query_filtered::<&Position, Changed<Position>>();
+            assert!(changed.is_empty());
+        });
+    }
+
+    #[test]
+    fn query_optional_fetches() {
+        let world = World::new();
+        let entity1 = world.spawn((Position { x: 1.0, y: 0.0 }, Velocity { x: 1.0, y: 1.0 }));
+        let entity2 = world.spawn(Position { x: 2.0, y: 0.0 });
+
+        let query = world.query::<(Entity, &Position, Option<&Velocity>, Has<Acceleration>)>();
+        assert_eq!(query.len(), 2);
+
+        let (entity, position, velocity, has_acceleration) = query.get(entity1).unwrap();
+        assert_eq!(entity, entity1);
+        assert_eq!(position.x, 1.0);
+        assert_eq!(velocity.map(|v| v.x), Some(1.0));
+        assert!(!has_acceleration);
+
+        let (_, _, velocity, _) = query.get(entity2).unwrap();
+        assert!(velocity.is_none());
+        drop(query);
+
+        let query = world.query::<(&Position, Option<&mut Velocity>)>();
+        for (_, (_, velocity)) in query.iter() {
+            if let Some(mut velocity) = velocity {
+                velocity.x = 2.0;
+            }
+        }
+        drop(query);
+        assert_eq!(world.get_component::<Velocity>(entity1).unwrap().x, 2.0);
+    }
+
+    #[test]
+    fn query_composed_filters() {
+        let world = World::new();
+        let entity1 = world.spawn((Position::default(), Velocity::default()));
+        let entity2 = world.spawn((Position::default(), Acceleration::default()));
+        let entity3 = world.spawn(Position::default());
+
+        let query = world.query_filtered::<&Position, (With<Velocity>, Without<Acceleration>)>();
+        assert_eq!(query.entity_iter().collect::<Vec<_>>(), vec![entity1]);
+
+        let query = world.query_filtered::<&Position, Or<(With<Velocity>, With<Acceleration>)>>();
+        let matched = query.entity_iter().collect::<Vec<_>>();
+        assert!(matched.contains(&entity1) && matched.contains(&entity2));
+        assert!(!matched.contains(&entity3));
+
+        let query =
+            world.query_filtered::<&Position, Not<Or<(With<Velocity>, With<Acceleration>)>>>();
+        assert_eq!(query.entity_iter().collect::<Vec<_>>(), vec![entity3]);
+
+        let query =
+            world.query_filtered::<&Position, (Not<With<Velocity>>, (With<Acceleration>,))>();
+        assert_eq!(query.entity_iter().collect::<Vec<_>>(), vec![entity2]);
+    }
+
+    #[test]
+    fn query_state_caches_archetypes() {
+        let world = World::new();
+        let mut state = QueryState::<&Position, Without<Acceleration>>::new();
+
+        world.spawn((Position::default(), Velocity::default()));
+        let query = Query::from_state(&world, &mut state);
+        assert_eq!(query.len(), 1);
+        let matched = state.matched_archetypes().len();
+
+        // only archetypes created since the last update are tested
+        world.spawn(Position::default());
+        world.spawn((Position::default(), Acceleration::default()));
+        let query = Query::from_state(&world, &mut state);
+        assert_eq!(state.matched_archetypes().len(), matched + 1);
+        assert_eq!(query.len(), 2);
+    }
+
+    #[test]
+    fn query_iter_shares_column_locks() {
+        let world = World::new();
+        for i in 0..4 {
+            world.spawn((
+                Position {
+                    x: i as f32,
+                    y: 0.0,
+                },
+                Velocity { x: 1.0, y: 0.0 },
+            ));
+        }
+        let moving = world.spawn(Position { x: 10.0, y: 0.0 });
+
+        let query = world.query::<(&mut Position, &Velocity)>();
+        let items = query.iter().collect::<Vec<_>>();
+        assert_eq!(items.len(), 4);
+
+        // every item is alive at once, backed by a single lock per column
+        for (_, (mut position, velocity)) in items {
+            position.x += velocity.x;
+        }
+
+        let positions = world.query::<&Position>();
+        let mut xs = positions.iter().map(|(_, p)| p.x).collect::<Vec<_>>();
+        xs.sort_by(f32::total_cmp);
+        assert_eq!(xs, vec![1.0, 2.0, 3.0, 4.0, 10.0]);
+        assert!(positions.get(moving).is_some());
+        drop(positions);
+
+        // the filter is applied to single lookups as well
+        let query = world.query_filtered::<&Position, With<Velocity>>();
+        assert!(query.get(moving).is_none());
+    }
+
+    #[test]
+    fn query_par_iter() {
+        let world = World::new();
+        for i in 0..1000 {
+            world.spawn((
+                Position {
+                    x: i as f32,
+                    y: 0.0,
+                },
+                Velocity { x: 1.0, y: 2.0 },
+            ));
+            if i % 2 == 0 {
+                world.spawn(Position {
+                    x: i as f32,
+                    y: 0.0,
+                });
+            }
+        }
+
+        let query = world.query::<(&mut Position, &Velocity)>();
+        query
+            .par_iter()
+            .batch_size(64)
+            .for_each(|(_, (mut position, velocity))| {
+                position.x += velocity.x;
+                position.y += velocity.y;
+            });
+        query.par_for_each(|(_, (mut position, _))| position.y *= 2.0);
+        drop(query);
+
+        let query = world.query::<(&Position, Has<Velocity>)>();
+        assert_eq!(query.len(), 1500);
+        for (_, (position, has_velocity)) in query.iter() {
+            assert_eq!(position.y, if has_velocity { 4.0 } else { 0.0 });
+        }
+    }
+
+    #[test]
+    fn query_iter_combinations() {
+        let world = World::new();
+        for i in 0..5 {
+            world.spawn((
+                Position {
+                    x: i as f32,
+                    y: 0.0,
+                },
+                Velocity::default(),
+            ));
+        }
+        world.spawn(Position::default());
+
+        let query = world.query::<(&Position, &Velocity)>();
+        assert_eq!(query.iter_combinations::<2>().count(), 10);
+        assert_eq!(query.iter_combinations::<3>().count(), 10);
+        assert_eq!(query.iter_combinations::<5>().count(), 1);
+        assert_eq!(query.iter_combinations::<6>().count(), 0);
+
+        for [(a, _), (b, _)] in query.iter_combinations() {
+            assert_ne!(a, b);
+        }
+        drop(query);
+
+        // pairwise forces, with both sides of each pair borrowed mutably
+        let query = world.query::<(&Position, &mut Velocity)>();
+        let mut combinations = query.iter_combinations();
+        while let Some([(_, (a, va)), (_, (b, vb))]) = combinations.fetch_next() {
+            va.x += b.x - a.x;
+            vb.x += a.x - b.x;
+        }
+        drop(combinations);
+        drop(query);
+
+        let query = world.query::<(&Position, &Velocity)>();
+        for (_, (position, velocity)) in query.iter() {
+            assert_eq!(velocity.x, 10.0 - 5.0 * position.x);
+        }
+    }
+
+    #[test]
+    fn query_combinations_mut() {
+        let world = World::new();
+        let entities = (1..=3)
+            .map(|i| {
+                world.spawn(Position {
+                    x: i as f32,
+                    y: 0.0,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // every pair swaps its x into the other's y, accumulating over the pairs
+        let query = world.query::<&mut Position>();
+        let mut combinations = query.iter_combinations::<2>();
+        let mut pairs = 0;
+        while let Some([(_, a), (_, b)]) = combinations.fetch_next() {
+            a.y += b.x;
+            b.y += a.x;
+            pairs += 1;
+        }
+        drop(combinations);
+        drop(query);
+
+        assert_eq!(pairs, 3);
+        let ys = entities
+            .iter()
+            .map(|entity| world.get_component::<Position>(*entity).unwrap().y)
+            .collect::<Vec<_>>();
+        assert_eq!(ys, [5.0, 4.0, 3.0]);
+    }
+
+    #[test]
+    fn query_get_many() {
+        let world = World::new();
+        let entity1 = world.spawn((Position { x: 1.0, y: 0.0 }, Velocity::default()));
+        let entity2 = world.spawn(Position { x: 2.0, y: 0.0 });
+        let entity3 = world.spawn(Position { x: 3.0, y: 0.0 });
+        let other = world.spawn(Velocity::default());
+
+        let query = world.query::<&Position>();
+        let [a, b, c] = query.get_many([entity1, entity3, entity1]).unwrap();
+        assert_eq!((a.x, b.x, c.x), (1.0, 3.0, 1.0));
+        assert!(query.get_many([entity1, other]).is_none());
+        drop((a, b, c));
+
+        // entities 2 and 3 share an archetype, so their column is only locked once
+        let mut query = world.query::<&mut Position>();
+        let mut items = query.get_many_mut([entity1, entity2, entity3]).unwrap();
+        let [a, b, c] = &mut *items;
+        std::mem::swap(&mut a.x, &mut c.x);
+        b.x *= 10.0;
+        drop(items);
+
+        assert!(query.get_many_mut([entity1, entity1]).is_err());
+        assert!(query.get_many_mut([entity1, other]).is_err());
+
+        // overlapping results one after the other are fine; at the same time they don't compile,
+        // see `GetManyMutOverlap`
+        let mut items = query.get_many_mut([entity2]).unwrap();
+        items[0].y = 1.0;
+        drop(items);
+        let items = query.get_many_mut([entity2, entity3]).unwrap();
+        assert_eq!((items[0].y, items[1].y), (1.0, 0.0));
+        drop(items);
+        drop(query);
+
+        assert_eq!(world.get_component::<Position>(entity1).unwrap().x, 3.0);
+        assert_eq!(world.get_component::<Position>(entity2).unwrap().x, 20.0);
+        assert_eq!(world.get_component::<Position>(entity3).unwrap().x, 1.0);
+    }
 }
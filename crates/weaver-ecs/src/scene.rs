@@ -1,25 +1,110 @@
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ops::Deref,
+    sync::Arc,
+};
 
 use petgraph::prelude::*;
 use weaver_ecs_macros::Component;
-use weaver_util::lock::Lock;
+use weaver_util::{
+    lock::Lock,
+    prelude::{bail, Result},
+};
 
 use crate::{
     self as weaver_ecs,
     component::Component,
     entity::Entity,
     node::Node,
-    prelude::Bundle,
+    prelude::{Bundle, Query, QueryFetch, QueryFilter, QueryState},
     relationship::{Relationship, RelationshipConnection},
+    storage::Ref,
     world::World,
 };
 
+// parent/child links between nodes, kept apart from the relationship edges so children
+// stay in the order they were attached
+#[derive(Default)]
+struct Hierarchy {
+    nodes: HashMap<Entity, Node>,
+    parents: HashMap<NodeIndex, Node>,
+    children: HashMap<NodeIndex, Vec<Node>>,
+}
+
+impl Hierarchy {
+    fn attach(&mut self, child: Node, parent: Node) {
+        self.parents.insert(child.scene_index, parent);
+        self.children
+            .entry(parent.scene_index)
+            .or_default()
+            .push(child);
+    }
+
+    fn detach(&mut self, child: Node) {
+        if let Some(parent) = self.parents.remove(&child.scene_index) {
+            if let Some(siblings) = self.children.get_mut(&parent.scene_index) {
+                siblings.retain(|sibling| *sibling != child);
+            }
+        }
+    }
+
+    fn children(&self, node: Node) -> &[Node] {
+        self.children
+            .get(&node.scene_index)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+// nodes form a hierarchy rooted at the scene root, which `children_of`, `parent_of` and the
+// iterators walk; relationship edges live apart from it and are reached through
+// `neighbors_of` and the relationship lookups
 #[derive(Component)]
 pub struct Scene {
     world: Arc<World>,
     root_entity: Entity,
+    // shared with the world, which removes destroyed entities from every scene still alive
+    nodes: Arc<SceneNodes>,
+}
+
+pub(crate) struct SceneNodes {
     root: NodeIndex,
     graph: Lock<StableDiGraph<Node, RelationshipConnection>>,
+    hierarchy: Lock<Hierarchy>,
+}
+
+impl SceneNodes {
+    pub(crate) fn find_node(&self, entity: Entity) -> Option<Node> {
+        self.hierarchy.read().nodes.get(&entity).copied()
+    }
+
+    pub(crate) fn contains(&self, node: Node) -> bool {
+        self.graph.read().node_weight(node.scene_index) == Some(&node)
+    }
+
+    pub(crate) fn remove_node(&self, node: Node) {
+        if node.scene_index == self.root || !self.contains(node) {
+            return;
+        }
+
+        let mut hierarchy = self.hierarchy.write();
+        let parent = hierarchy.parents.get(&node.scene_index).copied();
+        let children = hierarchy
+            .children
+            .remove(&node.scene_index)
+            .unwrap_or_default();
+        hierarchy.detach(node);
+        for child in children {
+            hierarchy.parents.remove(&child.scene_index);
+            if let Some(parent) = parent {
+                hierarchy.attach(child, parent);
+            }
+        }
+        hierarchy.nodes.remove(&node.entity);
+        drop(hierarchy);
+
+        self.graph.write().remove_node(node.scene_index);
+    }
 }
 
 impl Scene {
@@ -31,11 +116,21 @@ impl Scene {
             scene_index: NodeIndex::new(0),
         });
         graph[root].scene_index = root;
+
+        let mut hierarchy = Hierarchy::default();
+        hierarchy.nodes.insert(root_entity, graph[root]);
+
+        let nodes = Arc::new(SceneNodes {
+            root,
+            graph: Lock::new(graph),
+            hierarchy: Lock::new(hierarchy),
+        });
+        world.track_scene(&nodes);
+
         Self {
             world,
             root_entity,
-            root,
-            graph: Lock::new(graph),
+            nodes,
         }
     }
 
@@ -48,11 +143,11 @@ impl Scene {
     }
 
     pub fn root(&self) -> Node {
-        self.graph.read()[self.root]
+        self.nodes.graph.read()[self.nodes.root]
     }
 
     pub fn graph(&self) -> &Lock<StableDiGraph<Node, RelationshipConnection>> {
-        &self.graph
+        &self.nodes.graph
     }
 
     pub fn create_node(&self) -> Node {
@@ -71,21 +166,63 @@ impl Scene {
         self.add_node(entity)
     }
 
+    // new nodes are children of the root, so every node is reachable from it; an entity
+    // already in the scene keeps its node
     pub fn add_node(&self, entity: Entity) -> Node {
-        let node_scene_index = self.graph.write().add_node(Node {
+        if let Some(node) = self.find_node(entity) {
+            return node;
+        }
+
+        let mut graph = self.nodes.graph.write();
+        let scene_index = graph.add_node(Node {
             entity,
             scene_index: NodeIndex::new(0),
         });
-        self.graph.write()[node_scene_index].scene_index = node_scene_index;
-        self.graph.read()[node_scene_index]
+        graph[scene_index].scene_index = scene_index;
+        let node = graph[scene_index];
+        let root = graph[self.nodes.root];
+        drop(graph);
+
+        let mut hierarchy = self.nodes.hierarchy.write();
+        hierarchy.nodes.insert(entity, node);
+        hierarchy.attach(node, root);
+        node
+    }
+
+    pub fn add_child(&self, parent: Node, entity: Entity) -> Result<Node> {
+        let node = self.add_node(entity);
+        self.set_parent(node, parent)?;
+        Ok(node)
+    }
+
+    pub fn spawn_child<T: Bundle>(&self, parent: Node, bundle: T) -> Result<Node> {
+        let entity = self.world.spawn(bundle);
+        self.add_child(parent, entity)
+    }
+
+    // moves `child` to the end of `new_parent`'s children
+    pub fn set_parent(&self, child: Node, new_parent: Node) -> Result<()> {
+        if !self.contains(child) || !self.contains(new_parent) {
+            bail!("Both nodes must belong to the scene");
+        }
+        if child.scene_index == self.nodes.root {
+            bail!("The scene root can't be reparented");
+        }
+        if self.ancestors(new_parent).any(|ancestor| ancestor == child) || child == new_parent {
+            bail!("A node can't become a descendant of itself");
+        }
+
+        let mut hierarchy = self.nodes.hierarchy.write();
+        hierarchy.detach(child);
+        hierarchy.attach(child, new_parent);
+        Ok(())
     }
 
     pub fn add_relationship<T: Relationship>(&self, from: Node, to: Node, weight: T) {
-        let from = from.scene_index;
-        let to = to.scene_index;
+        let mut graph = self.nodes.graph.write();
         let connection =
-            RelationshipConnection::new(self.graph.read()[from], self.graph.read()[to], weight);
-        self.graph.write().add_edge(from, to, connection);
+            RelationshipConnection::new(graph[from.scene_index], graph[to.scene_index], weight);
+        graph.add_edge(from.scene_index, to.scene_index, connection);
     }
 
     pub fn create_sub_scene(&self) -> Scene {
@@ -94,43 +231,147 @@ impl Scene {
         sub_scene
     }
 
+    pub fn contains(&self, node: Node) -> bool {
+        self.nodes.contains(node)
+    }
+
+    // only removes the node, whose children are handed to its parent; the entity is left alive
     pub fn remove_node(&self, node: Node) {
-        self.graph.write().remove_node(node.scene_index);
+        self.nodes.remove_node(node);
+    }
+
+    // removes the node and all of its descendants, destroying their entities;
+    // the root node is kept, so despawning it only clears the scene
+    pub fn despawn_recursive(&self, node: Node) {
+        if !self.contains(node) {
+            return;
+        }
+
+        // children go before their parents
+        let mut nodes = self.iter_depth_first(node).collect::<Vec<_>>();
+        nodes.reverse();
+
+        {
+            let mut hierarchy = self.nodes.hierarchy.write();
+            let mut graph = self.nodes.graph.write();
+            for node in &nodes {
+                if node.scene_index == self.nodes.root {
+                    continue;
+                }
+                hierarchy.detach(*node);
+                hierarchy.children.remove(&node.scene_index);
+                hierarchy.nodes.remove(&node.entity);
+                graph.remove_node(node.scene_index);
+            }
+        }
+
+        for node in nodes {
+            if node.scene_index != self.nodes.root {
+                self.world.destroy_entity(node.entity);
+            }
+        }
     }
 
     pub fn remove_relationship(&mut self, from: Node, to: Node) -> Option<Arc<dyn Relationship>> {
         let from = from.scene_index;
         let to = to.scene_index;
-        if let Some(edge) = self.graph.read().find_edge(from, to) {
-            let connection = self.graph.write().remove_edge(edge)?;
-            Some(connection.weight)
-        } else {
-            None
+        let mut graph = self.nodes.graph.write();
+        let edge = graph.find_edge(from, to)?;
+        let connection = graph.remove_edge(edge)?;
+        Some(connection.weight)
+    }
+
+    // edges of type `R` leaving `node`, along with the node at their other end
+    pub fn outgoing_relationships<R: Relationship>(&self, node: Node) -> Vec<(Node, Arc<R>)> {
+        self.relationships_directed(node, Direction::Outgoing)
+    }
+
+    // edges of type `R` entering `node`, along with the node at their other end
+    pub fn incoming_relationships<R: Relationship>(&self, node: Node) -> Vec<(Node, Arc<R>)> {
+        self.relationships_directed(node, Direction::Incoming)
+    }
+
+    fn relationships_directed<R: Relationship>(
+        &self,
+        node: Node,
+        direction: Direction,
+    ) -> Vec<(Node, Arc<R>)> {
+        let graph = self.nodes.graph.read();
+        if !graph.contains_node(node.scene_index) {
+            return Vec::new();
         }
+
+        graph
+            .edges_directed(node.scene_index, direction)
+            .filter_map(|edge| {
+                let connection = edge.weight();
+                let other = match direction {
+                    Direction::Outgoing => connection.to,
+                    Direction::Incoming => connection.from,
+                };
+                Some((other, connection.weight.clone().downcast_arc::<R>().ok()?))
+            })
+            .collect()
     }
 
-    pub fn find_node(&self, entity: Entity) -> Option<Node> {
-        self.graph
+    // the nodes `node` points at through an `R`
+    pub fn related<R: Relationship>(&self, node: Node) -> Vec<Node> {
+        self.outgoing_relationships::<R>(node)
+            .into_iter()
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    pub fn get_relationship<R: Relationship>(&self, from: Node, to: Node) -> Option<Arc<R>> {
+        let graph = self.nodes.graph.read();
+        graph
+            .edges_connecting(from.scene_index, to.scene_index)
+            .find_map(|edge| edge.weight().weight.clone().downcast_arc::<R>().ok())
+    }
+
+    // every edge of type `R` in the scene as (from, to, weight)
+    pub fn relationships<R: Relationship>(&self) -> Vec<(Node, Node, Arc<R>)> {
+        self.nodes
+            .graph
             .read()
-            .node_indices()
-            .find(|&node| self.graph.read()[node].entity == entity)
-            .map(|node| self.graph.read()[node])
+            .edge_weights()
+            .filter_map(|connection| {
+                let weight = connection.weight.clone().downcast_arc::<R>().ok()?;
+                Some((connection.from, connection.to, weight))
+            })
+            .collect()
+    }
+
+    pub fn find_node(&self, entity: Entity) -> Option<Node> {
+        self.nodes.find_node(entity)
     }
 
+    // the hierarchy children, in the order they were attached; relationship edges aren't
+    // followed, see `neighbors_of`
     pub fn children_of(&self, node: Node) -> Vec<Node> {
-        self.graph
-            .read()
+        self.nodes.hierarchy.read().children(node).to_vec()
+    }
+
+    // the nodes `node` has relationship edges to, of any type
+    pub fn neighbors_of(&self, node: Node) -> Vec<Node> {
+        let graph = self.nodes.graph.read();
+        if !graph.contains_node(node.scene_index) {
+            return Vec::new();
+        }
+
+        graph
             .neighbors_directed(node.scene_index, Direction::Outgoing)
-            .map(|node| self.graph.read()[node])
+            .map(|neighbor| graph[neighbor])
             .collect()
     }
 
     pub fn parent_of(&self, node: Node) -> Option<Node> {
-        self.graph
+        self.nodes
+            .hierarchy
             .read()
-            .neighbors_directed(node.scene_index, Direction::Incoming)
-            .map(|node| self.graph.read()[node])
-            .next()
+            .parents
+            .get(&node.scene_index)
+            .copied()
     }
 
     pub fn siblings_of(&self, node: Node) -> Option<Vec<Node>> {
@@ -142,4 +383,346 @@ impl Scene {
                 .collect(),
         )
     }
+
+    // pre-order, starting with `node` itself
+    pub fn iter_depth_first(&self, node: Node) -> DepthFirstIter<'_> {
+        DepthFirstIter {
+            scene: self,
+            stack: vec![node],
+        }
+    }
+
+    // level by level, starting with `node` itself
+    pub fn iter_breadth_first(&self, node: Node) -> BreadthFirstIter<'_> {
+        BreadthFirstIter {
+            scene: self,
+            queue: VecDeque::from([node]),
+        }
+    }
+
+    // from the parent of `node` up to the root
+    pub fn ancestors(&self, node: Node) -> AncestorIter<'_> {
+        AncestorIter {
+            scene: self,
+            current: node,
+        }
+    }
+}
+
+// the scene iterators only lock the hierarchy while stepping, so it can be changed in between
+pub struct DepthFirstIter<'a> {
+    scene: &'a Scene,
+    stack: Vec<Node>,
+}
+
+impl Iterator for DepthFirstIter<'_> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let hierarchy = self.scene.nodes.hierarchy.read();
+        self.stack
+            .extend(hierarchy.children(node).iter().rev().copied());
+        Some(node)
+    }
+}
+
+pub struct BreadthFirstIter<'a> {
+    scene: &'a Scene,
+    queue: VecDeque<Node>,
+}
+
+impl Iterator for BreadthFirstIter<'_> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.pop_front()?;
+        let hierarchy = self.scene.nodes.hierarchy.read();
+        self.queue.extend(hierarchy.children(node).iter().copied());
+        Some(node)
+    }
+}
+
+pub struct AncestorIter<'a> {
+    scene: &'a Scene,
+    current: Node,
+}
+
+impl Iterator for AncestorIter<'_> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let parent = self.scene.parent_of(self.current)?;
+        self.current = parent;
+        Some(parent)
+    }
+}
+
+// a component query joined with the relationships of the root scene
+pub struct SceneQuery<Q, F = ()>
+where
+    Q: QueryFetch,
+    F: QueryFilter,
+{
+    scene: Ref<Scene>,
+    query: Query<Q, F>,
+}
+
+impl<Q, F> SceneQuery<Q, F>
+where
+    Q: QueryFetch,
+    F: QueryFilter,
+{
+    pub fn new(world: &Arc<World>) -> Self {
+        Self::from_state(world, &mut QueryState::new())
+    }
+
+    pub fn from_state(world: &Arc<World>, state: &mut QueryState<Q, F>) -> Self {
+        Self {
+            scene: world.root_scene(),
+            query: Query::from_state(world, state),
+        }
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn query(&self) -> &Query<Q, F> {
+        &self.query
+    }
+
+    // the entities `entity` points at through an `R`, where they match the query
+    pub fn get_related<R: Relationship>(
+        &self,
+        entity: Entity,
+    ) -> Vec<(Entity, Arc<R>, Q::Fetch<'_>)> {
+        let Some(node) = self.scene.find_node(entity) else {
+            return Vec::new();
+        };
+        self.fetch_related(self.scene.outgoing_relationships::<R>(node))
+    }
+
+    // the entities pointing at `entity` through an `R`, where they match the query
+    pub fn get_related_incoming<R: Relationship>(
+        &self,
+        entity: Entity,
+    ) -> Vec<(Entity, Arc<R>, Q::Fetch<'_>)> {
+        let Some(node) = self.scene.find_node(entity) else {
+            return Vec::new();
+        };
+        self.fetch_related(self.scene.incoming_relationships::<R>(node))
+    }
+
+    fn fetch_related<R: Relationship>(
+        &self,
+        mut related: Vec<(Node, Arc<R>)>,
+    ) -> Vec<(Entity, Arc<R>, Q::Fetch<'_>)> {
+        // an entity is fetched once, even if several edges lead to it
+        let mut seen = HashSet::new();
+        related.retain(|(node, _)| seen.insert(node.entity));
+
+        let entities = related
+            .iter()
+            .map(|(node, _)| node.entity)
+            .collect::<Vec<_>>();
+
+        self.query
+            .fetch_each(&entities)
+            .into_iter()
+            .zip(related)
+            .filter_map(|(fetch, (node, weight))| Some((node.entity, weight, fetch?)))
+            .collect()
+    }
+}
+
+impl<Q, F> Deref for SceneQuery<Q, F>
+where
+    Q: QueryFetch,
+    F: QueryFilter,
+{
+    type Target = Query<Q, F>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.query
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Component)]
+    struct Marker;
+
+    #[test]
+    fn test_hierarchy() {
+        let world = World::new();
+        let scene = Scene::new(world.clone());
+        let root = scene.root();
+
+        let a = scene.create_node();
+        let b = scene.create_node();
+        let a1 = scene.add_child(a, world.create_entity()).unwrap();
+        let a2 = scene.spawn_child(a, Marker).unwrap();
+        let a1x = scene.add_child(a1, world.create_entity()).unwrap();
+
+        assert_eq!(scene.find_node(a2.entity()), Some(a2));
+        assert_eq!(scene.children_of(root), vec![a, b]);
+        assert_eq!(scene.children_of(a), vec![a1, a2]);
+        assert_eq!(scene.siblings_of(a1), Some(vec![a2]));
+
+        let dfs = scene.iter_depth_first(root).collect::<Vec<_>>();
+        assert_eq!(dfs, vec![root, a, a1, a1x, a2, b]);
+        let bfs = scene.iter_breadth_first(root).collect::<Vec<_>>();
+        assert_eq!(bfs, vec![root, a, b, a1, a2, a1x]);
+        let ancestors = scene.ancestors(a1x).collect::<Vec<_>>();
+        assert_eq!(ancestors, vec![a1, a, root]);
+
+        scene.set_parent(a1, b).unwrap();
+        assert_eq!(scene.children_of(a), vec![a2]);
+        assert_eq!(scene.children_of(b), vec![a1]);
+        assert_eq!(scene.parent_of(a1x), Some(a1));
+
+        assert!(scene.set_parent(b, a1x).is_err());
+        assert!(scene.set_parent(a, a).is_err());
+        assert!(scene.set_parent(root, a).is_err());
+
+        // removing a node hands its children to its parent
+        scene.remove_node(a1);
+        assert!(world.is_alive(a1.entity()));
+        assert_eq!(scene.children_of(b), vec![a1x]);
+        assert!(scene.find_node(a1.entity()).is_none());
+    }
+
+    #[test]
+    fn test_despawn_recursive() {
+        let world = World::new();
+        let scene = Scene::new(world.clone());
+
+        let parent = scene.spawn(Marker);
+        let child = scene.spawn_child(parent, Marker).unwrap();
+        let grandchild = scene.spawn_child(child, Marker).unwrap();
+        let other = scene.spawn(Marker);
+
+        scene.despawn_recursive(parent);
+
+        for node in [parent, child, grandchild] {
+            assert!(!world.is_alive(node.entity()));
+            assert!(scene.find_node(node.entity()).is_none());
+            assert!(!scene.contains(node));
+        }
+        assert!(world.is_alive(other.entity()));
+        assert_eq!(scene.children_of(scene.root()), vec![other]);
+
+        scene.despawn_recursive(scene.root());
+        assert!(!world.is_alive(other.entity()));
+        assert!(scene.contains(scene.root()));
+        assert!(scene.children_of(scene.root()).is_empty());
+    }
+
+    #[test]
+    fn test_destroy_entity_removes_node() {
+        let world = World::new();
+        let scene = world.root_scene();
+
+        let parent = scene.spawn(Marker);
+        let child = scene.spawn_child(parent, Marker).unwrap();
+        let other = scene.spawn(Marker);
+        scene.add_relationship(other, parent, Targets);
+
+        // the node goes with its entity, handing its children to its parent
+        world.destroy_entity(parent.entity());
+        assert!(!scene.contains(parent));
+        assert!(scene.find_node(parent.entity()).is_none());
+        assert!(scene.neighbors_of(other).is_empty());
+        assert_eq!(scene.parent_of(child), Some(scene.root()));
+        assert_eq!(scene.children_of(scene.root()), vec![other, child]);
+    }
+
+    #[test]
+    fn test_destroy_entity_removes_sub_scene_node() {
+        let world = World::new();
+        let scene = world.root_scene();
+        let sub_scene = scene.create_sub_scene();
+
+        let node = sub_scene.spawn(Marker);
+        let other = sub_scene.spawn(Marker);
+        sub_scene.add_relationship(other, node, Targets);
+        // an entity can be in several scenes at once
+        let shared = scene.spawn(Marker);
+        let shared_in_sub = sub_scene.add_node(shared.entity());
+
+        world.destroy_entity(node.entity());
+        assert!(!sub_scene.contains(node));
+        assert!(sub_scene.neighbors_of(other).is_empty());
+
+        world.destroy_entity(shared.entity());
+        assert!(!scene.contains(shared));
+        assert!(!sub_scene.contains(shared_in_sub));
+        assert_eq!(sub_scene.children_of(sub_scene.root()), vec![other]);
+    }
+
+    #[derive(Component)]
+    struct Health(u32);
+
+    struct Targets;
+    impl Relationship for Targets {}
+
+    struct Owns(u32);
+    impl Relationship for Owns {}
+
+    #[test]
+    fn test_relationship_queries() {
+        let world = World::new();
+        let scene = world.root_scene();
+
+        let player = scene.spawn(Health(10));
+        let enemy1 = scene.spawn(Health(5));
+        let enemy2 = scene.spawn(Health(7));
+        let item = scene.spawn(Marker);
+
+        scene.add_relationship(player, enemy1, Targets);
+        scene.add_relationship(player, enemy2, Targets);
+        scene.add_relationship(player, item, Owns(3));
+        scene.add_relationship(enemy2, player, Targets);
+
+        let mut targets = scene.related::<Targets>(player);
+        targets.sort();
+        assert_eq!(targets, vec![enemy1, enemy2]);
+
+        let owned = scene.outgoing_relationships::<Owns>(player);
+        assert_eq!(owned.len(), 1);
+        assert_eq!((owned[0].0, owned[0].1 .0), (item, 3));
+
+        let targeted_by = scene.incoming_relationships::<Targets>(player);
+        assert_eq!(targeted_by.len(), 1);
+        assert_eq!(targeted_by[0].0, enemy2);
+
+        assert_eq!(scene.neighbors_of(enemy2), vec![player]);
+        assert!(scene.get_relationship::<Owns>(player, item).is_some());
+        assert!(scene.get_relationship::<Targets>(player, item).is_none());
+        assert_eq!(scene.relationships::<Targets>().len(), 3);
+        drop(scene);
+
+        // damage everything the player targets, both enemies living in the same column
+        let query = SceneQuery::<&mut Health>::new(&world);
+        for (_, _, mut health) in query.get_related::<Targets>(player.entity()) {
+            health.0 -= 1;
+        }
+        // the item doesn't match the query
+        assert!(query.get_related::<Owns>(player.entity()).is_empty());
+
+        let attackers = query.get_related_incoming::<Targets>(player.entity());
+        assert_eq!(attackers.len(), 1);
+        assert_eq!(attackers[0].2 .0, 6);
+        drop(attackers);
+        drop(query);
+
+        assert_eq!(world.get_component::<Health>(enemy1.entity()).unwrap().0, 4);
+        assert_eq!(
+            world.get_component::<Health>(player.entity()).unwrap().0,
+            10
+        );
+    }
 }
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use weaver_ecs_macros::Resource;
+
+use crate::{
+    self as weaver_ecs,
+    component::{ComponentId, Resources},
+    entity::Entity,
+    scene::Scene,
+    storage::{ArchetypeId, Storage},
+};
+
+#[derive(Debug, Clone)]
+pub struct ColumnStats {
+    pub id: ComponentId,
+    pub name: String,
+    pub allocated_bytes: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchetypeStats {
+    pub id: ArchetypeId,
+    // one per component in the archetype's signature, in signature order
+    pub columns: Vec<ColumnStats>,
+    pub entities: usize,
+}
+
+impl ArchetypeStats {
+    pub fn allocated_bytes(&self) -> usize {
+        self.columns
+            .iter()
+            .map(|column| column.allocated_bytes)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ComponentStats {
+    pub id: ComponentId,
+    pub name: String,
+    pub instances: usize,
+    // the number of archetypes with a column for it, empty or not
+    pub archetypes: usize,
+    pub allocated_bytes: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResourceStats {
+    pub name: &'static str,
+    // the size of the value itself, not of anything it points to
+    pub size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SceneStats {
+    // the entity holding the `Scene` component
+    pub entity: Entity,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+// a snapshot of what a world holds and roughly how much memory it takes up
+#[derive(Resource, Debug, Clone, Default)]
+pub struct WorldStats {
+    pub entities: usize,
+    // every archetype in creation order, including ones nothing lives in anymore
+    pub archetypes: Vec<ArchetypeStats>,
+    // sorted by name
+    pub components: Vec<ComponentStats>,
+    // sorted by name
+    pub resources: Vec<ResourceStats>,
+    pub scenes: Vec<SceneStats>,
+}
+
+impl WorldStats {
+    pub(crate) fn collect(entities: usize, storage: &Storage, resources: &Resources) -> Self {
+        let mut components: HashMap<ComponentId, ComponentStats> = HashMap::new();
+        let mut archetypes = Vec::with_capacity(storage.archetypes().len());
+        let mut scenes = Vec::new();
+
+        for archetype in storage.archetypes() {
+            let mut columns = Vec::with_capacity(archetype.component_ids().len());
+            for &id in archetype.component_ids() {
+                let column = archetype.get_column_by_id(id).unwrap();
+                let name = component_name(storage, id);
+                let allocated_bytes = column.allocated_bytes();
+
+                let component = components.entry(id).or_insert_with(|| ComponentStats {
+                    id,
+                    name: name.clone(),
+                    instances: 0,
+                    archetypes: 0,
+                    allocated_bytes: 0,
+                });
+                component.instances += column.len();
+                component.archetypes += 1;
+                component.allocated_bytes += allocated_bytes;
+
+                if let Some(column_scenes) = column.as_slice::<Scene>() {
+                    for (scene, entity) in column_scenes.iter().zip(archetype.entity_iter()) {
+                        let graph = scene.graph().read();
+                        scenes.push(SceneStats {
+                            entity,
+                            nodes: graph.node_count(),
+                            edges: graph.edge_count(),
+                        });
+                    }
+                }
+
+                columns.push(ColumnStats {
+                    id,
+                    name,
+                    allocated_bytes,
+                });
+            }
+
+            archetypes.push(ArchetypeStats {
+                id: archetype.id(),
+                columns,
+                entities: archetype.len(),
+            });
+        }
+
+        let mut components = components.into_values().collect::<Vec<_>>();
+        components.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut resources = resources
+            .iter()
+            .map(|resource| {
+                let resource = resource.read();
+                ResourceStats {
+                    name: resource.resource_type_name(),
+                    size: std::mem::size_of_val(&**resource),
+                }
+            })
+            .collect::<Vec<_>>();
+        resources.sort_by_key(|resource| resource.name);
+
+        Self {
+            entities,
+            archetypes,
+            components,
+            resources,
+            scenes,
+        }
+    }
+
+    // archetypes left behind by entities moving to other signatures
+    pub fn empty_archetypes(&self) -> usize {
+        self.archetypes
+            .iter()
+            .filter(|archetype| archetype.entities == 0)
+            .count()
+    }
+
+    pub fn component_bytes(&self) -> usize {
+        self.components
+            .iter()
+            .map(|component| component.allocated_bytes)
+            .sum()
+    }
+
+    pub fn resource_bytes(&self) -> usize {
+        self.resources.iter().map(|resource| resource.size).sum()
+    }
+}
+
+fn component_name(storage: &Storage, id: ComponentId) -> String {
+    if let Some(info) = storage.get_dynamic_component_info(id) {
+        return info.name().to_owned();
+    }
+    storage
+        .get_component_info(id)
+        .map(|info| info.type_name().to_owned())
+        .unwrap_or_else(|| format!("{id:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use weaver_ecs_macros::Component;
+
+    use crate::world::World;
+
+    use super::*;
+
+    #[derive(Component)]
+    struct Position(#[allow(dead_code)] [f32; 3]);
+
+    #[derive(Component)]
+    struct Velocity;
+
+    #[derive(Resource)]
+    struct Gravity(#[allow(dead_code)] f32);
+
+    #[test]
+    fn test_world_stats() {
+        let world = World::new();
+        world.insert_resource(Gravity(9.8));
+        let scene = world.root_scene();
+        let moving = scene.spawn((Position([0.0; 3]), Velocity));
+        scene.spawn(Position([1.0; 3]));
+        drop(scene);
+        world.remove_component::<Velocity>(moving.entity());
+
+        let stats = world.stats();
+        assert_eq!(stats.entities, world.entity_count());
+
+        let position = stats
+            .components
+            .iter()
+            .find(|component| component.id == ComponentId::of::<Position>())
+            .unwrap();
+        assert_eq!(position.instances, 2);
+        assert_eq!(position.archetypes, 2);
+        assert!(position.allocated_bytes >= 2 * std::mem::size_of::<Position>());
+
+        // the moved entity left its old archetype empty
+        assert_eq!(stats.empty_archetypes(), 2);
+        let archetype = stats
+            .archetypes
+            .iter()
+            .find(|archetype| archetype.columns.len() == 2)
+            .unwrap();
+        assert_eq!(archetype.entities, 0);
+
+        let gravity = stats
+            .resources
+            .iter()
+            .find(|resource| resource.name.ends_with("Gravity"))
+            .unwrap();
+        assert_eq!(gravity.size, 4);
+
+        assert_eq!(stats.scenes.len(), 1);
+        assert_eq!(stats.scenes[0].entity, world.root_scene_entity());
+        assert_eq!(stats.scenes[0].nodes, 3);
+        assert_eq!(stats.scenes[0].edges, 0);
+    }
+}
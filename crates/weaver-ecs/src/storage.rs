@@ -1,40 +1,75 @@
 use std::{
     any::TypeId,
     collections::{HashMap, HashSet},
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
-use weaver_util::lock::{ArcRead, ArcWrite, SharedLock};
+use weaver_util::{
+    lock::{ArcRead, ArcWrite, SharedLock},
+    prelude::{bail, Result},
+};
 
-use crate::prelude::Bundle;
+use crate::{
+    blob::BlobVec,
+    dynamic::{DynamicComponent, DynamicComponentInfo, DynamicMut, DynamicRef, DynamicValue},
+    hooks::{ComponentEvent, LifecycleEvent},
+    prelude::{Bundle, ComponentId, ComponentInfo, ComponentTicks, Tick},
+};
 
 use super::{component::Component, entity::Entity};
 
 pub struct Data {
-    type_id: TypeId,
+    id: ComponentId,
     data: Box<dyn Component>,
+    ticks: ComponentTicks,
 }
 
 impl Data {
-    pub fn new<T: Component>(data: T) -> Self {
+    pub fn new<T: Component>(data: T, tick: Tick) -> Self {
         Self {
-            type_id: TypeId::of::<T>(),
+            id: ComponentId::of::<T>(),
             data: Box::new(data),
+            ticks: ComponentTicks::new(tick),
         }
     }
 
-    pub fn new_dynamic(data: Box<dyn Component>) -> Self {
+    pub fn new_dynamic(data: Box<dyn Component>, tick: Tick) -> Self {
+        // runtime-defined components carry their own id
+        let id = match (*data).downcast_ref::<DynamicComponent>() {
+            Some(dynamic) => dynamic.id(),
+            None => ComponentId::Static((*data).as_any().type_id()),
+        };
+
         Self {
-            type_id: (*data).as_any().type_id(),
+            id,
             data,
+            ticks: ComponentTicks::new(tick),
         }
     }
 
-    pub fn type_id(&self) -> TypeId {
-        self.type_id
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    // the storage this data goes into
+    pub fn component_info(&self) -> ComponentInfo {
+        self.data.component_info().with_id(self.id)
     }
 
     pub fn is<T: Component>(&self) -> bool {
-        self.type_id == TypeId::of::<T>()
+        self.id == ComponentId::of::<T>()
+    }
+
+    pub fn ticks(&self) -> ComponentTicks {
+        self.ticks
+    }
+
+    pub fn ticks_mut(&mut self) -> &mut ComponentTicks {
+        &mut self.ticks
     }
 
     pub fn get_data(&self) -> &dyn Component {
@@ -167,18 +202,203 @@ impl<T> SparseSet<T> {
     }
 }
 
+// moves a boxed component into `f` as a raw pointer, freeing the box allocation afterwards
+// SAFETY: `f` must take ownership of the pointed-to value
+unsafe fn with_unboxed(data: Box<dyn Component>, f: impl FnOnce(*mut u8)) {
+    let layout = std::alloc::Layout::for_value(&*data);
+    let ptr = Box::into_raw(data) as *mut u8;
+    f(ptr);
+    if layout.size() != 0 {
+        std::alloc::dealloc(ptr, layout);
+    }
+}
+
+// components of a single type stored contiguously by value, one per archetype row
+pub struct Column {
+    info: ComponentInfo,
+    data: BlobVec,
+    ticks: Vec<ComponentTicks>,
+    // set for runtime-defined components, whose rows hold their fields rather than a Rust value
+    dynamic: Option<Arc<DynamicComponentInfo>>,
+}
+
+impl Column {
+    pub fn new(info: ComponentInfo) -> Self {
+        Self {
+            info,
+            data: BlobVec::new(info.layout(), info.drop()),
+            ticks: Vec::new(),
+            dynamic: None,
+        }
+    }
+
+    pub fn new_dynamic(id: ComponentId, dynamic: Arc<DynamicComponentInfo>) -> Self {
+        Self {
+            dynamic: Some(dynamic.clone()),
+            ..Self::new(ComponentInfo::dynamic(id, dynamic.layout()))
+        }
+    }
+
+    pub fn info(&self) -> &ComponentInfo {
+        &self.info
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    // what the column has allocated for values and ticks; heap data owned by the values
+    // themselves isn't counted
+    pub fn allocated_bytes(&self) -> usize {
+        self.data.allocated_bytes() + self.ticks.capacity() * std::mem::size_of::<ComponentTicks>()
+    }
+
+    fn push(&mut self, data: Data) {
+        debug_assert_eq!(data.id(), self.info.id());
+        self.ticks.push(data.ticks());
+        if self.dynamic.is_some() {
+            let component = self.dynamic_data(data);
+            // SAFETY: the bytes have the column's layout and hold plain data
+            unsafe { self.data.push(component.as_ptr()) };
+            return;
+        }
+        // SAFETY: the data has the column's type and ownership moves into the blob
+        unsafe { with_unboxed(data.into_data(), |ptr| self.data.push(ptr)) };
+    }
+
+    fn replace(&mut self, row: usize, data: Data) {
+        debug_assert_eq!(data.id(), self.info.id());
+        assert!(row < self.len(), "row out of bounds");
+        self.ticks[row] = data.ticks();
+        if self.dynamic.is_some() {
+            let component = self.dynamic_data(data);
+            // SAFETY: see `push`, and the row is in bounds
+            unsafe { self.data.replace_unchecked(row, component.as_ptr()) };
+            return;
+        }
+        // SAFETY: the row is in bounds and the data has the column's type
+        unsafe {
+            with_unboxed(data.into_data(), |ptr| {
+                self.data.replace_unchecked(row, ptr)
+            })
+        };
+    }
+
+    // the value whose bytes go into a dynamic column; they're copied, so it can be dropped after
+    fn dynamic_data(&self, data: Data) -> Box<DynamicComponent> {
+        let Ok(component) = data.into_data().downcast::<DynamicComponent>() else {
+            panic!("only dynamic components go into a dynamic component column");
+        };
+        assert!(
+            self.dynamic.as_deref() == Some(&**component.info()),
+            "{} doesn't have the layout it was registered with",
+            component.name()
+        );
+        component
+    }
+
+    // moves the component at `row` to the end of `other`, which must store the same type
+    fn swap_remove_into(&mut self, row: usize, other: &mut Column) {
+        debug_assert_eq!(self.info.id(), other.info.id());
+        assert!(row < self.len(), "row out of bounds");
+        // SAFETY: the row is in bounds and the value is moved, not copied
+        unsafe {
+            other.data.push(self.data.get_unchecked(row));
+            self.data.swap_remove_and_forget_unchecked(row);
+        }
+        other.ticks.push(self.ticks.swap_remove(row));
+    }
+
+    fn swap_remove_and_drop(&mut self, row: usize) {
+        assert!(row < self.len(), "row out of bounds");
+        // SAFETY: the row is in bounds
+        unsafe { self.data.swap_remove_and_drop_unchecked(row) };
+        self.ticks.swap_remove(row);
+    }
+
+    fn swap_remove(&mut self, row: usize) -> Data {
+        assert!(row < self.len(), "row out of bounds");
+        // SAFETY: the row is in bounds and the value is moved out before its slot is reused
+        let data = unsafe {
+            let ptr = self.data.get_unchecked(row);
+            let data = match &self.dynamic {
+                Some(dynamic) => Box::new(DynamicComponent::copy_from(
+                    self.info.id(),
+                    dynamic.clone(),
+                    ptr,
+                )),
+                None => self.info.read_boxed(ptr),
+            };
+            self.data.swap_remove_and_forget_unchecked(row);
+            data
+        };
+
+        Data {
+            id: self.info.id(),
+            data,
+            ticks: self.ticks.swap_remove(row),
+        }
+    }
+
+    pub fn is<T: Component>(&self) -> bool {
+        self.info.type_id() == TypeId::of::<T>()
+    }
+
+    pub fn get<T: Component>(&self, row: usize) -> Option<&T> {
+        self.as_slice::<T>()?.get(row)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, row: usize) -> Option<&mut T> {
+        self.as_mut_slice::<T>()?.get_mut(row)
+    }
+
+    pub fn get_ticks(&self, row: usize) -> Option<ComponentTicks> {
+        self.ticks.get(row).copied()
+    }
+
+    pub fn get_ticks_mut(&mut self, row: usize) -> Option<&mut ComponentTicks> {
+        self.ticks.get_mut(row)
+    }
+
+    pub fn ticks(&self) -> &[ComponentTicks] {
+        &self.ticks
+    }
+
+    pub fn as_slice<T: Component>(&self) -> Option<&[T]> {
+        if !self.is::<T>() {
+            return None;
+        }
+
+        // SAFETY: the column stores `len` initialized values of type T with T's layout
+        Some(unsafe { std::slice::from_raw_parts(self.data.as_ptr().cast::<T>(), self.len()) })
+    }
+
+    pub fn as_mut_slice<T: Component>(&mut self) -> Option<&mut [T]> {
+        if !self.is::<T>() {
+            return None;
+        }
+
+        // SAFETY: see `as_slice`
+        Some(unsafe { std::slice::from_raw_parts_mut(self.data.as_ptr().cast::<T>(), self.len()) })
+    }
+}
+
 pub struct ColumnRef {
-    column: ArcRead<SparseSet<Data>>,
+    column: ArcRead<Column>,
 }
 
 impl ColumnRef {
-    pub fn new(column: ArcRead<SparseSet<Data>>) -> Self {
+    pub fn new(column: ArcRead<Column>) -> Self {
         Self { column }
     }
 }
 
 impl std::ops::Deref for ColumnRef {
-    type Target = SparseSet<Data>;
+    type Target = Column;
 
     fn deref(&self) -> &Self::Target {
         &self.column
@@ -186,17 +406,17 @@ impl std::ops::Deref for ColumnRef {
 }
 
 pub struct ColumnMut {
-    column: ArcWrite<SparseSet<Data>>,
+    column: ArcWrite<Column>,
 }
 
 impl ColumnMut {
-    pub fn new(column: ArcWrite<SparseSet<Data>>) -> Self {
+    pub fn new(column: ArcWrite<Column>) -> Self {
         Self { column }
     }
 }
 
 impl std::ops::Deref for ColumnMut {
-    type Target = SparseSet<Data>;
+    type Target = Column;
 
     fn deref(&self) -> &Self::Target {
         &self.column
@@ -209,103 +429,140 @@ impl std::ops::DerefMut for ColumnMut {
     }
 }
 
+// cached transitions to the archetypes reached by adding or removing a single component type
 #[derive(Default)]
-pub struct Archetype {
-    columns: HashMap<TypeId, SharedLock<SparseSet<Data>>>,
-    entities: HashSet<Entity>,
+pub struct ArchetypeEdges {
+    add: HashMap<ComponentId, ArchetypeId>,
+    remove: HashMap<ComponentId, ArchetypeId>,
 }
 
-impl Archetype {
-    pub fn new() -> Self {
-        Self::default()
+impl ArchetypeEdges {
+    pub fn get_add(&self, id: ComponentId) -> Option<ArchetypeId> {
+        self.add.get(&id).copied()
     }
 
-    pub fn type_ids(&self) -> Vec<TypeId> {
-        self.columns.keys().copied().collect::<Vec<_>>()
+    pub fn get_remove(&self, id: ComponentId) -> Option<ArchetypeId> {
+        self.remove.get(&id).copied()
     }
+}
 
-    pub fn insert(&mut self, entity: Entity, data: Data) {
-        let type_id = data.type_id();
+pub struct Archetype {
+    id: ArchetypeId,
+    component_ids: Box<[ComponentId]>,
+    columns: HashMap<ComponentId, SharedLock<Column>>,
+    // row -> entity, shared by every column
+    entities: Vec<Entity>,
+    edges: ArchetypeEdges,
+}
 
-        self.entities.insert(entity);
+impl Archetype {
+    // `component_ids` must be sorted and deduplicated, and every one must have registered info
+    pub fn new(
+        id: ArchetypeId,
+        component_ids: Box<[ComponentId]>,
+        components: &HashMap<ComponentId, ComponentInfo>,
+        dynamic_components: &[Arc<DynamicComponentInfo>],
+    ) -> Self {
+        let columns = component_ids
+            .iter()
+            .map(|id| {
+                let column = match id {
+                    ComponentId::Dynamic(index) => {
+                        let info = dynamic_components
+                            .get(*index as usize)
+                            .expect("dynamic component isn't registered with this world");
+                        Column::new_dynamic(*id, info.clone())
+                    }
+                    ComponentId::Static(_) => Column::new(components[id]),
+                };
+                (*id, SharedLock::new(column))
+            })
+            .collect();
 
-        self.columns[&type_id]
-            .write()
-            .insert(entity.as_usize(), data);
+        Self {
+            id,
+            component_ids,
+            columns,
+            entities: Vec::new(),
+            edges: ArchetypeEdges::default(),
+        }
     }
 
-    pub fn remove(&mut self, entity: Entity) -> Vec<Data> {
-        self.entities.remove(&entity);
-
-        self.columns
-            .values()
-            .filter_map(|column| column.write().remove(entity.as_usize()))
-            .collect()
+    pub fn id(&self) -> ArchetypeId {
+        self.id
     }
 
-    #[inline]
-    pub fn get<T: Component>(&self, entity: Entity) -> Option<DataRef> {
-        self.get_by_type_id(entity, TypeId::of::<T>())
+    pub fn component_ids(&self) -> &[ComponentId] {
+        &self.component_ids
     }
 
-    #[inline]
-    pub fn get_mut<T: Component>(&self, entity: Entity) -> Option<DataMut> {
-        self.get_by_type_id_mut(entity, TypeId::of::<T>())
+    pub fn edges(&self) -> &ArchetypeEdges {
+        &self.edges
     }
 
-    pub fn get_by_type_id(&self, entity: Entity, type_id: TypeId) -> Option<DataRef> {
-        if self.columns[&type_id].read().contains(entity.as_usize()) {
-            Some(DataRef::new(entity, self.columns[&type_id].read()))
-        } else {
-            None
-        }
+    fn push_entity(&mut self, entity: Entity) -> usize {
+        self.entities.push(entity);
+        self.entities.len() - 1
     }
 
-    pub fn get_by_type_id_mut(&self, entity: Entity, type_id: TypeId) -> Option<DataMut> {
-        if self.columns[&type_id].read().contains(entity.as_usize()) {
-            Some(DataMut::new(entity, self.columns[&type_id].write()))
-        } else {
-            None
-        }
+    // returns the entity that was moved into `row`, if any
+    fn swap_remove_entity(&mut self, row: usize) -> Option<Entity> {
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
     }
 
     #[inline]
     pub fn get_column<T: Component>(&self) -> Option<ColumnRef> {
-        self.get_column_by_type_id(TypeId::of::<T>())
+        self.get_column_by_id(ComponentId::of::<T>())
     }
 
     #[inline]
     pub fn get_column_mut<T: Component>(&self) -> Option<ColumnMut> {
-        self.get_column_by_type_id_mut(TypeId::of::<T>())
+        self.get_column_by_id_mut(ComponentId::of::<T>())
     }
 
-    pub fn get_column_by_type_id(&self, type_id: TypeId) -> Option<ColumnRef> {
-        Some(ColumnRef::new(self.columns[&type_id].read()))
+    pub fn get_column_by_id(&self, id: ComponentId) -> Option<ColumnRef> {
+        Some(ColumnRef::new(self.columns.get(&id)?.read()))
     }
 
-    pub fn get_column_by_type_id_mut(&self, type_id: TypeId) -> Option<ColumnMut> {
-        Some(ColumnMut::new(self.columns[&type_id].write()))
+    pub fn get_column_by_id_mut(&self, id: ComponentId) -> Option<ColumnMut> {
+        Some(ColumnMut::new(self.columns.get(&id)?.write()))
     }
 
-    pub fn has_component<T: Component>(&self, entity: Entity) -> bool {
-        self.has_component_by_type_id(entity, TypeId::of::<T>())
+    // locks the column once so its rows can be shared by many `Ref`s
+    pub fn lock_column<T: Component>(&self) -> Option<SharedColumnRead> {
+        self.lock_column_by_id(ComponentId::of::<T>())
+    }
+
+    // locks the column once so its rows can be shared by many `Mut`s
+    pub fn lock_column_mut<T: Component>(&self) -> Option<SharedColumnWrite> {
+        self.lock_column_by_id_mut(ComponentId::of::<T>())
     }
 
-    pub fn has_component_by_type_id(&self, entity: Entity, type_id: TypeId) -> bool {
-        self.columns
-            .get(&type_id)
-            .map(|c| c.read().contains(entity.as_usize()))
-            .unwrap_or(false)
+    pub fn lock_column_by_id(&self, id: ComponentId) -> Option<SharedColumnRead> {
+        let column = self.columns.get(&id)?;
+        Some(Arc::new(LockedColumn::from_read(column.read())))
     }
 
-    pub fn contains_component_by_type_id(&self, type_id: TypeId) -> bool {
-        self.columns.contains_key(&type_id)
+    pub fn lock_column_by_id_mut(&self, id: ComponentId) -> Option<SharedColumnWrite> {
+        let column = self.columns.get(&id)?;
+        Some(Arc::new(LockedColumn::from_write(column.write())))
     }
 
-    pub fn contains_entity(&self, entity: Entity) -> bool {
-        self.columns
-            .values()
-            .any(|column| column.read().contains(entity.as_usize()))
+    pub fn get_ticks<T: Component>(&self, row: usize) -> Option<ComponentTicks> {
+        self.get_ticks_by_id(ComponentId::of::<T>(), row)
+    }
+
+    pub fn get_ticks_by_id(&self, id: ComponentId, row: usize) -> Option<ComponentTicks> {
+        self.columns.get(&id)?.read().get_ticks(row)
+    }
+
+    pub fn contains_component<T: Component>(&self) -> bool {
+        self.contains_component_by_id(ComponentId::of::<T>())
+    }
+
+    pub fn contains_component_by_id(&self, id: ComponentId) -> bool {
+        self.columns.contains_key(&id)
     }
 
     pub fn len(&self) -> usize {
@@ -316,23 +573,20 @@ impl Archetype {
         self.entities.is_empty()
     }
 
-    pub fn contains_all_types(&self, type_ids: &[TypeId]) -> bool {
-        type_ids
-            .iter()
-            .all(|type_id| self.columns.contains_key(type_id))
+    pub fn contains_all(&self, ids: &[ComponentId]) -> bool {
+        ids.iter().all(|id| self.columns.contains_key(id))
     }
 
-    pub fn contains_any_type(&self, type_ids: &[TypeId]) -> bool {
-        type_ids
-            .iter()
-            .any(|type_id| self.columns.contains_key(type_id))
+    pub fn contains_any(&self, ids: &[ComponentId]) -> bool {
+        ids.iter().any(|id| self.columns.contains_key(id))
     }
 
-    pub fn exclusively_contains_types(&self, type_ids: &[TypeId]) -> bool {
-        type_ids
-            .iter()
-            .all(|type_id| self.columns.contains_key(type_id))
-            && self.columns.len() == type_ids.len()
+    pub fn exclusively_contains(&self, ids: &[ComponentId]) -> bool {
+        ids.iter().all(|id| self.columns.contains_key(id)) && self.columns.len() == ids.len()
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
     }
 
     pub fn entity_iter(&self) -> impl Iterator<Item = Entity> + '_ {
@@ -340,68 +594,129 @@ impl Archetype {
     }
 }
 
-pub struct DataRef {
-    entity: Entity,
-    column: ArcRead<SparseSet<Data>>,
+// a locked column whose rows are read through raw pointers, so that a single lock
+// can back every `Ref` or `Mut` handed out for one archetype
+pub struct LockedColumn<G> {
+    _guard: G,
+    type_id: TypeId,
+    id: ComponentId,
+    dynamic: Option<Arc<DynamicComponentInfo>>,
+    item_size: usize,
+    data: NonNull<u8>,
+    ticks: NonNull<ComponentTicks>,
+    len: usize,
+    // one bit per row currently borrowed by a `Mut`, only used for write locks
+    borrowed: Box<[AtomicU64]>,
 }
 
-impl DataRef {
-    pub fn new(entity: Entity, column: ArcRead<SparseSet<Data>>) -> Self {
-        Self { entity, column }
-    }
+// SAFETY: the pointers are only dereferenced while the guard keeps the column locked
+unsafe impl<G: Send> Send for LockedColumn<G> {}
+unsafe impl<G: Sync> Sync for LockedColumn<G> {}
 
-    pub fn entity(this: &Self) -> Entity {
-        this.entity
+impl LockedColumn<ArcRead<Column>> {
+    pub fn from_read(guard: ArcRead<Column>) -> Self {
+        Self {
+            type_id: guard.info.type_id(),
+            id: guard.info.id(),
+            dynamic: guard.dynamic.clone(),
+            item_size: guard.info.layout().pad_to_align().size(),
+            // SAFETY: the data pointer of a BlobVec is never null
+            data: unsafe { NonNull::new_unchecked(guard.data.as_ptr()) },
+            ticks: NonNull::from(guard.ticks.as_slice()).cast(),
+            len: guard.len(),
+            borrowed: Box::default(),
+            _guard: guard,
+        }
     }
 }
 
-impl std::ops::Deref for DataRef {
-    type Target = Data;
+impl LockedColumn<ArcWrite<Column>> {
+    pub fn from_write(mut guard: ArcWrite<Column>) -> Self {
+        Self {
+            type_id: guard.info.type_id(),
+            id: guard.info.id(),
+            dynamic: guard.dynamic.clone(),
+            item_size: guard.info.layout().pad_to_align().size(),
+            // SAFETY: the data pointer of a BlobVec is never null
+            data: unsafe { NonNull::new_unchecked(guard.data.as_ptr()) },
+            ticks: NonNull::from(guard.ticks.as_mut_slice()).cast(),
+            len: guard.len(),
+            borrowed: (0..guard.len().div_ceil(64))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            _guard: guard,
+        }
+    }
 
-    fn deref(&self) -> &Self::Target {
-        self.column.get(self.entity.as_usize()).unwrap()
+    // returns false if the row is already borrowed
+    pub(crate) fn borrow_row(&self, row: usize) -> bool {
+        let bit = 1 << (row % 64);
+        self.borrowed[row / 64].fetch_or(bit, Ordering::Acquire) & bit == 0
     }
-}
 
-pub struct DataMut {
-    entity: Entity,
-    column: ArcWrite<SparseSet<Data>>,
+    pub(crate) fn release_row(&self, row: usize) {
+        self.borrowed[row / 64].fetch_and(!(1 << (row % 64)), Ordering::Release);
+    }
 }
 
-impl DataMut {
-    pub fn new(entity: Entity, column: ArcWrite<SparseSet<Data>>) -> Self {
-        Self { entity, column }
+impl<G> LockedColumn<G> {
+    pub fn is<T: Component>(&self) -> bool {
+        self.type_id == TypeId::of::<T>()
     }
 
-    pub fn entity(this: &Self) -> Entity {
-        this.entity
+    pub fn len(&self) -> usize {
+        self.len
     }
-}
 
-impl std::ops::Deref for DataMut {
-    type Target = Data;
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 
-    fn deref(&self) -> &Self::Target {
-        self.column.get(self.entity.as_usize()).unwrap()
+    pub fn get_ticks(&self, row: usize) -> Option<ComponentTicks> {
+        // SAFETY: the row is in bounds
+        (row < self.len).then(|| unsafe { *self.ticks_ptr(row) })
     }
-}
 
-impl std::ops::DerefMut for DataMut {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.column.get_mut(self.entity.as_usize()).unwrap()
+    // SAFETY: `row` must be in bounds and the column must store `T`
+    unsafe fn value_ptr<T>(&self, row: usize) -> *mut T {
+        self.data.as_ptr().cast::<T>().add(row)
+    }
+
+    // a view of a dynamic component's row, valid for as long as the column stays locked
+    pub(crate) fn dynamic_value(&self, row: usize) -> Option<DynamicValue> {
+        let dynamic = self.dynamic.clone()?;
+        if row >= self.len {
+            return None;
+        }
+        // SAFETY: the row is in bounds, and holds a value laid out by the column's info
+        unsafe {
+            let data = NonNull::new_unchecked(self.data.as_ptr().add(row * self.item_size));
+            Some(DynamicValue::new(self.id, dynamic, data))
+        }
+    }
+
+    // SAFETY: `row` must be in bounds
+    pub(crate) unsafe fn ticks_ptr(&self, row: usize) -> *mut ComponentTicks {
+        self.ticks.as_ptr().add(row)
     }
 }
 
+pub type SharedColumnRead = Arc<LockedColumn<ArcRead<Column>>>;
+pub type SharedColumnWrite = Arc<LockedColumn<ArcWrite<Column>>>;
+
 pub struct Ref<T: Component> {
     entity: Entity,
-    column: ArcRead<SparseSet<Data>>,
+    row: usize,
+    column: SharedColumnRead,
     _phantom: std::marker::PhantomData<T>,
 }
 
 impl<T: Component> Ref<T> {
-    pub fn new(entity: Entity, column: ArcRead<SparseSet<Data>>) -> Self {
+    pub fn new(entity: Entity, row: usize, column: SharedColumnRead) -> Self {
+        assert!(column.is::<T>() && row < column.len());
         Self {
             entity,
+            row,
             column,
             _phantom: std::marker::PhantomData,
         }
@@ -410,31 +725,45 @@ impl<T: Component> Ref<T> {
     pub fn entity(this: &Self) -> Entity {
         this.entity
     }
+
+    pub fn ticks(this: &Self) -> ComponentTicks {
+        // SAFETY: checked in `new`
+        unsafe { *this.column.ticks_ptr(this.row) }
+    }
 }
 
 impl<T: Component> std::ops::Deref for Ref<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        self.column
-            .get(self.entity.as_usize())
-            .unwrap()
-            .downcast_ref()
-            .unwrap()
+        // SAFETY: checked in `new`, and the column can't change while we hold its lock
+        unsafe { &*self.column.value_ptr::<T>(self.row) }
     }
 }
 
+// several `Mut`s may share one write lock, as long as each of them points at a different row
 pub struct Mut<T: Component> {
     entity: Entity,
-    column: ArcWrite<SparseSet<Data>>,
+    row: usize,
+    column: SharedColumnWrite,
+    change_tick: Tick,
     _phantom: std::marker::PhantomData<T>,
 }
 
 impl<T: Component> Mut<T> {
-    pub fn new(entity: Entity, column: ArcWrite<SparseSet<Data>>) -> Self {
+    pub fn new(entity: Entity, row: usize, column: SharedColumnWrite, change_tick: Tick) -> Self {
+        assert!(column.is::<T>() && row < column.len());
+        assert!(
+            column.borrow_row(row),
+            "{} of {:?} is already mutably borrowed",
+            std::any::type_name::<T>(),
+            entity
+        );
         Self {
             entity,
+            row,
             column,
+            change_tick,
             _phantom: std::marker::PhantomData,
         }
     }
@@ -442,38 +771,88 @@ impl<T: Component> Mut<T> {
     pub fn entity(this: &Self) -> Entity {
         this.entity
     }
+
+    pub fn ticks(this: &Self) -> ComponentTicks {
+        // SAFETY: checked in `new`
+        unsafe { *this.column.ticks_ptr(this.row) }
+    }
 }
 
 impl<T: Component> std::ops::Deref for Mut<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        self.column
-            .get(self.entity.as_usize())
-            .unwrap()
-            .downcast_ref()
-            .unwrap()
+        // SAFETY: checked in `new`, and the column can't change while we hold its lock
+        unsafe { &*self.column.value_ptr::<T>(self.row) }
+    }
+}
+
+impl<T: Component> Drop for Mut<T> {
+    fn drop(&mut self) {
+        self.column.release_row(self.row);
     }
 }
 
 impl<T: Component> std::ops::DerefMut for Mut<T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.column
-            .get_mut(self.entity.as_usize())
-            .unwrap()
-            .downcast_mut()
-            .unwrap()
+        // SAFETY: see `deref`; no other `Mut` points at this row, as checked in `new`
+        unsafe {
+            (*self.column.ticks_ptr(self.row)).set_changed(self.change_tick);
+            &mut *self.column.value_ptr::<T>(self.row)
+        }
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct ArchetypeId(usize);
 
-#[derive(Default)]
+impl ArchetypeId {
+    pub const EMPTY: Self = Self(0);
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityLocation {
+    pub archetype_id: ArchetypeId,
+    pub row: usize,
+}
+
 pub struct Storage {
-    next_archetype_id: usize,
-    archetypes: HashMap<ArchetypeId, Archetype>,
-    entity_archetype: HashMap<Entity, ArchetypeId>,
+    components: HashMap<ComponentId, ComponentInfo>,
+    // indexed by `ComponentId::Dynamic`
+    dynamic_components: Vec<Arc<DynamicComponentInfo>>,
+    archetypes: Vec<Archetype>,
+    archetype_ids: HashMap<Box<[ComponentId]>, ArchetypeId>,
+    // indexed by entity id, holding the generation the location belongs to
+    entity_locations: Vec<Option<(u32, EntityLocation)>>,
+    removed: HashMap<ComponentId, Vec<Entity>>,
+    // components whose inserts, replaces and removes are recorded for hooks
+    watched: HashSet<ComponentId>,
+    lifecycle_events: Vec<LifecycleEvent>,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        let mut storage = Self {
+            components: HashMap::new(),
+            dynamic_components: Vec::new(),
+            archetypes: Vec::new(),
+            archetype_ids: HashMap::new(),
+            entity_locations: Vec::new(),
+            removed: HashMap::new(),
+            watched: HashSet::new(),
+            lifecycle_events: Vec::new(),
+        };
+
+        // entities without components live in the empty archetype
+        let empty = storage.get_or_create_archetype(Box::new([]));
+        debug_assert_eq!(empty, ArchetypeId::EMPTY);
+
+        storage
+    }
 }
 
 impl Storage {
@@ -481,126 +860,230 @@ impl Storage {
         Self::default()
     }
 
-    pub fn insert_components<T: Bundle>(&mut self, entity: Entity, bundle: T) {
-        let old_archetype_id = self.entity_archetype.remove(&entity);
-        let old_archetype = old_archetype_id.and_then(|id| self.archetypes.get_mut(&id));
-
-        let components = bundle.into_components();
-        let mut data = components
-            .into_iter()
-            .map(Data::new_dynamic)
-            .collect::<Vec<_>>();
-
-        if let Some(old_archetype) = old_archetype {
-            let mut old_data = old_archetype.remove(entity);
-
-            data.append(&mut old_data);
-        }
+    pub fn get_component_info(&self, id: ComponentId) -> Option<&ComponentInfo> {
+        self.components.get(&id)
+    }
 
+    // registering the same layout under a name again hands back the id it already has
+    pub fn register_dynamic_component(
+        &mut self,
+        info: DynamicComponentInfo,
+    ) -> Result<ComponentId> {
         let existing = self
-            .archetypes
-            .iter_mut()
-            .find(|(_, archetype)| {
-                archetype.exclusively_contains_types(
-                    &data
-                        .iter()
-                        .map(|component| component.type_id())
-                        .collect::<Vec<_>>(),
-                )
-            })
-            .map(|(id, archetype)| (archetype, *id));
-
-        let (archetype, archetype_id) = if let Some((archetype, existing_archetype_id)) = existing {
-            (archetype, existing_archetype_id)
-        } else {
-            let archetype_id = ArchetypeId(self.next_archetype_id);
-            self.next_archetype_id += 1;
+            .dynamic_components
+            .iter()
+            .position(|existing| existing.name() == info.name());
+        if let Some(index) = existing {
+            if *self.dynamic_components[index] != info {
+                bail!(
+                    "Dynamic component {} is already registered with another layout",
+                    info.name()
+                );
+            }
+            return Ok(ComponentId::Dynamic(index as u32));
+        }
 
-            let mut archetype = Archetype::new();
+        let id = ComponentId::Dynamic(self.dynamic_components.len() as u32);
+        self.components
+            .insert(id, ComponentInfo::dynamic(id, info.layout()));
+        self.dynamic_components.push(Arc::new(info));
+        Ok(id)
+    }
 
-            archetype.columns = data
-                .iter()
-                .map(|data| (data.type_id(), SharedLock::new(SparseSet::new())))
-                .collect();
+    pub fn dynamic_component_id(&self, name: &str) -> Option<ComponentId> {
+        self.dynamic_components
+            .iter()
+            .position(|info| info.name() == name)
+            .map(|index| ComponentId::Dynamic(index as u32))
+    }
 
-            self.archetypes.insert(archetype_id, archetype);
+    pub fn get_dynamic_component_info(
+        &self,
+        id: ComponentId,
+    ) -> Option<&Arc<DynamicComponentInfo>> {
+        match id {
+            ComponentId::Dynamic(index) => self.dynamic_components.get(index as usize),
+            ComponentId::Static(_) => None,
+        }
+    }
 
-            let archetype = self.archetypes.get_mut(&archetype_id).unwrap();
+    fn get_or_create_archetype(&mut self, component_ids: Box<[ComponentId]>) -> ArchetypeId {
+        if let Some(id) = self.archetype_ids.get(&component_ids) {
+            return *id;
+        }
 
-            (archetype, archetype_id)
-        };
+        let id = ArchetypeId(self.archetypes.len());
+        self.archetypes.push(Archetype::new(
+            id,
+            component_ids.clone(),
+            &self.components,
+            &self.dynamic_components,
+        ));
+        self.archetype_ids.insert(component_ids, id);
+        id
+    }
 
-        for data in data {
-            archetype.insert(entity, data);
+    fn archetype_with(&mut self, id: ArchetypeId, component_id: ComponentId) -> ArchetypeId {
+        if let Some(target) = self.archetypes[id.0].edges.get_add(component_id) {
+            return target;
         }
 
-        self.entity_archetype.insert(entity, archetype_id);
-
-        if let Some(old_archetype_id) = old_archetype_id {
-            if self.archetypes[&old_archetype_id].is_empty() {
-                self.archetypes.remove(&old_archetype_id);
-            }
+        let mut component_ids = self.archetypes[id.0].component_ids.to_vec();
+        if let Err(index) = component_ids.binary_search(&component_id) {
+            component_ids.insert(index, component_id);
         }
-    }
 
-    pub fn insert_component<T: Component>(&mut self, entity: Entity, component: T) {
-        self.insert_components(entity, component);
+        let target = self.get_or_create_archetype(component_ids.into_boxed_slice());
+        self.archetypes[id.0].edges.add.insert(component_id, target);
+        self.archetypes[target.0]
+            .edges
+            .remove
+            .insert(component_id, id);
+        target
     }
 
-    pub fn remove_component<T: Component>(&mut self, entity: Entity) -> Option<T> {
-        // remove the entity from its current archetype
-        let old_archetype_id = self.entity_archetype.remove(&entity)?;
-        let old_archetype = self.archetypes.get_mut(&old_archetype_id)?;
+    fn archetype_without(&mut self, id: ArchetypeId, component_id: ComponentId) -> ArchetypeId {
+        if let Some(target) = self.archetypes[id.0].edges.get_remove(component_id) {
+            return target;
+        }
 
-        let mut data = old_archetype.remove(entity);
+        let mut component_ids = self.archetypes[id.0].component_ids.to_vec();
+        component_ids.retain(|other| *other != component_id);
+
+        let target = self.get_or_create_archetype(component_ids.into_boxed_slice());
+        self.archetypes[id.0]
+            .edges
+            .remove
+            .insert(component_id, target);
+        self.archetypes[target.0].edges.add.insert(component_id, id);
+        target
+    }
+
+    // moves the entity's row into the `to` archetype and returns its new row
+    // `take` is handed every column `to` doesn't have and must swap-remove the row from it
+    fn move_entity(
+        &mut self,
+        entity: Entity,
+        to: ArchetypeId,
+        mut take: impl FnMut(&mut Column, usize),
+    ) -> usize {
+        let location = self.get_location(entity);
+        let new_row = self.archetypes[to.0].push_entity(entity);
+
+        if let Some(location) = location {
+            debug_assert_ne!(location.archetype_id, to);
+
+            let from = &self.archetypes[location.archetype_id.0];
+            let target = &self.archetypes[to.0];
+            for (id, column) in from.columns.iter() {
+                let mut column = column.write();
+                match target.columns.get(id) {
+                    Some(target_column) => {
+                        column.swap_remove_into(location.row, &mut target_column.write())
+                    }
+                    None => take(&mut column, location.row),
+                }
+            }
 
-        if old_archetype.is_empty() {
-            self.archetypes.remove(&old_archetype_id);
+            let from = &mut self.archetypes[location.archetype_id.0];
+            if let Some(swapped) = from.swap_remove_entity(location.row) {
+                self.set_row(swapped, location.row);
+            }
         }
 
-        self.entity_archetype.remove(&entity);
-
-        // remove the component from the data
-        let component = data.remove(data.iter().position(|data| data.is::<T>())?);
+        self.set_location(
+            entity,
+            EntityLocation {
+                archetype_id: to,
+                row: new_row,
+            },
+        );
 
-        // find the new archetype for the entity
-        let new_archetype = self
-            .archetypes
-            .iter_mut()
-            .find(|(_, archetype)| {
-                archetype.exclusively_contains_types(
-                    &data.iter().map(|data| data.type_id()).collect::<Vec<_>>(),
-                )
-            })
-            .map(|(id, archetype)| (archetype, *id));
+        new_row
+    }
 
-        let (new_archetype, new_archetype_id) =
-            if let Some((archetype, existing_archetype_id)) = new_archetype {
-                (archetype, existing_archetype_id)
-            } else {
-                let archetype_id = ArchetypeId(self.next_archetype_id);
-                self.next_archetype_id += 1;
+    pub fn insert_components<T: Bundle>(&mut self, entity: Entity, bundle: T, tick: Tick) {
+        let mut data = bundle
+            .into_components()
+            .into_iter()
+            .map(|component| Data::new_dynamic(component, tick))
+            .collect::<Vec<_>>();
 
-                let mut archetype = Archetype::new();
+        for data in &data {
+            self.components
+                .entry(data.id())
+                .or_insert_with(|| data.component_info());
+        }
 
-                archetype.columns = data
-                    .iter()
-                    .map(|data| (data.type_id(), SharedLock::new(SparseSet::new())))
-                    .collect();
+        let location = self.get_location(entity);
+        if location.is_none() && self.is_slot_occupied(entity) {
+            log::warn!("Tried to insert components on stale entity {:?}", entity);
+            return;
+        }
 
-                self.archetypes.insert(archetype_id, archetype);
+        let old_archetype_id = location
+            .map(|location| location.archetype_id)
+            .unwrap_or(ArchetypeId::EMPTY);
+
+        let mut new_ids = Vec::new();
+        let mut replaced_ids = Vec::new();
+        for data in &mut data {
+            let old_ticks = location.and_then(|location| {
+                self.archetypes[location.archetype_id.0].get_ticks_by_id(data.id(), location.row)
+            });
+
+            if let Some(old_ticks) = old_ticks {
+                // replacing a component keeps the tick it was originally added at
+                data.ticks_mut().added = old_ticks.added;
+                replaced_ids.push(data.id());
+            } else if !new_ids.contains(&data.id()) {
+                new_ids.push(data.id());
+            }
+        }
 
-                let archetype = self.archetypes.get_mut(&archetype_id).unwrap();
+        let new_archetype_id = match new_ids.as_slice() {
+            [] => old_archetype_id,
+            [id] => self.archetype_with(old_archetype_id, *id),
+            _ => {
+                let mut ids = self.archetypes[old_archetype_id.0].component_ids.to_vec();
+                ids.extend(new_ids.iter().copied());
+                ids.sort();
+                self.get_or_create_archetype(ids.into_boxed_slice())
+            }
+        };
 
-                (archetype, archetype_id)
-            };
+        let row = match location {
+            Some(location) if location.archetype_id == new_archetype_id => location.row,
+            // the new archetype is a superset of the old one, so nothing gets left behind
+            _ => self.move_entity(entity, new_archetype_id, |column, row| {
+                column.swap_remove_and_drop(row)
+            }),
+        };
 
+        let archetype = &self.archetypes[new_archetype_id.0];
         for data in data {
-            new_archetype.insert(entity, data);
+            let mut column = archetype.columns[&data.id()].write();
+            if row < column.len() {
+                column.replace(row, data);
+            } else {
+                column.push(data);
+            }
         }
 
-        self.entity_archetype.insert(entity, new_archetype_id);
+        for id in new_ids {
+            self.record(ComponentEvent::Insert, id, entity);
+        }
+        for id in replaced_ids {
+            self.record(ComponentEvent::Replace, id, entity);
+        }
+    }
 
+    pub fn insert_component<T: Component>(&mut self, entity: Entity, component: T, tick: Tick) {
+        self.insert_components(entity, component, tick);
+    }
+
+    pub fn remove_component<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let component = self.remove_component_by_id(entity, ComponentId::of::<T>())?;
         let Ok(component) = component.into_data().downcast::<T>() else {
             panic!("downcast failed: expected {}", std::any::type_name::<T>());
         };
@@ -608,88 +1091,184 @@ impl Storage {
         Some(*component)
     }
 
-    pub fn remove_entity(&mut self, entity: Entity) -> Option<Vec<Data>> {
-        let archetype_id = self.entity_archetype.get(&entity)?;
+    pub fn remove_component_by_id(&mut self, entity: Entity, id: ComponentId) -> Option<Data> {
+        let location = self.get_location(entity)?;
+        if !self.archetypes[location.archetype_id.0].contains_component_by_id(id) {
+            return None;
+        }
+
+        let new_archetype_id = self.archetype_without(location.archetype_id, id);
+
+        // the removed column is the only one the new archetype lacks
+        let mut component = None;
+        self.move_entity(entity, new_archetype_id, |column, row| {
+            component = Some(column.swap_remove(row));
+        });
+
+        self.removed.entry(id).or_default().push(entity);
+        self.record(ComponentEvent::Remove, id, entity);
 
-        let archetype = self.archetypes.get_mut(archetype_id)?;
+        component
+    }
+
+    pub fn remove_entity(&mut self, entity: Entity) -> Option<Vec<Data>> {
+        let location = self.get_location(entity)?;
+        self.entity_locations[entity.id() as usize] = None;
 
-        let data = archetype.remove(entity);
+        let archetype = &mut self.archetypes[location.archetype_id.0];
+        let data = archetype
+            .component_ids
+            .iter()
+            .map(|id| archetype.columns[id].write().swap_remove(location.row))
+            .collect::<Vec<_>>();
 
-        if archetype.is_empty() {
-            self.archetypes.remove(archetype_id);
+        if let Some(swapped) = archetype.swap_remove_entity(location.row) {
+            self.set_row(swapped, location.row);
         }
 
-        self.entity_archetype.remove(&entity);
+        for data in &data {
+            self.removed.entry(data.id()).or_default().push(entity);
+            self.record(ComponentEvent::Remove, data.id(), entity);
+        }
 
         Some(data)
     }
 
-    pub fn get_component<T: Component>(&self, entity: Entity) -> Option<Ref<T>> {
-        let archetype_id = self.entity_archetype.get(&entity)?;
-
-        let archetype = self.archetypes.get(archetype_id)?;
-
-        if archetype
-            .columns
-            .get(&TypeId::of::<T>())?
-            .read()
-            .contains(entity.as_usize())
-        {
-            Some(Ref::new(
-                entity,
-                archetype.columns[&TypeId::of::<T>()].read(),
-            ))
-        } else {
-            None
+    pub fn watch_lifecycle(&mut self, id: ComponentId) {
+        self.watched.insert(id);
+    }
+
+    fn record(&mut self, event: ComponentEvent, id: ComponentId, entity: Entity) {
+        if self.watched.contains(&id) {
+            self.lifecycle_events
+                .push(LifecycleEvent { event, id, entity });
         }
     }
 
-    pub fn get_component_mut<T: Component>(&self, entity: Entity) -> Option<Mut<T>> {
-        let archetype_id = self.entity_archetype.get(&entity)?;
+    pub fn drain_lifecycle_events(&mut self) -> Vec<LifecycleEvent> {
+        std::mem::take(&mut self.lifecycle_events)
+    }
 
-        let archetype = self.archetypes.get(archetype_id)?;
+    pub fn removed<T: Component>(&self) -> &[Entity] {
+        self.removed_by_id(ComponentId::of::<T>())
+    }
 
-        if archetype
-            .columns
-            .get(&TypeId::of::<T>())?
-            .read()
-            .contains(entity.as_usize())
-        {
-            Some(Mut::new(
-                entity,
-                archetype.columns[&TypeId::of::<T>()].write(),
-            ))
-        } else {
-            None
-        }
+    pub fn removed_by_id(&self, id: ComponentId) -> &[Entity] {
+        self.removed
+            .get(&id)
+            .map(|entities| entities.as_slice())
+            .unwrap_or_default()
     }
 
-    pub fn has_component<T: Component>(&self, entity: Entity) -> bool {
-        self.has_component_by_type_id(entity, TypeId::of::<T>())
+    pub fn clear_removed(&mut self) {
+        for entities in self.removed.values_mut() {
+            entities.clear();
+        }
     }
 
-    pub fn has_component_by_type_id(&self, entity: Entity, type_id: TypeId) -> bool {
-        if let Some(archetype_id) = self.entity_archetype.get(&entity) {
-            if let Some(archetype) = self.archetypes.get(archetype_id) {
-                return archetype.has_component_by_type_id(entity, type_id);
+    pub fn get_location(&self, entity: Entity) -> Option<EntityLocation> {
+        match self.entity_locations.get(entity.id() as usize) {
+            Some(Some((generation, location))) if *generation == entity.generation() => {
+                Some(*location)
             }
+            _ => None,
         }
+    }
+
+    // whether the entity's id currently belongs to some generation of it
+    fn is_slot_occupied(&self, entity: Entity) -> bool {
+        self.entity_locations
+            .get(entity.id() as usize)
+            .is_some_and(|slot| slot.is_some())
+    }
+
+    fn set_location(&mut self, entity: Entity, location: EntityLocation) {
+        let index = entity.id() as usize;
+        if index >= self.entity_locations.len() {
+            self.entity_locations.resize(index + 1, None);
+        }
+        self.entity_locations[index] = Some((entity.generation(), location));
+    }
+
+    fn set_row(&mut self, entity: Entity, row: usize) {
+        if let Some((_, location)) = &mut self.entity_locations[entity.id() as usize] {
+            location.row = row;
+        }
+    }
+
+    pub fn get_component<T: Component>(&self, entity: Entity) -> Option<Ref<T>> {
+        let location = self.get_location(entity)?;
+        let column = self.archetypes[location.archetype_id.0].lock_column::<T>()?;
+        Some(Ref::new(entity, location.row, column))
+    }
+
+    pub fn get_component_mut<T: Component>(
+        &self,
+        entity: Entity,
+        change_tick: Tick,
+    ) -> Option<Mut<T>> {
+        let location = self.get_location(entity)?;
+        let column = self.archetypes[location.archetype_id.0].lock_column_mut::<T>()?;
+        Some(Mut::new(entity, location.row, column, change_tick))
+    }
+
+    pub fn get_dynamic(&self, entity: Entity, id: ComponentId) -> Option<DynamicRef> {
+        if !id.is_dynamic() {
+            return None;
+        }
+        let location = self.get_location(entity)?;
+        let column = self.archetypes[location.archetype_id.0].lock_column_by_id(id)?;
+        Some(DynamicRef::new(entity, location.row, column))
+    }
+
+    pub fn get_dynamic_mut(
+        &self,
+        entity: Entity,
+        id: ComponentId,
+        change_tick: Tick,
+    ) -> Option<DynamicMut> {
+        if !id.is_dynamic() {
+            return None;
+        }
+        let location = self.get_location(entity)?;
+        let column = self.archetypes[location.archetype_id.0].lock_column_by_id_mut(id)?;
+        Some(DynamicMut::new(entity, location.row, column, change_tick))
+    }
+
+    pub fn has_component<T: Component>(&self, entity: Entity) -> bool {
+        self.has_component_by_id(entity, ComponentId::of::<T>())
+    }
 
-        false
+    pub fn has_component_by_id(&self, entity: Entity, id: ComponentId) -> bool {
+        self.get_archetype(entity)
+            .is_some_and(|archetype| archetype.contains_component_by_id(id))
     }
 
     pub fn entity_iter(&self) -> impl Iterator<Item = Entity> + '_ {
-        self.entity_archetype.keys().copied()
+        self.entity_locations
+            .iter()
+            .enumerate()
+            .filter_map(|(id, slot)| slot.map(|(generation, _)| Entity::new(id as u32, generation)))
+    }
+
+    // every archetype in creation order, including empty ones
+    pub fn archetypes(&self) -> &[Archetype] {
+        &self.archetypes
     }
 
     pub fn archetype_iter(&self) -> impl Iterator<Item = &Archetype> + '_ {
-        self.archetypes.values()
+        self.archetypes
+            .iter()
+            .filter(|archetype| !archetype.is_empty())
+    }
+
+    pub fn get_archetype_by_id(&self, id: ArchetypeId) -> Option<&Archetype> {
+        self.archetypes.get(id.0)
     }
 
     pub fn get_archetype(&self, entity: Entity) -> Option<&Archetype> {
-        self.entity_archetype
-            .get(&entity)
-            .and_then(|archetype_id| self.archetypes.get(archetype_id))
+        self.get_location(entity)
+            .and_then(|location| self.archetypes.get(location.archetype_id.0))
     }
 }
 
@@ -724,8 +1303,8 @@ mod tests {
 
         let entity = Entity::new(0, 0);
 
-        storage.insert_component(entity, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 });
+        storage.insert_component(entity, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
 
         assert_eq!(
             storage.get_component::<Position>(entity).map(|data| data.x),
@@ -745,8 +1324,8 @@ mod tests {
 
         let entity = Entity::new(0, 0);
 
-        storage.insert_component(entity, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 });
+        storage.insert_component(entity, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
 
         assert_eq!(
             storage.get_component::<Position>(entity).map(|data| data.x),
@@ -776,8 +1355,8 @@ mod tests {
 
         let entity = Entity::new(0, 0);
 
-        storage.insert_component(entity, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 });
+        storage.insert_component(entity, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
 
         assert_eq!(
             storage.get_component::<Position>(entity).map(|data| data.x),
@@ -798,14 +1377,216 @@ mod tests {
         assert_eq!(storage.get_component::<Velocity>(entity).as_deref(), None);
     }
 
+    #[test]
+    fn test_replace_component_ticks() {
+        let mut storage = Storage::new();
+
+        let entity = Entity::new(0, 0);
+
+        storage.insert_component(entity, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(2));
+        storage.insert_component(entity, Position { x: 1.0, y: 1.0 }, Tick::new(3));
+
+        assert_eq!(
+            storage.get_component::<Position>(entity).map(|data| data.x),
+            Some(1.0)
+        );
+
+        let archetype = storage.get_archetype(entity).unwrap();
+        assert_eq!(archetype.component_ids().len(), 2);
+
+        let ticks = Ref::ticks(&storage.get_component::<Position>(entity).unwrap());
+        assert_eq!(ticks.added, Tick::new(1));
+        assert_eq!(ticks.changed, Tick::new(3));
+
+        let mut velocity = storage
+            .get_component_mut::<Velocity>(entity, Tick::new(4))
+            .unwrap();
+        velocity.dx = 2.0;
+        drop(velocity);
+
+        let ticks = Ref::ticks(&storage.get_component::<Velocity>(entity).unwrap());
+        assert_eq!(ticks.added, Tick::new(2));
+        assert_eq!(ticks.changed, Tick::new(4));
+    }
+
+    #[test]
+    fn test_removed() {
+        let mut storage = Storage::new();
+
+        let entity1 = Entity::new(0, 0);
+        let entity2 = Entity::new(1, 0);
+
+        storage.insert_component(entity1, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity1, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
+        storage.insert_component(entity2, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity2, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
+
+        storage.remove_component::<Velocity>(entity1);
+        storage.remove_entity(entity2);
+
+        assert_eq!(storage.removed::<Velocity>(), &[entity1, entity2]);
+        assert_eq!(storage.removed::<Position>(), &[entity2]);
+        assert!(storage.removed::<Acceleration>().is_empty());
+
+        storage.clear_removed();
+
+        assert!(storage.removed::<Velocity>().is_empty());
+        assert!(storage.removed::<Position>().is_empty());
+    }
+
+    #[test]
+    fn test_archetype_edges() {
+        let mut storage = Storage::new();
+
+        let entity1 = Entity::new(0, 0);
+        let entity2 = Entity::new(1, 0);
+
+        storage.insert_component(entity1, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        let position_archetype = storage.get_archetype(entity1).unwrap().id();
+
+        storage.insert_component(entity1, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
+        let position_velocity_archetype = storage.get_archetype(entity1).unwrap().id();
+
+        let edges = storage
+            .get_archetype_by_id(position_archetype)
+            .unwrap()
+            .edges();
+        assert_eq!(
+            edges.get_add(ComponentId::of::<Velocity>()),
+            Some(position_velocity_archetype)
+        );
+
+        // the same signature maps to the same archetype regardless of insertion order
+        storage.insert_components(
+            entity2,
+            (Velocity { dx: 1.0, dy: 1.0 }, Position { x: 0.0, y: 0.0 }),
+            Tick::new(1),
+        );
+        assert_eq!(
+            storage.get_archetype(entity2).unwrap().id(),
+            position_velocity_archetype
+        );
+
+        storage.remove_component::<Velocity>(entity1);
+        assert_eq!(
+            storage.get_archetype(entity1).unwrap().id(),
+            position_archetype
+        );
+
+        // removing a missing component leaves the entity untouched
+        assert!(storage.remove_component::<Acceleration>(entity1).is_none());
+        assert_eq!(
+            storage
+                .get_component::<Position>(entity1)
+                .map(|data| data.x),
+            Some(0.0)
+        );
+    }
+
+    #[derive(Component)]
+    struct Marker;
+
+    #[derive(Component)]
+    struct Tracked {
+        _tracker: std::sync::Arc<()>,
+    }
+
+    #[test]
+    fn test_shared_rows() {
+        let mut storage = Storage::new();
+
+        let tracker = std::sync::Arc::new(());
+        let entities = (0..4).map(|i| Entity::new(i, 0)).collect::<Vec<_>>();
+
+        for (i, entity) in entities.iter().enumerate() {
+            storage.insert_components(
+                *entity,
+                (
+                    Position {
+                        x: i as f32,
+                        y: 0.0,
+                    },
+                    Marker,
+                    Tracked {
+                        _tracker: tracker.clone(),
+                    },
+                ),
+                Tick::new(1),
+            );
+        }
+        assert_eq!(std::sync::Arc::strong_count(&tracker), 5);
+
+        // moving and removing rows swaps the last row into the hole
+        storage.remove_component::<Marker>(entities[0]);
+        storage.remove_entity(entities[1]);
+        assert_eq!(std::sync::Arc::strong_count(&tracker), 4);
+
+        for (i, entity) in entities.iter().enumerate().skip(2) {
+            assert_eq!(
+                storage
+                    .get_component::<Position>(*entity)
+                    .map(|data| data.x),
+                Some(i as f32)
+            );
+            assert!(storage.has_component::<Marker>(*entity));
+        }
+        assert_eq!(
+            storage
+                .get_component::<Position>(entities[0])
+                .map(|data| data.x),
+            Some(0.0)
+        );
+
+        let archetype = storage.get_archetype(entities[2]).unwrap();
+        let column = archetype.get_column::<Position>().unwrap();
+        let xs = column
+            .as_slice::<Position>()
+            .unwrap()
+            .iter()
+            .map(|position| position.x)
+            .collect::<Vec<_>>();
+        assert_eq!(xs, vec![3.0, 2.0]);
+        drop(column);
+
+        drop(storage);
+        assert_eq!(std::sync::Arc::strong_count(&tracker), 1);
+    }
+
+    #[test]
+    fn test_stale_entity() {
+        let mut storage = Storage::new();
+
+        let entity = Entity::new(0, 0);
+        let stale = Entity::new(0, 1);
+
+        storage.insert_component(entity, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+
+        assert!(storage.get_component::<Position>(stale).is_none());
+        assert!(!storage.has_component::<Position>(stale));
+        assert!(storage.remove_component::<Position>(stale).is_none());
+        assert!(storage.remove_entity(stale).is_none());
+
+        // a stale handle can't clobber the slot's current owner
+        storage.insert_component(stale, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
+        assert!(!storage.has_component::<Velocity>(entity));
+        assert!(storage.has_component::<Position>(entity));
+
+        storage.remove_entity(entity);
+        storage.insert_component(stale, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
+        assert!(storage.has_component::<Velocity>(stale));
+        assert!(!storage.has_component::<Velocity>(entity));
+        assert_eq!(storage.entity_iter().collect::<Vec<_>>(), vec![stale]);
+    }
+
     #[test]
     fn test_get() {
         let mut storage = Storage::new();
 
         let entity = Entity::new(0, 0);
 
-        storage.insert_component(entity, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 });
+        storage.insert_component(entity, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
 
         assert_eq!(
             storage.get_component::<Position>(entity).map(|data| data.x),
@@ -829,8 +1610,8 @@ mod tests {
 
         let entity = Entity::new(0, 0);
 
-        storage.insert_component(entity, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 });
+        storage.insert_component(entity, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
 
         assert!(storage.has_component::<Position>(entity));
         assert!(storage.has_component::<Velocity>(entity));
@@ -845,9 +1626,9 @@ mod tests {
         let entity2 = Entity::new(1, 0);
         let entity3 = Entity::new(2, 0);
 
-        storage.insert_component(entity1, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity2, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity3, Position { x: 0.0, y: 0.0 });
+        storage.insert_component(entity1, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity2, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity3, Position { x: 0.0, y: 0.0 }, Tick::new(1));
 
         let entities = storage.entity_iter().collect::<Vec<_>>();
 
@@ -865,9 +1646,9 @@ mod tests {
         let entity2 = Entity::new(1, 0);
         let entity3 = Entity::new(2, 0);
 
-        storage.insert_component(entity1, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity2, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity3, Position { x: 0.0, y: 0.0 });
+        storage.insert_component(entity1, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity2, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity3, Position { x: 0.0, y: 0.0 }, Tick::new(1));
 
         let archetypes = storage.archetype_iter().collect::<Vec<_>>();
 
@@ -881,8 +1662,8 @@ mod tests {
 
         let entity = Entity::new(0, 0);
 
-        storage.insert_component(entity, Position { x: 0.0, y: 0.0 });
-        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 });
+        storage.insert_component(entity, Position { x: 0.0, y: 0.0 }, Tick::new(1));
+        storage.insert_component(entity, Velocity { dx: 1.0, dy: 1.0 }, Tick::new(1));
 
         let archetype = storage.get_archetype(entity).unwrap();
 
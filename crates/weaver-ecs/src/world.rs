@@ -1,26 +1,35 @@
 use std::sync::{
-    atomic::{AtomicU32, AtomicU64, Ordering},
-    Arc,
+    atomic::{AtomicU64, Ordering},
+    Arc, Weak,
 };
 
-use weaver_util::lock::Lock;
+use weaver_util::{
+    lock::Lock,
+    prelude::{anyhow, bail, Result},
+};
 
 use crate::prelude::{
-    Bundle, Query, QueryFetch, QueryFilter, Res, ResMut, Resource, Resources, Scene, Tick,
+    Bundle, ComponentId, ComponentTicks, DynamicComponent, DynamicComponentInfo, DynamicMut,
+    DynamicRef, Query, QueryFetch, QueryFilter, Res, ResMut, Resource, Resources, Scene,
+    SystemTicks, Tick, WorldStats,
 };
 
 use super::{
     component::Component,
-    entity::Entity,
+    entity::{Entities, Entity},
+    hooks::{ComponentEvent, ComponentHooks, LifecycleEvent},
+    scene::SceneNodes,
     storage::{Mut, Ref, Storage},
 };
 
 pub struct World {
     root_scene_entity: Entity,
-    next_entity: AtomicU32,
-    free_entities: Lock<Vec<Entity>>,
+    entities: Lock<Entities>,
     storage: Lock<Storage>,
     resources: Lock<Resources>,
+    hooks: Lock<ComponentHooks>,
+    // every scene created in the world, whether or not it's stored as a component
+    scenes: Lock<Vec<Weak<SceneNodes>>>,
     update_tick: AtomicU64,
 }
 
@@ -28,11 +37,12 @@ impl World {
     pub fn new() -> Arc<Self> {
         let mut world = Self {
             root_scene_entity: Entity::new(1, 0),
-            next_entity: AtomicU32::new(0),
-            free_entities: Lock::new(Vec::new()),
+            entities: Lock::new(Entities::new()),
             storage: Lock::new(Storage::new()),
             resources: Lock::new(Resources::default()),
-            update_tick: AtomicU64::new(0),
+            hooks: Lock::new(ComponentHooks::default()),
+            scenes: Lock::new(Vec::new()),
+            update_tick: AtomicU64::new(1),
         };
 
         world.root_scene_entity = world.create_entity(); // reserve entity 0 for the root scene
@@ -50,35 +60,91 @@ impl World {
     }
 
     pub fn create_entity(&self) -> Entity {
-        if let Some(entity) = self.free_entities.write().pop() {
-            Entity::new(entity.id(), entity.generation() + 1)
-        } else {
-            let id = self.next_entity.fetch_add(1, Ordering::Relaxed);
-            Entity::new(id, 0)
-        }
+        self.entities.write().alloc()
+    }
+
+    // allocates `count` entities under a single lock
+    pub fn reserve_entities(&self, count: usize) -> Vec<Entity> {
+        self.entities.write().reserve(count)
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.read().is_alive(entity)
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.entities.read().len()
     }
 
     pub fn spawn<T: Bundle>(&self, bundle: T) -> Entity {
         let entity = self.create_entity();
-        self.storage().write().insert_components(entity, bundle);
+        let events = {
+            let mut storage = self.storage.write();
+            storage.insert_components(entity, bundle, self.change_tick());
+            storage.drain_lifecycle_events()
+        };
+        self.run_hooks(events);
         entity
     }
 
     pub fn destroy_entity(&self, entity: Entity) {
-        self.storage.write().remove_entity(entity);
-        self.free_entities.write().push(entity);
+        if !self.entities.write().free(entity) {
+            log::warn!("Tried to destroy dead entity {:?}", entity);
+            return;
+        }
+        let events = {
+            let mut storage = self.storage.write();
+            storage.remove_entity(entity);
+            storage.drain_lifecycle_events()
+        };
+        self.run_hooks(events);
+        self.hooks.write().remove_observers(entity);
+
+        // otherwise the node would linger in its scenes, along with its relationship edges
+        let scenes = self
+            .scenes
+            .read()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>();
+        for scene in scenes {
+            if let Some(node) = scene.find_node(entity) {
+                scene.remove_node(node);
+            }
+        }
+    }
+
+    pub(crate) fn track_scene(&self, scene: &Arc<SceneNodes>) {
+        let mut scenes = self.scenes.write();
+        scenes.retain(|scene| scene.strong_count() > 0);
+        scenes.push(Arc::downgrade(scene));
     }
 
     pub fn insert_component<T: Component>(&self, entity: Entity, component: T) {
-        self.storage.write().insert_component(entity, component)
+        self.insert_components(entity, component);
     }
 
     pub fn insert_components<T: Bundle>(&self, entity: Entity, bundle: T) {
-        self.storage.write().insert_components(entity, bundle)
+        if !self.is_alive(entity) {
+            log::warn!("Tried to insert components on dead entity {:?}", entity);
+            return;
+        }
+        let events = {
+            let mut storage = self.storage.write();
+            storage.insert_components(entity, bundle, self.change_tick());
+            storage.drain_lifecycle_events()
+        };
+        self.run_hooks(events);
     }
 
     pub fn remove_component<T: Component>(&self, entity: Entity) -> Option<T> {
-        self.storage.write().remove_component::<T>(entity)
+        let (component, events) = {
+            let mut storage = self.storage.write();
+            let component = storage.remove_component::<T>(entity);
+            (component, storage.drain_lifecycle_events())
+        };
+        self.run_hooks(events);
+        component
     }
 
     pub fn get_component<T: Component>(&self, entity: Entity) -> Option<Ref<T>> {
@@ -86,13 +152,155 @@ impl World {
     }
 
     pub fn get_component_mut<T: Component>(&self, entity: Entity) -> Option<Mut<T>> {
-        self.storage.read().get_component_mut::<T>(entity)
+        self.storage
+            .read()
+            .get_component_mut::<T>(entity, self.change_tick())
     }
 
     pub fn has_component<T: Component>(&self, entity: Entity) -> bool {
         self.storage.read().has_component::<T>(entity)
     }
 
+    pub fn has_component_by_id(&self, entity: Entity, id: ComponentId) -> bool {
+        self.storage.read().has_component_by_id(entity, id)
+    }
+
+    pub fn register_dynamic_component(&self, info: DynamicComponentInfo) -> Result<ComponentId> {
+        self.storage.write().register_dynamic_component(info)
+    }
+
+    pub fn dynamic_component_id(&self, name: &str) -> Option<ComponentId> {
+        self.storage.read().dynamic_component_id(name)
+    }
+
+    pub fn dynamic_component_info(&self, id: ComponentId) -> Option<Arc<DynamicComponentInfo>> {
+        self.storage.read().get_dynamic_component_info(id).cloned()
+    }
+
+    // a new value of a registered dynamic component, with every field at its default
+    pub fn new_dynamic(&self, id: ComponentId) -> Option<DynamicComponent> {
+        let info = self.dynamic_component_info(id)?;
+        Some(DynamicComponent::new(id, info))
+    }
+
+    pub fn insert_dynamic(&self, entity: Entity, component: DynamicComponent) -> Result<()> {
+        let info = self
+            .dynamic_component_info(component.id())
+            .ok_or_else(|| anyhow!("{:?} is not a registered dynamic component", component.id()))?;
+        if *info != **component.info() {
+            bail!(
+                "{} doesn't match the component registered as {:?}",
+                component.name(),
+                component.id()
+            );
+        }
+        self.insert_components(entity, component);
+        Ok(())
+    }
+
+    pub fn get_dynamic(&self, entity: Entity, id: ComponentId) -> Option<DynamicRef> {
+        self.storage.read().get_dynamic(entity, id)
+    }
+
+    pub fn get_dynamic_mut(&self, entity: Entity, id: ComponentId) -> Option<DynamicMut> {
+        self.storage
+            .read()
+            .get_dynamic_mut(entity, id, self.change_tick())
+    }
+
+    pub fn remove_dynamic(&self, entity: Entity, id: ComponentId) -> Option<DynamicComponent> {
+        if !id.is_dynamic() {
+            return None;
+        }
+        let (component, events) = {
+            let mut storage = self.storage.write();
+            let component = storage.remove_component_by_id(entity, id);
+            (component, storage.drain_lifecycle_events())
+        };
+        self.run_hooks(events);
+        component?
+            .into_data()
+            .downcast()
+            .ok()
+            .map(|component| *component)
+    }
+
+    // takes the storage and resource locks, so don't call it while holding a component or
+    // resource
+    pub fn stats(&self) -> WorldStats {
+        WorldStats::collect(
+            self.entity_count(),
+            &self.storage.read(),
+            &self.resources.read(),
+        )
+    }
+
+    pub fn removed_components<T: Component>(&self) -> Vec<Entity> {
+        self.storage.read().removed::<T>().to_vec()
+    }
+
+    pub fn clear_removed_components(&self) {
+        self.storage.write().clear_removed();
+    }
+
+    pub fn on_insert<T: Component>(&self, hook: impl Fn(&World, Entity) + Send + Sync + 'static) {
+        self.add_hook::<T>(ComponentEvent::Insert, hook);
+    }
+
+    pub fn on_replace<T: Component>(&self, hook: impl Fn(&World, Entity) + Send + Sync + 'static) {
+        self.add_hook::<T>(ComponentEvent::Replace, hook);
+    }
+
+    pub fn on_remove<T: Component>(&self, hook: impl Fn(&World, Entity) + Send + Sync + 'static) {
+        self.add_hook::<T>(ComponentEvent::Remove, hook);
+    }
+
+    // hooks run right after the change, once the storage is unlocked, so they're free to
+    // touch the world; changes they make run their own hooks in turn
+    pub fn add_hook<T: Component>(
+        &self,
+        event: ComponentEvent,
+        hook: impl Fn(&World, Entity) + Send + Sync + 'static,
+    ) {
+        self.add_hook_by_id(ComponentId::of::<T>(), event, hook);
+    }
+
+    pub fn add_hook_by_id(
+        &self,
+        id: ComponentId,
+        event: ComponentEvent,
+        hook: impl Fn(&World, Entity) + Send + Sync + 'static,
+    ) {
+        self.hooks.write().add_hook(id, event, Arc::new(hook));
+        self.storage.write().watch_lifecycle(id);
+    }
+
+    // like a hook, but only for one entity
+    pub fn observe<T: Component>(
+        &self,
+        entity: Entity,
+        event: ComponentEvent,
+        observer: impl Fn(&World, Entity) + Send + Sync + 'static,
+    ) {
+        if !self.is_alive(entity) {
+            log::warn!("Tried to observe dead entity {:?}", entity);
+            return;
+        }
+        self.hooks
+            .write()
+            .add_observer(entity, ComponentId::of::<T>(), event, Arc::new(observer));
+        self.storage.write().watch_lifecycle(ComponentId::of::<T>());
+    }
+
+    fn run_hooks(&self, events: Vec<LifecycleEvent>) {
+        for event in events {
+            let hooks = self.hooks.read().hooks_for(&event);
+            for hook in hooks {
+                hook(self, event.entity);
+            }
+        }
+    }
+
     pub fn query<Q: QueryFetch>(self: &Arc<Self>) -> Query<Q, ()> {
         Query::new(self)
     }
@@ -106,11 +314,15 @@ impl World {
     }
 
     pub fn get_resource<T: Resource>(&self) -> Option<Res<T>> {
-        self.resources.read().get::<T>()
+        self.resources.read().get::<T>(self.system_ticks())
     }
 
     pub fn get_resource_mut<T: Resource>(&self) -> Option<ResMut<T>> {
-        self.resources.read().get_mut::<T>()
+        self.resources.read().get_mut::<T>(self.change_tick())
+    }
+
+    pub fn get_resource_ticks<T: Resource>(&self) -> Option<ComponentTicks> {
+        self.resources.read().get_ticks::<T>()
     }
 
     pub fn has_resource<T: Resource>(&self) -> bool {
@@ -118,7 +330,7 @@ impl World {
     }
 
     pub fn insert_resource<T: Resource>(&self, component: T) {
-        self.resources.write().insert(component)
+        self.resources.write().insert(component, self.change_tick())
     }
 
     pub fn remove_resource<T: Resource>(&self) -> Option<T> {
@@ -137,4 +349,103 @@ impl World {
     pub fn update(&self) {
         self.update_tick.fetch_add(1, Ordering::AcqRel);
     }
+
+    // returns the tick before incrementing, so changes made afterwards are newer than it
+    pub fn increment_update_tick(&self) -> Tick {
+        Tick::new(self.update_tick.fetch_add(1, Ordering::AcqRel))
+    }
+
+    // the tick that mutations are stamped with: the running system's tick, or the world's current tick
+    pub fn change_tick(&self) -> Tick {
+        SystemTicks::current()
+            .map(|ticks| ticks.this_run)
+            .unwrap_or_else(|| self.update_tick())
+    }
+
+    pub fn system_ticks(&self) -> SystemTicks {
+        SystemTicks::current()
+            .unwrap_or_else(|| SystemTicks::new(Tick::default(), self.update_tick()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate as weaver_ecs;
+    use weaver_ecs_macros::Component;
+
+    use super::*;
+
+    #[derive(Component)]
+    struct Health(u32);
+
+    #[derive(Component)]
+    struct Dead;
+
+    #[test]
+    fn test_stale_entity() {
+        let world = World::new();
+
+        let entity = world.spawn(Health(10));
+        world.destroy_entity(entity);
+        world.destroy_entity(entity);
+        assert!(!world.is_alive(entity));
+
+        // the slot is recycled once, and the old handle can't reach the new entity
+        let reused = world.reserve_entities(2);
+        assert_eq!(reused.iter().filter(|e| e.id() == entity.id()).count(), 1);
+
+        let recycled = reused.into_iter().find(|e| e.id() == entity.id()).unwrap();
+        world.insert_component(recycled, Health(20));
+        world.insert_component(entity, Health(30));
+
+        assert!(world.get_component::<Health>(entity).is_none());
+        assert_eq!(world.get_component::<Health>(recycled).unwrap().0, 20);
+    }
+
+    #[test]
+    fn test_component_hooks() {
+        let world = World::new();
+        let log = Arc::new(Lock::new(Vec::new()));
+
+        let hook_log = log.clone();
+        world.on_insert::<Health>(move |world, entity| {
+            let health = world.get_component::<Health>(entity).unwrap().0;
+            hook_log.write().push(("insert", health));
+        });
+        let hook_log = log.clone();
+        world.on_replace::<Health>(move |world, entity| {
+            let health = world.get_component::<Health>(entity).unwrap().0;
+            hook_log.write().push(("replace", health));
+        });
+        // hooks can change the world themselves
+        world.on_remove::<Health>(|world, entity| {
+            if world.is_alive(entity) {
+                world.insert_component(entity, Dead);
+            }
+        });
+
+        let entity = world.spawn(Health(10));
+        world.insert_component(entity, Health(5));
+        assert_eq!(*log.read(), [("insert", 10), ("replace", 5)]);
+
+        world.remove_component::<Health>(entity);
+        assert!(world.has_component::<Dead>(entity));
+
+        let observer_log = log.clone();
+        let observed = world.spawn(Health(1));
+        world.observe::<Dead>(observed, ComponentEvent::Insert, move |_, entity| {
+            observer_log.write().push(("observed", entity.id()));
+        });
+        world.insert_component(entity, Dead);
+        world.insert_component(observed, Dead);
+        assert_eq!(log.read().last(), Some(&("observed", observed.id())));
+        assert_eq!(log.read().len(), 4);
+
+        // observers go away with their entity
+        world.destroy_entity(observed);
+        let recycled = world.create_entity();
+        assert_eq!(recycled.id(), observed.id());
+        world.insert_component(recycled, Dead);
+        assert_eq!(log.read().len(), 4);
+    }
 }
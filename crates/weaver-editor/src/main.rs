@@ -5,12 +5,17 @@ use weaver::{
     weaver_app::{system::SystemStage, App},
     weaver_core::{input::InputPlugin, mesh::Mesh, time::TimePlugin},
     weaver_ecs::world::World,
-    weaver_pbr::{camera::PbrCamera, material::Material, PbrPlugin},
+    weaver_pbr::{
+        bundle::{CameraBundle, PbrMeshBundle},
+        camera::PbrCamera,
+        material::Material,
+        PbrPlugin,
+    },
     weaver_renderer::{camera::Camera, RendererPlugin},
     weaver_winit::WinitPlugin,
 };
 use weaver_core::CoreTypesPlugin;
-use weaver_diagnostics::frame_time::LogFrameTimePlugin;
+use weaver_diagnostics::{frame_time::LogFrameTimePlugin, world_stats::WorldStatsPlugin};
 use weaver_egui::prelude::*;
 
 pub mod camera;
@@ -37,6 +42,8 @@ fn main() -> Result<()> {
         .add_plugin(LogFrameTimePlugin {
             log_interval: std::time::Duration::from_secs(1),
         })?
+        .add_plugin(WorldStatsPlugin { log_interval: None })?
+        .add_plugin(WorldStatsOverlayPlugin)?
         .add_system(setup, SystemStage::Init)?
         .add_system(camera::update_camera, SystemStage::Update)?
         .add_system(camera::update_aspect_ratio, SystemStage::Update)?
@@ -48,16 +55,18 @@ fn main() -> Result<()> {
 fn setup(world: &Arc<World>) -> Result<()> {
     let scene = world.root_scene();
     let _camera = scene.spawn((
-        Camera::perspective_lookat(
-            Vec3::new(10.0, 10.0, 10.0),
-            Vec3::ZERO,
-            Vec3::Y,
-            45.0f32.to_radians(),
-            1280.0 / 720.0,
-            0.1,
-            100.0,
-        ),
-        PbrCamera::new(Color::new(0.1, 0.1, 0.1, 1.0)),
+        CameraBundle {
+            camera: Camera::perspective_lookat(
+                Vec3::new(10.0, 10.0, 10.0),
+                Vec3::ZERO,
+                Vec3::Y,
+                45.0f32.to_radians(),
+                1280.0 / 720.0,
+                0.1,
+                100.0,
+            ),
+            pbr_camera: PbrCamera::new(Color::new(0.1, 0.1, 0.1, 1.0)),
+        },
         *camera::FlyCameraController {
             aspect: 1280.0 / 720.0,
             ..Default::default()
@@ -84,12 +93,14 @@ fn setup(world: &Arc<World>) -> Result<()> {
     }
 
     let _ground = scene.spawn((
-        mesh,
-        material,
-        Transform {
-            translation: Vec3::new(0.0, -1.0, 0.0),
-            rotation: Quat::IDENTITY,
-            scale: Vec3::new(20.0, 1.0, 20.0),
+        PbrMeshBundle {
+            mesh,
+            material,
+            transform: Transform {
+                translation: Vec3::new(0.0, -1.0, 0.0),
+                rotation: Quat::IDENTITY,
+                scale: Vec3::new(20.0, 1.0, 20.0),
+            },
         },
         Floor,
     ));
@@ -118,12 +129,14 @@ fn setup(world: &Arc<World>) -> Result<()> {
     for i in 0..6 {
         let angle = i as f32 / 6.0 * std::f32::consts::PI * 2.0;
         let _mesh = scene.spawn((
-            mesh,
-            material2,
-            Transform {
-                translation: Vec3::new(angle.cos() * 5.0, 2.0, angle.sin() * 5.0),
-                rotation: Quat::IDENTITY,
-                scale: Vec3::splat(0.5),
+            PbrMeshBundle {
+                mesh,
+                material: material2,
+                transform: Transform {
+                    translation: Vec3::new(angle.cos() * 5.0, 2.0, angle.sin() * 5.0),
+                    rotation: Quat::IDENTITY,
+                    scale: Vec3::splat(0.5),
+                },
             },
             Object,
         ));
@@ -134,11 +147,11 @@ fn setup(world: &Arc<World>) -> Result<()> {
 
 fn update(world: &Arc<World>) -> Result<()> {
     let time = world.get_resource::<Time>().unwrap();
+    let angle = time.total_time * 0.5;
     let query = world.query_filtered::<&mut Transform, With<Object>>();
-    for (_entity, mut transform) in query.iter() {
-        let angle = time.total_time * 0.5;
+    query.par_for_each(|(_entity, mut transform)| {
         transform.rotation = Quat::from_rotation_y(angle);
-    }
+    });
 
     Ok(())
 }
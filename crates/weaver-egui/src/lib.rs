@@ -11,8 +11,10 @@ use weaver_renderer::prelude::wgpu;
 use weaver_util::{lock::SharedLock, prelude::Result};
 use weaver_winit::{Window, WinitEvent};
 
+pub mod world_stats;
+
 pub mod prelude {
-    pub use super::{EguiContext, EguiPlugin};
+    pub use super::{world_stats::WorldStatsOverlayPlugin, EguiContext, EguiPlugin};
     pub use egui;
 }
 
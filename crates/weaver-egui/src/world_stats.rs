@@ -0,0 +1,109 @@
+use weaver_app::{plugin::Plugin, system::SystemStage, App};
+use weaver_ecs::{component::Res, prelude::WorldStats};
+use weaver_util::prelude::Result;
+
+use crate::EguiContext;
+
+// draws the `WorldStats` resource in a window; something else has to keep it up to date
+pub struct WorldStatsOverlayPlugin;
+
+impl Plugin for WorldStatsOverlayPlugin {
+    fn build(&self, app: &mut App) -> Result<()> {
+        app.add_system(draw_world_stats, SystemStage::Ui)?;
+        Ok(())
+    }
+}
+
+fn draw_world_stats(egui_context: Res<EguiContext>, stats: Option<Res<WorldStats>>) -> Result<()> {
+    let Some(stats) = stats else {
+        return Ok(());
+    };
+    egui_context.draw_if_ready(|ctx| {
+        egui::Window::new("World Stats")
+            .default_open(false)
+            .show(ctx, |ui| world_stats_ui(ui, &stats));
+    });
+    Ok(())
+}
+
+pub fn world_stats_ui(ui: &mut egui::Ui, stats: &WorldStats) {
+    ui.label(format!("Entities: {}", stats.entities));
+    ui.label(format!(
+        "Archetypes: {} ({} empty)",
+        stats.archetypes.len(),
+        stats.empty_archetypes()
+    ));
+    ui.label(format!(
+        "Component memory: {}",
+        format_bytes(stats.component_bytes())
+    ));
+    ui.label(format!(
+        "Resource memory: {}",
+        format_bytes(stats.resource_bytes())
+    ));
+
+    ui.collapsing("Archetypes", |ui| {
+        for archetype in &stats.archetypes {
+            let names = archetype
+                .columns
+                .iter()
+                .map(|column| short_name(&column.name))
+                .collect::<Vec<_>>();
+            ui.label(format!(
+                "{}: [{}] x{} ({})",
+                archetype.id.index(),
+                names.join(", "),
+                archetype.entities,
+                format_bytes(archetype.allocated_bytes())
+            ));
+        }
+    });
+
+    ui.collapsing("Components", |ui| {
+        for component in &stats.components {
+            ui.label(format!(
+                "{}: x{} in {} archetypes ({})",
+                short_name(&component.name),
+                component.instances,
+                component.archetypes,
+                format_bytes(component.allocated_bytes)
+            ));
+        }
+    });
+
+    ui.collapsing("Resources", |ui| {
+        for resource in &stats.resources {
+            ui.label(format!(
+                "{}: {}",
+                short_name(resource.name),
+                format_bytes(resource.size)
+            ));
+        }
+    });
+
+    ui.collapsing("Scenes", |ui| {
+        for scene in &stats.scenes {
+            ui.label(format!(
+                "{:?}: {} nodes, {} edges",
+                scene.entity, scene.nodes, scene.edges
+            ));
+        }
+    });
+}
+
+// drops the module path, keeping generic arguments readable
+fn short_name(name: &str) -> &str {
+    let end = name.find('<').unwrap_or(name.len());
+    let start = name[..end].rfind("::").map_or(0, |index| index + 2);
+    &name[start..]
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{bytes}B")
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1}KiB", bytes as f32 / 1024.0)
+    } else {
+        format!("{:.1}MiB", bytes as f32 / (1024.0 * 1024.0))
+    }
+}
@@ -0,0 +1,19 @@
+use weaver_asset::Handle;
+use weaver_core::{mesh::Mesh, transform::Transform};
+use weaver_ecs::prelude::Bundle;
+use weaver_renderer::camera::Camera;
+
+use crate::{camera::PbrCamera, material::Material};
+
+#[derive(Bundle)]
+pub struct PbrMeshBundle {
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<Material>,
+    pub transform: Transform,
+}
+
+#[derive(Bundle)]
+pub struct CameraBundle {
+    pub camera: Camera,
+    pub pbr_camera: PbrCamera,
+}
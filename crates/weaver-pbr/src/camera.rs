@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use weaver_app::{plugin::Plugin, system::SystemStage, App};
+use weaver_app::{condition::resource_exists, plugin::Plugin, system::SystemStage, App};
 use weaver_core::color::Color;
 use weaver_ecs::{entity::Entity, prelude::Component, world::World};
 use weaver_renderer::{
@@ -51,8 +51,10 @@ pub struct PbrCameraPlugin;
 
 impl Plugin for PbrCameraPlugin {
     fn build(&self, app: &mut App) -> Result<()> {
-        app.add_system(prepare_pbr_cameras, SystemStage::PreRender)?;
-        app.add_system(render_pbr_cameras, SystemStage::Render)?;
+        app.add_system(prepare_pbr_cameras, SystemStage::PreRender)?
+            .run_if(resource_exists::<Renderer>)?;
+        app.add_system(render_pbr_cameras, SystemStage::Render)?
+            .run_if(resource_exists::<Renderer>)?;
         Ok(())
     }
 }
@@ -60,7 +62,12 @@ impl Plugin for PbrCameraPlugin {
 fn prepare_pbr_cameras(world: &Arc<World>) -> Result<()> {
     let camera_query = world.query::<(&mut Camera, &PbrCamera)>();
 
-    for (camera_entity, (mut base_camera, pbr_camera)) in camera_query.iter() {
+    // fetched one camera at a time, since preparing the graph reads the camera again
+    for camera_entity in camera_query.entity_iter() {
+        let Some((mut base_camera, pbr_camera)) = camera_query.get(camera_entity) else {
+            continue;
+        };
+
         if base_camera.active() {
             let graph = base_camera.render_graph_mut();
             let renderer = world.get_resource::<Renderer>().unwrap();
@@ -4,12 +4,14 @@ use material::MaterialPlugin;
 use weaver_app::prelude::*;
 use weaver_util::prelude::*;
 
+pub mod bundle;
 pub mod camera;
 pub mod light;
 pub mod material;
 pub mod render;
 
 pub mod prelude {
+    pub use crate::bundle::*;
     pub use crate::camera::*;
     pub use crate::light::*;
     pub use crate::material::*;
@@ -4,7 +4,7 @@ use weaver_app::{plugin::Plugin, App};
 use wgpu::util::DeviceExt;
 
 use weaver_core::{color::Color, prelude::Vec3};
-use weaver_ecs::prelude::{Component, Resource, World};
+use weaver_ecs::prelude::{Changed, Component, Resource, World};
 use weaver_renderer::{
     bind_group::{CreateResourceBindGroup, ResourceBindGroup, ResourceBindGroupPlugin},
     buffer::GpuBuffer,
@@ -111,6 +111,14 @@ impl RenderResource for GpuPointLightArray {
     }
 
     fn update_render_resource(&mut self, world: &Arc<World>, renderer: &Renderer) -> Result<()> {
+        if world
+            .query_filtered::<&PointLight, Changed<PointLight>>()
+            .is_empty()
+            && world.removed_components::<PointLight>().is_empty()
+        {
+            return Ok(());
+        }
+
         let point_lights = world.query::<&PointLight>();
 
         let point_light_uniforms: Vec<PointLightUniform> = point_lights
@@ -1,7 +1,7 @@
 use std::path::Path;
 
 use weaver_app::{plugin::Plugin, App};
-use weaver_asset::{prelude::Asset, Assets, Handle};
+use weaver_asset::{prelude::Asset, Assets, Handle, UntypedHandle};
 use weaver_core::{color::Color, texture::Texture};
 use weaver_ecs::prelude::{Component, World};
 use weaver_renderer::{
@@ -269,6 +269,15 @@ impl RenderAsset for GpuMaterial {
         })
     }
 
+    fn dependencies(base_asset: &Material) -> Vec<UntypedHandle> {
+        vec![
+            base_asset.diffuse_texture.into_untyped(),
+            base_asset.normal_texture.into_untyped(),
+            base_asset.metallic_roughness_texture.into_untyped(),
+            base_asset.ao_texture.into_untyped(),
+        ]
+    }
+
     fn update_render_asset(
         &self,
         base_asset: &Self::BaseAsset,
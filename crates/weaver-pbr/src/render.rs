@@ -23,7 +23,7 @@ struct UniqueMaterialMesh {
     mesh: Handle<GpuMesh>,
     transform_buffer: wgpu::Buffer,
     transform_bind_group: wgpu::BindGroup,
-    entities: Vec<Entity>,
+    transforms: Vec<Mat4>,
 }
 
 pub struct PbrNode {
@@ -152,12 +152,16 @@ impl Render for PbrNode {
         }
 
         for unique_material_mesh in self.unique_material_meshes.write().values_mut() {
-            unique_material_mesh.entities.clear();
+            unique_material_mesh.transforms.clear();
         }
 
-        let query = world.query::<(&Handle<ComponentBindGroup<GpuMaterial>>, &Handle<GpuMesh>)>();
+        let query = world.query::<(
+            &Handle<ComponentBindGroup<GpuMaterial>>,
+            &Handle<GpuMesh>,
+            Option<&Transform>,
+        )>();
 
-        for (entity, (material, gpu_mesh)) in query.iter() {
+        for (_, (material, gpu_mesh, transform)) in query.iter() {
             let mut unique_material_meshes = self.unique_material_meshes.write();
 
             let unique_material_mesh = unique_material_meshes
@@ -192,33 +196,29 @@ impl Render for PbrNode {
                         mesh: *gpu_mesh,
                         transform_buffer,
                         transform_bind_group,
-                        entities: Vec::new(),
+                        transforms: Vec::new(),
                     }
                 });
 
-            unique_material_mesh.entities.push(entity);
+            let transform = transform.map(|t| *t).unwrap_or_default();
+            unique_material_mesh.transforms.push(transform.matrix());
         }
 
+        // drop batches whose entities were all despawned or moved to other batches
+        self.unique_material_meshes
+            .write()
+            .retain(|_, unique_material_mesh| !unique_material_mesh.transforms.is_empty());
+
         for unique_material_mesh in self.unique_material_meshes.read().values() {
             let UniqueMaterialMesh {
                 transform_buffer,
-                entities,
+                transforms,
                 ..
             } = unique_material_mesh;
 
-            let mut transforms = Vec::new();
-
-            for entity in entities {
-                let transform = world
-                    .get_component::<Transform>(*entity)
-                    .map(|t| *t)
-                    .unwrap_or_default();
-                transforms.push(transform.matrix());
-            }
-
             renderer
                 .queue()
-                .write_buffer(transform_buffer, 0, bytemuck::cast_slice(&transforms));
+                .write_buffer(transform_buffer, 0, bytemuck::cast_slice(transforms));
         }
 
         Ok(())
@@ -266,7 +266,7 @@ impl Render for PbrNode {
                 mesh,
                 transform_buffer: _,
                 transform_bind_group,
-                entities,
+                transforms,
             } = unique_material_mesh;
 
             let material_bind_group = assets
@@ -306,7 +306,7 @@ impl Render for PbrNode {
                 render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
                 render_pass
                     .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                render_pass.draw_indexed(0..mesh.num_indices, 0, 0..entities.len() as u32);
+                render_pass.draw_indexed(0..mesh.num_indices, 0, 0..transforms.len() as u32);
             }
         }
 
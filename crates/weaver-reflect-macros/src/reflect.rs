@@ -63,6 +63,18 @@ pub fn impl_reflect_struct(name: &syn::Ident, fields: &syn::FieldsNamed) -> Toke
             fn reflect_type_name(&self) -> &'static str {
                 <Self as #reflect_module::Typed>::type_name()
             }
+
+            fn reflect_type_info(&self) -> &'static #reflect_module::TypeInfo {
+                <Self as #reflect_module::Typed>::type_info()
+            }
+
+            fn reflect_ref(&self) -> #reflect_module::ReflectRef<'_> {
+                #reflect_module::ReflectRef::Struct(self)
+            }
+
+            fn reflect_mut(&mut self) -> #reflect_module::ReflectMut<'_> {
+                #reflect_module::ReflectMut::Struct(self)
+            }
         }
 
         impl #reflect_module::Typed for #name {
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use weaver_ecs::{
+    component::Component, entity::Entity, node::Node, relationship::Relationship, scene::Scene,
+    world::World,
+};
+use weaver_util::prelude::{anyhow, Result};
+
+use crate::{
+    registry::{FromType, Typed},
+    Reflect,
+};
+
+type ReadComponentFn = fn(&World, Entity, &mut dyn FnMut(&dyn Reflect)) -> bool;
+
+// lets components be read and inserted when only their registration is known
+#[derive(Clone)]
+pub struct ReflectComponent {
+    default: fn() -> Box<dyn Reflect>,
+    insert: fn(&World, Entity, Box<dyn Reflect>) -> Result<()>,
+    read: ReadComponentFn,
+}
+
+impl ReflectComponent {
+    pub fn default_value(&self) -> Box<dyn Reflect> {
+        (self.default)()
+    }
+
+    pub fn insert(&self, world: &World, entity: Entity, component: Box<dyn Reflect>) -> Result<()> {
+        (self.insert)(world, entity, component)
+    }
+
+    // calls `f` with the entity's component, returning false if it has none
+    pub fn read(&self, world: &World, entity: Entity, f: &mut dyn FnMut(&dyn Reflect)) -> bool {
+        (self.read)(world, entity, f)
+    }
+}
+
+impl<T: Component + Reflect + Typed + Default> FromType<T> for ReflectComponent {
+    fn from_type() -> Arc<Self> {
+        Arc::new(Self {
+            default: || Box::new(T::default()),
+            insert: |world, entity, component| {
+                let component = component.take::<T>().map_err(|component| {
+                    anyhow!(
+                        "Expected a {}, got a {}",
+                        T::type_name(),
+                        component.reflect_type_name()
+                    )
+                })?;
+                world.insert_component(entity, component);
+                Ok(())
+            },
+            read: |world, entity, f| match world.get_component::<T>(entity) {
+                Some(component) => {
+                    f(&*component);
+                    true
+                }
+                None => false,
+            },
+        })
+    }
+}
+
+// the same for relationship weights stored in a scene graph
+#[derive(Clone)]
+pub struct ReflectRelationship {
+    default: fn() -> Box<dyn Reflect>,
+    add: fn(&Scene, Node, Node, Box<dyn Reflect>) -> Result<()>,
+    reflect: fn(&dyn Relationship) -> Option<&dyn Reflect>,
+}
+
+impl ReflectRelationship {
+    pub fn default_value(&self) -> Box<dyn Reflect> {
+        (self.default)()
+    }
+
+    pub fn add(&self, scene: &Scene, from: Node, to: Node, weight: Box<dyn Reflect>) -> Result<()> {
+        (self.add)(scene, from, to, weight)
+    }
+
+    pub fn reflect<'a>(&self, weight: &'a dyn Relationship) -> Option<&'a dyn Reflect> {
+        (self.reflect)(weight)
+    }
+}
+
+impl<T: Relationship + Reflect + Typed + Default> FromType<T> for ReflectRelationship {
+    fn from_type() -> Arc<Self> {
+        Arc::new(Self {
+            default: || Box::new(T::default()),
+            add: |scene, from, to, weight| {
+                let weight = weight.take::<T>().map_err(|weight| {
+                    anyhow!(
+                        "Expected a {}, got a {}",
+                        T::type_name(),
+                        weight.reflect_type_name()
+                    )
+                })?;
+                scene.add_relationship(from, to, weight);
+                Ok(())
+            },
+            reflect: |weight| {
+                weight
+                    .downcast_ref::<T>()
+                    .map(|weight| weight as &dyn Reflect)
+            },
+        })
+    }
+}
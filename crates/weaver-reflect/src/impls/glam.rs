@@ -2,7 +2,7 @@ use std::{any::TypeId, sync::OnceLock};
 
 use crate::{
     registry::{FieldInfo, Struct, StructInfo, TypeInfo, Typed},
-    Reflect,
+    Reflect, ReflectMut, ReflectRef,
 };
 
 macro_rules! impl_reflect {
@@ -23,6 +23,18 @@ macro_rules! impl_reflect {
             fn reflect_type_name(&self) -> &'static str {
                 Self::type_name()
             }
+
+            fn reflect_type_info(&self) -> &'static TypeInfo {
+                Self::type_info()
+            }
+
+            fn reflect_ref(&self) -> ReflectRef<'_> {
+                ReflectRef::Struct(self)
+            }
+
+            fn reflect_mut(&mut self) -> ReflectMut<'_> {
+                ReflectMut::Struct(self)
+            }
         }
     };
 }
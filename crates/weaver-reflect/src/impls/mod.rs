@@ -1,13 +1,48 @@
-use std::{any::TypeId, collections::HashMap, hash::Hash, sync::OnceLock};
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    hash::Hash,
+    sync::{OnceLock, RwLock},
+};
+
+use weaver_ecs::entity::Entity;
 
 use crate::{
     prelude::{ListInfo, MapInfo},
     registry::{TypeInfo, Typed, ValueInfo},
-    Reflect,
+    Reflect, ReflectMut, ReflectRef,
 };
 
 pub mod glam;
 
+// statics inside generic impls are shared by every instantiation, so generic types cache their
+// names and infos per type id instead
+fn generic_type_name<T: 'static>(init: impl FnOnce() -> String) -> &'static str {
+    static TYPE_NAMES: OnceLock<RwLock<HashMap<TypeId, &'static str>>> = OnceLock::new();
+    let names = TYPE_NAMES.get_or_init(Default::default);
+    if let Some(name) = names.read().unwrap().get(&TypeId::of::<T>()) {
+        return name;
+    }
+    names
+        .write()
+        .unwrap()
+        .entry(TypeId::of::<T>())
+        .or_insert_with(|| Box::leak(init().into_boxed_str()))
+}
+
+fn generic_type_info<T: 'static>(init: impl FnOnce() -> TypeInfo) -> &'static TypeInfo {
+    static TYPE_INFOS: OnceLock<RwLock<HashMap<TypeId, &'static TypeInfo>>> = OnceLock::new();
+    let infos = TYPE_INFOS.get_or_init(Default::default);
+    if let Some(info) = infos.read().unwrap().get(&TypeId::of::<T>()) {
+        return info;
+    }
+    infos
+        .write()
+        .unwrap()
+        .entry(TypeId::of::<T>())
+        .or_insert_with(|| Box::leak(Box::new(init())))
+}
+
 macro_rules! impl_primitive {
     ($t:ty) => {
         impl Reflect for $t {
@@ -26,6 +61,18 @@ macro_rules! impl_primitive {
             fn reflect_type_name(&self) -> &'static str {
                 Self::type_name()
             }
+
+            fn reflect_type_info(&self) -> &'static TypeInfo {
+                Self::type_info()
+            }
+
+            fn reflect_ref(&self) -> ReflectRef<'_> {
+                ReflectRef::Value(self)
+            }
+
+            fn reflect_mut(&mut self) -> ReflectMut<'_> {
+                ReflectMut::Value(self)
+            }
         }
 
         impl Typed for $t {
@@ -63,6 +110,7 @@ impl_primitive!(f64);
 impl_primitive!(bool);
 impl_primitive!(char);
 impl_primitive!(String);
+impl_primitive!(Entity);
 
 impl<T: Reflect + Typed> Reflect for Vec<T> {
     fn as_reflect(&self) -> &dyn Reflect {
@@ -80,17 +128,27 @@ impl<T: Reflect + Typed> Reflect for Vec<T> {
     fn reflect_type_name(&self) -> &'static str {
         Self::type_name()
     }
+
+    fn reflect_type_info(&self) -> &'static TypeInfo {
+        Self::type_info()
+    }
+
+    fn reflect_ref(&self) -> ReflectRef<'_> {
+        ReflectRef::List(self)
+    }
+
+    fn reflect_mut(&mut self) -> ReflectMut<'_> {
+        ReflectMut::List(self)
+    }
 }
 
 impl<T: Reflect + Typed> Typed for Vec<T> {
     fn type_name() -> &'static str {
-        static TYPE_NAME: OnceLock<String> = OnceLock::new();
-        TYPE_NAME.get_or_init(|| format!("Vec<{}>", T::type_name()))
+        generic_type_name::<Self>(|| format!("Vec<{}>", T::type_name()))
     }
 
     fn type_info() -> &'static TypeInfo {
-        static TYPE_INFO: OnceLock<TypeInfo> = OnceLock::new();
-        TYPE_INFO.get_or_init(|| TypeInfo::List(ListInfo::new::<Vec<T>, T>()))
+        generic_type_info::<Self>(|| TypeInfo::List(ListInfo::new::<Vec<T>, T>()))
     }
 }
 
@@ -140,7 +198,7 @@ impl<T: Reflect + Typed> crate::registry::List for Vec<T> {
     }
 }
 
-impl<K: Reflect + Typed, V: Reflect + Typed> Reflect for HashMap<K, V> {
+impl<K: Reflect + Typed + Hash + Eq, V: Reflect + Typed> Reflect for HashMap<K, V> {
     fn as_reflect(&self) -> &dyn Reflect {
         self
     }
@@ -154,20 +212,29 @@ impl<K: Reflect + Typed, V: Reflect + Typed> Reflect for HashMap<K, V> {
     }
 
     fn reflect_type_name(&self) -> &'static str {
-        static TYPE_NAME: OnceLock<String> = OnceLock::new();
-        TYPE_NAME.get_or_init(|| format!("HashMap<{}, {}>", K::type_name(), V::type_name()))
+        Self::type_name()
+    }
+
+    fn reflect_type_info(&self) -> &'static TypeInfo {
+        Self::type_info()
+    }
+
+    fn reflect_ref(&self) -> ReflectRef<'_> {
+        ReflectRef::Map(self)
+    }
+
+    fn reflect_mut(&mut self) -> ReflectMut<'_> {
+        ReflectMut::Map(self)
     }
 }
 
-impl<K: Reflect + Typed, V: Reflect + Typed> Typed for HashMap<K, V> {
+impl<K: Reflect + Typed + Hash + Eq, V: Reflect + Typed> Typed for HashMap<K, V> {
     fn type_name() -> &'static str {
-        static TYPE_NAME: OnceLock<String> = OnceLock::new();
-        TYPE_NAME.get_or_init(|| format!("HashMap<{}, {}>", K::type_name(), V::type_name()))
+        generic_type_name::<Self>(|| format!("HashMap<{}, {}>", K::type_name(), V::type_name()))
     }
 
     fn type_info() -> &'static TypeInfo {
-        static TYPE_INFO: OnceLock<TypeInfo> = OnceLock::new();
-        TYPE_INFO.get_or_init(|| TypeInfo::Map(MapInfo::new::<HashMap<K, V>, K, V>()))
+        generic_type_info::<Self>(|| TypeInfo::Map(MapInfo::new::<HashMap<K, V>, K, V>()))
     }
 }
 
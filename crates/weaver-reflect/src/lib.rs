@@ -1,11 +1,17 @@
+use registry::{List, Map, Struct, TypeInfo};
 use weaver_util::prelude::{impl_downcast, Downcast};
 
+pub mod component;
 pub mod impls;
 pub mod registry;
+pub mod scene;
+pub mod serialize;
 
 pub mod prelude {
+    pub use crate::component::*;
     pub use crate::registry::*;
-    pub use crate::Reflect;
+    pub use crate::scene::*;
+    pub use crate::{Reflect, ReflectMut, ReflectRef};
     pub use weaver_reflect_macros::*;
 }
 
@@ -15,9 +21,29 @@ pub trait Reflect: Downcast {
     fn into_reflect_box(self: Box<Self>) -> Box<dyn Reflect>;
 
     fn reflect_type_name(&self) -> &'static str;
+
+    fn reflect_type_info(&self) -> &'static TypeInfo;
+
+    fn reflect_ref(&self) -> ReflectRef<'_>;
+    fn reflect_mut(&mut self) -> ReflectMut<'_>;
 }
 impl_downcast!(Reflect);
 
+// what kind of value a `dyn Reflect` is, for walking it without knowing its type
+pub enum ReflectRef<'a> {
+    Struct(&'a dyn Struct),
+    List(&'a dyn List),
+    Map(&'a dyn Map),
+    Value(&'a dyn Reflect),
+}
+
+pub enum ReflectMut<'a> {
+    Struct(&'a mut dyn Struct),
+    List(&'a mut dyn List),
+    Map(&'a mut dyn Map),
+    Value(&'a mut dyn Reflect),
+}
+
 impl<T: Reflect> Reflect for Box<T> {
     fn as_reflect(&self) -> &dyn Reflect {
         self.as_ref()
@@ -34,12 +60,33 @@ impl<T: Reflect> Reflect for Box<T> {
     fn reflect_type_name(&self) -> &'static str {
         T::reflect_type_name(self)
     }
+
+    fn reflect_type_info(&self) -> &'static TypeInfo {
+        T::reflect_type_info(self)
+    }
+
+    fn reflect_ref(&self) -> ReflectRef<'_> {
+        T::reflect_ref(self)
+    }
+
+    fn reflect_mut(&mut self) -> ReflectMut<'_> {
+        T::reflect_mut(self)
+    }
 }
 
 impl dyn Reflect {
     pub fn take<T: Reflect>(self: Box<dyn Reflect>) -> Result<T, Box<dyn Reflect>> {
         self.downcast::<T>().map(|boxed| *boxed)
     }
+
+    // follows a dot-separated path of struct fields, e.g. "translation.x"
+    pub fn field_path_mut(&mut self, path: &str) -> Option<&mut dyn Reflect> {
+        path.split('.')
+            .try_fold(self, |value, name| match value.reflect_mut() {
+                ReflectMut::Struct(value) => value.field_mut(name),
+                _ => None,
+            })
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,6 @@
 use std::{any::TypeId, collections::HashMap, sync::Arc};
 
-use weaver_ecs::prelude::Resource;
+use weaver_ecs::{entity::Entity, prelude::Resource};
 use weaver_util::{
     prelude::{impl_downcast, DowncastSync},
     TypeIdMap,
@@ -193,6 +193,31 @@ pub trait FromType<T> {
     fn from_type() -> Arc<Self>;
 }
 
+// creates a default value of a type that is only known through its registration
+#[derive(Clone)]
+pub struct ReflectDefault {
+    default: fn() -> Box<dyn Reflect>,
+}
+
+impl ReflectDefault {
+    // for types that don't implement `Default` themselves
+    pub fn new(default: fn() -> Box<dyn Reflect>) -> Self {
+        Self { default }
+    }
+
+    pub fn default_value(&self) -> Box<dyn Reflect> {
+        (self.default)()
+    }
+}
+
+impl<T: Reflect + Default> FromType<T> for ReflectDefault {
+    fn from_type() -> Arc<Self> {
+        Arc::new(Self {
+            default: || Box::new(T::default()),
+        })
+    }
+}
+
 pub struct TypeRegistration {
     pub type_id: TypeId,
     pub type_name: &'static str,
@@ -200,6 +225,14 @@ pub struct TypeRegistration {
     pub type_aux_data: TypeIdMap<Arc<dyn TypeAuxData>>,
 }
 
+impl TypeRegistration {
+    pub fn data<D: TypeAuxData>(&self) -> Option<&D> {
+        self.type_aux_data
+            .get(&TypeId::of::<D>())
+            .and_then(|type_aux_data| type_aux_data.downcast_ref())
+    }
+}
+
 #[derive(Resource)]
 pub struct TypeRegistry {
     types: TypeIdMap<TypeRegistration>,
@@ -238,6 +271,43 @@ impl TypeRegistry {
         registry.register::<f64>();
         registry.register::<bool>();
         registry.register::<String>();
+        registry.register::<Entity>();
+        registry.register::<glam::Vec2>();
+        registry.register::<glam::Vec3>();
+        registry.register::<glam::Vec4>();
+        registry.register::<glam::Mat2>();
+        registry.register::<glam::Mat3>();
+        registry.register::<glam::Mat4>();
+        registry.register::<glam::Quat>();
+
+        registry.register_type_data::<u8, ReflectDefault>();
+        registry.register_type_data::<u16, ReflectDefault>();
+        registry.register_type_data::<u32, ReflectDefault>();
+        registry.register_type_data::<u64, ReflectDefault>();
+        registry.register_type_data::<u128, ReflectDefault>();
+        registry.register_type_data::<usize, ReflectDefault>();
+        registry.register_type_data::<i8, ReflectDefault>();
+        registry.register_type_data::<i16, ReflectDefault>();
+        registry.register_type_data::<i32, ReflectDefault>();
+        registry.register_type_data::<i64, ReflectDefault>();
+        registry.register_type_data::<i128, ReflectDefault>();
+        registry.register_type_data::<isize, ReflectDefault>();
+        registry.register_type_data::<f32, ReflectDefault>();
+        registry.register_type_data::<f64, ReflectDefault>();
+        registry.register_type_data::<bool, ReflectDefault>();
+        registry.register_type_data::<String, ReflectDefault>();
+        registry.register_type_data::<glam::Vec2, ReflectDefault>();
+        registry.register_type_data::<glam::Vec3, ReflectDefault>();
+        registry.register_type_data::<glam::Vec4, ReflectDefault>();
+        registry.register_type_data::<glam::Mat2, ReflectDefault>();
+        registry.register_type_data::<glam::Mat3, ReflectDefault>();
+        registry.register_type_data::<glam::Mat4, ReflectDefault>();
+        registry.register_type_data::<glam::Quat, ReflectDefault>();
+        // entities have no default; this one is never alive, and reading a saved entity id
+        // replaces it
+        registry.insert_type_data::<Entity, _>(ReflectDefault::new(|| {
+            Box::new(Entity::new(u32::MAX, u32::MAX))
+        }));
         registry
     }
 
@@ -258,6 +328,10 @@ impl TypeRegistry {
             .insert(type_registration.type_id, type_registration);
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &TypeRegistration> {
+        self.types.values()
+    }
+
     pub fn get_type_info<T: Reflect>(&self) -> Option<&TypeRegistration> {
         self.get_type_info_by_id(TypeId::of::<T>())
     }
@@ -277,19 +351,24 @@ impl TypeRegistry {
     }
 
     pub fn get_type_data_by_id<D: TypeAuxData>(&self, type_id: TypeId) -> Option<&D> {
-        self.types.get(&type_id).and_then(|type_registration| {
-            type_registration
-                .type_aux_data
-                .get(&type_id)
-                .and_then(|type_aux_data| type_aux_data.downcast_ref())
-        })
+        self.types
+            .get(&type_id)
+            .and_then(|type_registration| type_registration.data())
     }
 
+    // each kind of aux data is stored under its own type, so a type can have several
     pub fn register_type_data<T: Reflect, D: TypeAuxData + FromType<T>>(&mut self) {
-        let type_id = TypeId::of::<T>();
-        let type_registration = self.types.get_mut(&type_id).unwrap();
+        let type_registration = self.types.get_mut(&TypeId::of::<T>()).unwrap();
+        type_registration
+            .type_aux_data
+            .insert(TypeId::of::<D>(), D::from_type());
+    }
+
+    // like `register_type_data`, for aux data that can't be made from the type alone
+    pub fn insert_type_data<T: Reflect, D: TypeAuxData>(&mut self, data: D) {
+        let type_registration = self.types.get_mut(&TypeId::of::<T>()).unwrap();
         type_registration
             .type_aux_data
-            .insert(type_id, D::from_type());
+            .insert(TypeId::of::<D>(), Arc::new(data));
     }
 }
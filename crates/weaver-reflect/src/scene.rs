@@ -0,0 +1,489 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use weaver_ecs::{entity::Entity, node::Node, scene::Scene, world::World};
+use weaver_util::prelude::{anyhow, bail, Result};
+
+use crate::{
+    component::{ReflectComponent, ReflectRelationship},
+    registry::TypeRegistry,
+    serialize::{apply_json, to_json, IdToEntity},
+    Reflect,
+};
+
+// nodes are saved depth first, though they load in any order as long as every parent is in the
+// file; siblings keep their order, and ids are only meaningful within one file
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub nodes: Vec<NodeEntry>,
+    #[serde(default)]
+    pub relationships: Vec<RelationshipEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NodeEntry {
+    pub id: u64,
+    pub parent: Option<u64>,
+    #[serde(default)]
+    pub components: serde_json::Map<String, Json>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RelationshipEntry {
+    pub from: u64,
+    pub to: u64,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub value: Json,
+}
+
+// lets a component be changed between being deserialized and inserted, given the id of its
+// node in the file and its type name
+pub type PatchComponent<'a> = &'a mut dyn FnMut(u64, &str, &mut dyn Reflect) -> Result<()>;
+
+// writes every node under the scene root as JSON, along with the components and relationships
+// whose types are registered with `ReflectComponent` / `ReflectRelationship`
+pub fn save_scene(scene: &Scene, registry: &TypeRegistry) -> Result<String> {
+    let world = scene.world();
+    let nodes = scene
+        .iter_depth_first(scene.root())
+        .skip(1)
+        .collect::<Vec<_>>();
+    let ids = nodes
+        .iter()
+        .enumerate()
+        .map(|(id, node)| (node.entity(), id as u64))
+        .collect::<HashMap<_, _>>();
+    let entity_to_id = |entity: Entity| ids.get(&entity).copied();
+
+    let component_types = registry
+        .iter()
+        .filter_map(|registration| {
+            Some((
+                registration.type_name,
+                registration.data::<ReflectComponent>()?,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    let mut file = SceneFile {
+        nodes: Vec::with_capacity(nodes.len()),
+        relationships: Vec::new(),
+    };
+
+    for node in nodes {
+        let mut components = serde_json::Map::new();
+        for (type_name, reflect_component) in &component_types {
+            let mut json = Ok(Json::Null);
+            let found = reflect_component.read(world, node.entity(), &mut |component| {
+                json = to_json(component, &entity_to_id);
+            });
+            if found {
+                components.insert(type_name.to_string(), json?);
+            }
+        }
+
+        file.nodes.push(NodeEntry {
+            id: ids[&node.entity()],
+            parent: scene
+                .parent_of(node)
+                .and_then(|parent| entity_to_id(parent.entity())),
+            components,
+        });
+    }
+
+    let graph = scene.graph().read();
+    for connection in graph.edge_weights() {
+        let (Some(from), Some(to)) = (
+            entity_to_id(connection.from.entity()),
+            entity_to_id(connection.to.entity()),
+        ) else {
+            continue;
+        };
+
+        // relationships nobody registered can't be read back, so they're left out
+        let type_id = (*connection.weight).as_any().type_id();
+        let Some(registration) = registry.get_type_info_by_id(type_id) else {
+            continue;
+        };
+        let Some(reflect_relationship) = registration.data::<ReflectRelationship>() else {
+            continue;
+        };
+        let weight = reflect_relationship
+            .reflect(&*connection.weight)
+            .ok_or_else(|| anyhow!("Relationship is not a {}", registration.type_name))?;
+
+        file.relationships.push(RelationshipEntry {
+            from,
+            to,
+            type_name: registration.type_name.to_owned(),
+            value: to_json(weight, &entity_to_id)?,
+        });
+    }
+    drop(graph);
+
+    Ok(serde_json::to_string_pretty(&file)?)
+}
+
+// adds the nodes described by `source` to the scene, under its root, and returns them in file
+// order; entity references between them are remapped to the newly spawned entities
+pub fn load_scene(scene: &Scene, registry: &TypeRegistry, source: &str) -> Result<Vec<Node>> {
+    let file: SceneFile = serde_json::from_str(source)?;
+    spawn_scene_file(scene, scene.root(), registry, &file, &mut |_, _, _| Ok(()))
+}
+
+// like `load_scene`, but for an already parsed file whose top-level nodes go under `parent`
+pub fn spawn_scene_file(
+    scene: &Scene,
+    parent: Node,
+    registry: &TypeRegistry,
+    file: &SceneFile,
+    patch: PatchComponent,
+) -> Result<Vec<Node>> {
+    deserialize_scene_file(scene.world(), registry, file, patch)?.spawn(scene, parent)
+}
+
+// a scene file read against the registry, which can be spawned once the registry is unlocked
+// again, in case a hook needs it; its entities are already allocated, so it should be spawned
+pub struct LoadedScene {
+    // node ids in file order
+    ids: Vec<u64>,
+    entities: HashMap<u64, Entity>,
+    // (id, parent id), with parents before their children
+    attach_order: Vec<(u64, Option<u64>)>,
+    components: Vec<LoadedComponent>,
+    relationships: Vec<LoadedRelationship>,
+}
+
+type LoadedComponent = (Entity, ReflectComponent, Box<dyn Reflect>);
+type LoadedRelationship = (u64, u64, ReflectRelationship, Box<dyn Reflect>);
+type DeserializedScene = (
+    Vec<(u64, Option<u64>)>,
+    Vec<LoadedComponent>,
+    Vec<LoadedRelationship>,
+);
+
+// everything is deserialized before the scene is touched, so a bad file leaves it as it was
+pub fn deserialize_scene_file(
+    world: &World,
+    registry: &TypeRegistry,
+    file: &SceneFile,
+    patch: PatchComponent,
+) -> Result<LoadedScene> {
+    let mut entities = HashMap::with_capacity(file.nodes.len());
+    for entry in &file.nodes {
+        if entities.contains_key(&entry.id) {
+            for entity in entities.into_values() {
+                world.destroy_entity(entity);
+            }
+            bail!("Node id {} is used more than once", entry.id);
+        }
+        entities.insert(entry.id, world.create_entity());
+    }
+
+    match deserialize_scene(file, registry, &entities, patch) {
+        Ok((attach_order, components, relationships)) => Ok(LoadedScene {
+            ids: file.nodes.iter().map(|entry| entry.id).collect(),
+            entities,
+            attach_order,
+            components,
+            relationships,
+        }),
+        Err(err) => {
+            for entity in entities.into_values() {
+                world.destroy_entity(entity);
+            }
+            Err(err)
+        }
+    }
+}
+
+impl LoadedScene {
+    // adds the nodes to the scene, with the top-level ones under `parent`, and returns them in
+    // file order
+    pub fn spawn(self, scene: &Scene, parent: Node) -> Result<Vec<Node>> {
+        let world = scene.world();
+
+        let mut nodes = HashMap::with_capacity(self.ids.len());
+        for (id, node_parent) in &self.attach_order {
+            let node = scene.add_node(self.entities[id]);
+            match node_parent {
+                Some(node_parent) => scene.set_parent(node, nodes[node_parent])?,
+                None if parent != scene.root() => scene.set_parent(node, parent)?,
+                None => {}
+            }
+            nodes.insert(*id, node);
+        }
+
+        for (entity, reflect_component, component) in self.components {
+            reflect_component.insert(world, entity, component)?;
+        }
+
+        for (from, to, reflect_relationship, weight) in self.relationships {
+            reflect_relationship.add(scene, nodes[&from], nodes[&to], weight)?;
+        }
+
+        Ok(self.ids.iter().map(|id| nodes[id]).collect())
+    }
+}
+
+// a node may be listed before its parent, so they're attached walking down from the top-level
+// nodes, with siblings in file order
+fn attach_order(file: &SceneFile) -> Result<Vec<(u64, Option<u64>)>> {
+    let ids = file
+        .nodes
+        .iter()
+        .map(|entry| entry.id)
+        .collect::<HashSet<_>>();
+
+    let mut roots = Vec::new();
+    let mut children = HashMap::<u64, Vec<u64>>::new();
+    for entry in &file.nodes {
+        match entry.parent {
+            Some(parent) if !ids.contains(&parent) => {
+                bail!(
+                    "Node {} has a parent {} that isn't in the file",
+                    entry.id,
+                    parent
+                );
+            }
+            Some(parent) => children.entry(parent).or_default().push(entry.id),
+            None => roots.push(entry.id),
+        }
+    }
+
+    let mut order = Vec::with_capacity(file.nodes.len());
+    let mut stack = roots
+        .into_iter()
+        .rev()
+        .map(|id| (id, None))
+        .collect::<Vec<_>>();
+    while let Some((id, parent)) = stack.pop() {
+        order.push((id, parent));
+        if let Some(children) = children.get(&id) {
+            stack.extend(children.iter().rev().map(|child| (*child, Some(id))));
+        }
+    }
+
+    if order.len() != file.nodes.len() {
+        bail!("Some nodes are their own ancestors");
+    }
+    Ok(order)
+}
+
+fn deserialize_scene(
+    file: &SceneFile,
+    registry: &TypeRegistry,
+    entities: &HashMap<u64, Entity>,
+    patch: PatchComponent,
+) -> Result<DeserializedScene> {
+    let id_to_entity = |id: u64| entities.get(&id).copied();
+
+    let attach_order = attach_order(file)?;
+
+    let mut components = Vec::new();
+    for entry in &file.nodes {
+        for (type_name, json) in &entry.components {
+            let (reflect_component, mut component) =
+                deserialize_component(registry, type_name, json, &id_to_entity)?;
+            patch(entry.id, type_name, &mut *component)?;
+            components.push((entities[&entry.id], reflect_component.clone(), component));
+        }
+    }
+
+    let mut relationships = Vec::with_capacity(file.relationships.len());
+    for entry in &file.relationships {
+        if !entities.contains_key(&entry.from) || !entities.contains_key(&entry.to) {
+            bail!(
+                "Relationship {} refers to a missing node ({} -> {})",
+                entry.type_name,
+                entry.from,
+                entry.to
+            );
+        }
+
+        let registration = registry
+            .get_type_info_by_name(&entry.type_name)
+            .ok_or_else(|| anyhow!("Unknown relationship type {}", entry.type_name))?;
+        let reflect_relationship = registration
+            .data::<ReflectRelationship>()
+            .ok_or_else(|| anyhow!("{} is not registered as a relationship", entry.type_name))?;
+
+        let mut weight = reflect_relationship.default_value();
+        apply_json(&mut *weight, &entry.value, registry, &id_to_entity)?;
+        relationships.push((entry.from, entry.to, reflect_relationship.clone(), weight));
+    }
+
+    Ok((attach_order, components, relationships))
+}
+
+// a component of a registered type, read from `json` on top of its default value
+pub fn deserialize_component<'a>(
+    registry: &'a TypeRegistry,
+    type_name: &str,
+    json: &Json,
+    id_to_entity: IdToEntity,
+) -> Result<(&'a ReflectComponent, Box<dyn Reflect>)> {
+    let registration = registry
+        .get_type_info_by_name(type_name)
+        .ok_or_else(|| anyhow!("Unknown component type {type_name}"))?;
+    let reflect_component = registration
+        .data::<ReflectComponent>()
+        .ok_or_else(|| anyhow!("{type_name} is not registered as a component"))?;
+
+    let mut component = reflect_component.default_value();
+    apply_json(&mut *component, json, registry, id_to_entity)?;
+    Ok((reflect_component, component))
+}
+
+#[cfg(test)]
+mod tests {
+    use weaver_ecs::{prelude::Component, relationship::Relationship, world::World};
+
+    use crate::{self as weaver_reflect, prelude::Reflect};
+
+    use super::*;
+
+    #[derive(Component, Reflect, Default)]
+    struct Health {
+        current: u32,
+        tags: Vec<String>,
+    }
+
+    #[derive(Component, Reflect)]
+    struct Target {
+        entity: Entity,
+        others: Vec<Entity>,
+        waypoints: Vec<glam::Vec3>,
+    }
+
+    impl Default for Target {
+        fn default() -> Self {
+            Self {
+                entity: Entity::new(u32::MAX, 0),
+                others: Vec::new(),
+                waypoints: Vec::new(),
+            }
+        }
+    }
+
+    #[derive(Reflect, Default)]
+    struct Likes {
+        amount: f32,
+    }
+
+    impl Relationship for Likes {}
+
+    fn registry() -> TypeRegistry {
+        let mut registry = TypeRegistry::new();
+        registry.register::<Health>();
+        registry.register_type_data::<Health, ReflectComponent>();
+        registry.register::<Target>();
+        registry.register_type_data::<Target, ReflectComponent>();
+        registry.register::<Likes>();
+        registry.register_type_data::<Likes, ReflectRelationship>();
+        registry
+    }
+
+    #[test]
+    fn test_scene_round_trip() {
+        let registry = registry();
+
+        let world = World::new();
+        let scene = world.root_scene();
+        let parent = scene.spawn(Health {
+            current: 10,
+            tags: vec!["boss".to_owned()],
+        });
+        let first = scene.spawn_child(parent, Health::default()).unwrap();
+        let second = scene
+            .spawn_child(
+                parent,
+                Target {
+                    entity: parent.entity(),
+                    others: vec![first.entity(), parent.entity()],
+                    waypoints: vec![glam::Vec3::new(1.0, 2.0, 3.0)],
+                },
+            )
+            .unwrap();
+        scene.add_relationship(first, second, Likes { amount: 0.5 });
+
+        let source = save_scene(&scene, &registry).unwrap();
+        drop(scene);
+
+        let other_world = World::new();
+        let other_scene = other_world.root_scene();
+        let nodes = load_scene(&other_scene, &registry, &source).unwrap();
+        assert_eq!(nodes.len(), 3);
+        let [parent, first, second] = [nodes[0], nodes[1], nodes[2]];
+
+        assert_eq!(other_scene.children_of(parent), vec![first, second]);
+        assert_eq!(other_scene.parent_of(parent), Some(other_scene.root()));
+
+        let health = other_world
+            .get_component::<Health>(parent.entity())
+            .unwrap();
+        assert_eq!(health.current, 10);
+        assert_eq!(health.tags, vec!["boss".to_owned()]);
+        assert!(other_world.has_component::<Health>(first.entity()));
+
+        // entity references point at the loaded entities, not the saved ones
+        let target = other_world
+            .get_component::<Target>(second.entity())
+            .unwrap();
+        assert_eq!(target.entity, parent.entity());
+        assert_eq!(target.others, vec![first.entity(), parent.entity()]);
+        assert_eq!(target.waypoints, vec![glam::Vec3::new(1.0, 2.0, 3.0)]);
+
+        let likes = other_scene
+            .get_relationship::<Likes>(first, second)
+            .unwrap();
+        assert_eq!(likes.amount, 0.5);
+
+        // saving again gives the same file
+        assert_eq!(save_scene(&other_scene, &registry).unwrap(), source);
+    }
+
+    #[test]
+    fn test_load_unknown_component() {
+        let registry = registry();
+        let world = World::new();
+        let scene = world.root_scene();
+        let entity_count = world.entity_count();
+
+        let source = r#"{ "nodes": [{ "id": 0, "parent": null, "components": { "Mana": {} } }] }"#;
+        assert!(load_scene(&scene, &registry, source).is_err());
+
+        // nothing is left behind
+        assert_eq!(world.entity_count(), entity_count);
+        assert!(scene.children_of(scene.root()).is_empty());
+    }
+
+    #[test]
+    fn test_load_out_of_order() {
+        let registry = registry();
+        let world = World::new();
+        let scene = world.root_scene();
+
+        // the child comes first, but is still attached to its parent
+        let source = r#"{ "nodes": [
+            { "id": 1, "parent": 0, "components": { "Health": { "current": 1 } } },
+            { "id": 0, "parent": null, "components": { "Health": { "current": 0 } } }
+        ] }"#;
+        let nodes = load_scene(&scene, &registry, source).unwrap();
+        assert_eq!(scene.children_of(nodes[1]), vec![nodes[0]]);
+        assert_eq!(scene.children_of(scene.root()), vec![nodes[1]]);
+        let health = world.get_component::<Health>(nodes[0].entity()).unwrap();
+        assert_eq!(health.current, 1);
+        drop(health);
+
+        let entity_count = world.entity_count();
+        let cycle = r#"{ "nodes": [
+            { "id": 0, "parent": 1 },
+            { "id": 1, "parent": 0 }
+        ] }"#;
+        assert!(load_scene(&scene, &registry, cycle).is_err());
+        assert_eq!(world.entity_count(), entity_count);
+    }
+}
@@ -0,0 +1,137 @@
+use serde_json::Value as Json;
+use weaver_ecs::entity::Entity;
+use weaver_util::prelude::{anyhow, bail, Result};
+
+use crate::{
+    registry::{ReflectDefault, TypeInfo, TypeRegistry},
+    Reflect, ReflectMut, ReflectRef,
+};
+
+// entities only mean something inside the world they came from, so entity values are written
+// and read through these maps; an entity with no id is written as null
+pub type EntityToId<'a> = &'a dyn Fn(Entity) -> Option<u64>;
+pub type IdToEntity<'a> = &'a dyn Fn(u64) -> Option<Entity>;
+
+macro_rules! primitives {
+    ($m:ident) => {
+        $m!(
+            u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char,
+            String
+        )
+    };
+}
+
+pub fn to_json(value: &dyn Reflect, entity_to_id: EntityToId) -> Result<Json> {
+    match value.reflect_ref() {
+        ReflectRef::Struct(value) => {
+            let TypeInfo::Struct(info) = value.reflect_type_info() else {
+                bail!("{} is not described as a struct", value.reflect_type_name());
+            };
+            let mut fields = serde_json::Map::new();
+            for &name in info.field_names.iter() {
+                let field = value
+                    .field(name)
+                    .ok_or_else(|| anyhow!("{} has no field {}", info.type_name, name))?;
+                fields.insert(name.to_owned(), to_json(field, entity_to_id)?);
+            }
+            Ok(Json::Object(fields))
+        }
+        ReflectRef::List(list) => {
+            let mut items = Vec::with_capacity(list.len_reflect());
+            for index in 0..list.len_reflect() {
+                let item = list.get_reflect(index).unwrap();
+                items.push(to_json(item, entity_to_id)?);
+            }
+            Ok(Json::Array(items))
+        }
+        ReflectRef::Map(map) => bail!("Maps can't be serialized yet ({})", map.reflect_type_name()),
+        ReflectRef::Value(value) => {
+            if let Some(entity) = value.downcast_ref::<Entity>() {
+                return Ok(entity_to_id(*entity).map_or(Json::Null, Json::from));
+            }
+
+            macro_rules! try_primitive {
+                ($($t:ty),*) => {
+                    $(
+                        if let Some(value) = value.downcast_ref::<$t>() {
+                            return Ok(serde_json::to_value(value)?);
+                        }
+                    )*
+                };
+            }
+            primitives!(try_primitive);
+
+            bail!("Can't serialize a {}", value.reflect_type_name())
+        }
+    }
+}
+
+// overwrites `value` with what's in `json`; struct fields missing from `json` keep their values
+pub fn apply_json(
+    value: &mut dyn Reflect,
+    json: &Json,
+    registry: &TypeRegistry,
+    id_to_entity: IdToEntity,
+) -> Result<()> {
+    let type_name = value.reflect_type_name();
+    match value.reflect_mut() {
+        ReflectMut::Struct(value) => {
+            let Json::Object(fields) = json else {
+                bail!("Expected an object for {type_name}, got {json}");
+            };
+            for (name, field_json) in fields {
+                let field = value
+                    .field_mut(name)
+                    .ok_or_else(|| anyhow!("{type_name} has no field {name}"))?;
+                apply_json(field, field_json, registry, id_to_entity)?;
+            }
+        }
+        ReflectMut::List(list) => {
+            let Json::Array(items) = json else {
+                bail!("Expected an array for {type_name}, got {json}");
+            };
+            let TypeInfo::List(info) = list.reflect_type_info() else {
+                bail!("{type_name} is not described as a list");
+            };
+            let default = registry
+                .get_type_data_by_id::<ReflectDefault>(info.item_type_id)
+                .ok_or_else(|| anyhow!("No default registered for {}", info.item_type_name))?;
+
+            list.clear_reflect();
+            for item_json in items {
+                let mut item = default.default_value();
+                apply_json(&mut *item, item_json, registry, id_to_entity)?;
+                list.push_reflect(item);
+            }
+        }
+        ReflectMut::Map(_) => bail!("Maps can't be deserialized yet ({type_name})"),
+        ReflectMut::Value(value) => {
+            if let Some(entity) = value.downcast_mut::<Entity>() {
+                // null stands for an entity that wasn't saved along with this one
+                if !json.is_null() {
+                    let id = json
+                        .as_u64()
+                        .ok_or_else(|| anyhow!("Expected an entity id, got {json}"))?;
+                    *entity = id_to_entity(id).ok_or_else(|| anyhow!("Unknown entity id {id}"))?;
+                }
+                return Ok(());
+            }
+
+            macro_rules! try_primitive {
+                ($($t:ty),*) => {
+                    $(
+                        if let Some(value) = value.downcast_mut::<$t>() {
+                            *value = serde_json::from_value(json.clone())?;
+                            return Ok(());
+                        }
+                    )*
+                };
+            }
+            primitives!(try_primitive);
+
+            bail!("Can't deserialize a {type_name}")
+        }
+    }
+
+    Ok(())
+}
@@ -1,8 +1,15 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use weaver_app::{plugin::Plugin, system::SystemStage, App};
 use weaver_asset::{Asset, Assets, Handle, UntypedHandle};
-use weaver_ecs::{prelude::Resource, world::World};
+use weaver_ecs::{
+    entity::Entity,
+    prelude::{Commands, Resource},
+    world::World,
+};
 
 use crate::Renderer;
 
@@ -17,6 +24,12 @@ pub trait RenderAsset: Asset {
     where
         Self: Sized;
 
+    // other assets extraction reads, e.g. textures; while the base asset or any of these isn't
+    // loaded, extraction waits for them, and otherwise failing to extract is an error
+    fn dependencies(_base_asset: &Self::BaseAsset) -> Vec<UntypedHandle> {
+        Vec::new()
+    }
+
     fn update_render_asset(
         &self,
         base_asset: &Self::BaseAsset,
@@ -46,6 +59,24 @@ impl ExtractedRenderAssets {
     }
 }
 
+// entities whose base asset handle was inserted or replaced since the last extraction, along
+// with the ones waiting on assets to load
+#[derive(Resource)]
+pub struct PendingRenderAssets<T: RenderAsset> {
+    // a set, since an entity can be queued again before it's handled
+    entities: HashSet<Entity>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T: RenderAsset> Default for PendingRenderAssets<T> {
+    fn default() -> Self {
+        Self {
+            entities: HashSet::new(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
 pub struct ExtractRenderAssetPlugin<T: RenderAsset>(std::marker::PhantomData<T>);
 
 impl<T: RenderAsset> Default for ExtractRenderAssetPlugin<T> {
@@ -56,35 +87,78 @@ impl<T: RenderAsset> Default for ExtractRenderAssetPlugin<T> {
 
 impl<T: RenderAsset> Plugin for ExtractRenderAssetPlugin<T> {
     fn build(&self, app: &mut App) -> anyhow::Result<()> {
+        // entities spawned before the plugin was added never ran the hooks
+        let existing = app
+            .world()
+            .query::<&Handle<T::BaseAsset>>()
+            .entity_iter()
+            .collect();
+        app.insert_resource(PendingRenderAssets::<T> {
+            entities: existing,
+            _marker: std::marker::PhantomData,
+        });
+        app.on_insert::<Handle<T::BaseAsset>>(queue_render_asset::<T>);
+        app.on_replace::<Handle<T::BaseAsset>>(queue_render_asset::<T>);
+
         app.add_system(extract_render_asset::<T>, SystemStage::Extract)?;
         app.add_system(update_render_asset::<T>, SystemStage::PreRender)?;
         Ok(())
     }
 }
 
-fn extract_render_asset<T: RenderAsset>(world: &Arc<World>) -> anyhow::Result<()> {
-    // query for handles to the base asset
-    let query = world.query::<&Handle<T::BaseAsset>>();
+fn queue_render_asset<T: RenderAsset>(world: &World, entity: Entity) {
+    if let Some(mut pending) = world.get_resource_mut::<PendingRenderAssets<T>>() {
+        pending.entities.insert(entity);
+    }
+}
+
+fn extract_render_asset<T: RenderAsset>(
+    world: Arc<World>,
+    mut commands: Commands,
+) -> anyhow::Result<()> {
+    let entities = std::mem::take(
+        &mut world
+            .get_resource_mut::<PendingRenderAssets<T>>()
+            .unwrap()
+            .entities,
+    );
+    // extraction waits on assets that aren't loaded yet, so these go again next frame
+    let mut waiting = Vec::new();
+
+    for entity in entities {
+        // the handle may have been removed, or the entity despawned, since it was queued
+        let Some(handle) = world.get_component::<Handle<T::BaseAsset>>(entity) else {
+            continue;
+        };
+        let handle = *handle;
 
-    for (entity, handle) in query.iter() {
         let mut extracted_assets = world.get_resource_mut::<ExtractedRenderAssets>().unwrap();
-        if extracted_assets.contains(&handle.into_untyped()) {
-            if !world.has_component::<Handle<T>>(entity) {
-                // if the asset has already been extracted, insert the render asset handle into the entity
-                let render_handle = *extracted_assets.assets.get(&handle.into_untyped()).unwrap();
-                drop(handle);
-                let render_handle = Handle::<T>::try_from(render_handle).unwrap();
-
-                world.insert_component(entity, render_handle);
-            }
+        if let Some(render_handle) = extracted_assets.assets.get(&handle.into_untyped()) {
+            // if the asset has already been extracted, insert the render asset handle into the entity
+            let render_handle = Handle::<T>::try_from(*render_handle).unwrap();
+            commands.insert(entity, render_handle);
         } else {
             // if the asset has not been extracted yet, extract it
             let renderer = world
                 .get_resource::<Renderer>()
                 .expect("Renderer resource not present before extracting render asset");
             let assets = world.get_resource::<Assets>().unwrap();
-            let base_asset = assets.get::<T::BaseAsset>(*handle).unwrap();
-            if let Some(render_asset) = T::extract_render_asset(base_asset, world, &renderer) {
+            let Some(base_asset) = assets.get::<T::BaseAsset>(handle) else {
+                waiting.push(entity);
+                continue;
+            };
+            if !T::dependencies(base_asset)
+                .into_iter()
+                .all(|dependency| assets.is_loaded(dependency))
+            {
+                log::trace!(
+                    "Render asset dependencies not loaded, retrying next frame: {:?}",
+                    std::any::type_name::<T>()
+                );
+                waiting.push(entity);
+                continue;
+            }
+            if let Some(render_asset) = T::extract_render_asset(base_asset, &world, &renderer) {
                 log::debug!("Extracted render asset: {:?}", std::any::type_name::<T>());
 
                 // insert the render asset into the asset storage
@@ -92,15 +166,13 @@ fn extract_render_asset<T: RenderAsset>(world: &Arc<World>) -> anyhow::Result<()
                 let mut assets = world.get_resource_mut::<Assets>().unwrap();
                 let render_handle = assets.insert(render_asset);
 
-                let untyped_handle = handle.into_untyped();
-                drop(handle);
-
                 // insert the render asset handle into the entity
-                world.insert_component(entity, render_handle);
+                commands.insert(entity, render_handle);
 
                 // mark the original asset as extracted
-                extracted_assets.insert(untyped_handle, render_handle.into_untyped());
+                extracted_assets.insert(handle.into_untyped(), render_handle.into_untyped());
             } else {
+                // everything it needs is loaded, so trying again wouldn't help
                 log::error!(
                     "Failed to extract render asset: {:?}",
                     std::any::type_name::<T>()
@@ -109,6 +181,12 @@ fn extract_render_asset<T: RenderAsset>(world: &Arc<World>) -> anyhow::Result<()
         }
     }
 
+    world
+        .get_resource_mut::<PendingRenderAssets<T>>()
+        .unwrap()
+        .entities
+        .extend(waiting);
+
     Ok(())
 }
 
@@ -128,3 +206,161 @@ fn update_render_asset<T: RenderAsset>(world: &Arc<World>) -> anyhow::Result<()>
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use weaver_util::prelude::{bail, Result};
+
+    use super::*;
+
+    struct Texture;
+
+    impl Asset for Texture {
+        fn load(_assets: &mut Assets, _path: &std::path::Path) -> Result<Self> {
+            bail!("Texture cannot be loaded from a file")
+        }
+    }
+
+    struct Material {
+        texture: Handle<Texture>,
+    }
+
+    impl Asset for Material {
+        fn load(_assets: &mut Assets, _path: &std::path::Path) -> Result<Self> {
+            bail!("Material cannot be loaded from a file")
+        }
+    }
+
+    struct GpuMaterial;
+
+    impl Asset for GpuMaterial {
+        fn load(_assets: &mut Assets, _path: &std::path::Path) -> Result<Self> {
+            bail!("GpuMaterial cannot be loaded from a file")
+        }
+    }
+
+    impl RenderAsset for GpuMaterial {
+        type BaseAsset = Material;
+
+        fn extract_render_asset(
+            base_asset: &Material,
+            world: &World,
+            _renderer: &Renderer,
+        ) -> Option<Self> {
+            let assets = world.get_resource::<Assets>()?;
+            assets.get(base_asset.texture)?;
+            Some(GpuMaterial)
+        }
+
+        fn dependencies(base_asset: &Material) -> Vec<UntypedHandle> {
+            vec![base_asset.texture.into_untyped()]
+        }
+
+        fn update_render_asset(
+            &self,
+            _base_asset: &Material,
+            _world: &World,
+            _renderer: &Renderer,
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    // fails even with everything loaded
+    struct BrokenGpuMaterial;
+
+    impl Asset for BrokenGpuMaterial {
+        fn load(_assets: &mut Assets, _path: &std::path::Path) -> Result<Self> {
+            bail!("BrokenGpuMaterial cannot be loaded from a file")
+        }
+    }
+
+    impl RenderAsset for BrokenGpuMaterial {
+        type BaseAsset = Material;
+
+        fn extract_render_asset(
+            _base_asset: &Material,
+            _world: &World,
+            _renderer: &Renderer,
+        ) -> Option<Self> {
+            None
+        }
+
+        fn update_render_asset(
+            &self,
+            _base_asset: &Material,
+            _world: &World,
+            _renderer: &Renderer,
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn app_with<T: RenderAsset>() -> App {
+        let mut app = App::new().unwrap();
+        app.insert_resource(Renderer::new());
+        app.insert_resource(ExtractedRenderAssets::new());
+        app.insert_resource(Assets::new());
+        app.add_plugin(ExtractRenderAssetPlugin::<T>::default())
+            .unwrap();
+        app
+    }
+
+    #[test]
+    fn test_extract_retries_until_ready() {
+        let app = app_with::<GpuMaterial>();
+
+        // the material gets the first handle and its texture the second, both loaded late
+        let entity = app.world().spawn(Handle::<Material>::from_raw(0));
+        let extracted = |app: &App| {
+            app.world()
+                .get_component::<Handle<GpuMaterial>>(entity)
+                .is_some()
+        };
+
+        app.run_schedule(SystemStage::Extract).unwrap();
+        assert!(!extracted(&app));
+
+        app.get_resource_mut::<Assets>().unwrap().insert(Material {
+            texture: Handle::from_raw(1),
+        });
+        app.run_schedule(SystemStage::Extract).unwrap();
+        assert!(!extracted(&app));
+
+        app.get_resource_mut::<Assets>().unwrap().insert(Texture);
+        app.run_schedule(SystemStage::Extract).unwrap();
+        assert!(extracted(&app));
+        assert!(app
+            .get_resource::<PendingRenderAssets<GpuMaterial>>()
+            .unwrap()
+            .entities
+            .is_empty());
+    }
+
+    #[test]
+    fn test_extract_failure_is_dropped() {
+        let app = app_with::<BrokenGpuMaterial>();
+        let mut assets = app.get_resource_mut::<Assets>().unwrap();
+        let texture = assets.insert(Texture);
+        let material = assets.insert(Material { texture });
+        drop(assets);
+
+        // inserting and then replacing the handle queues the entity once
+        let entity = app.world().spawn(material);
+        app.world().insert_component(entity, material);
+        let pending = || {
+            app.get_resource::<PendingRenderAssets<BrokenGpuMaterial>>()
+                .unwrap()
+                .entities
+                .len()
+        };
+        assert_eq!(pending(), 1);
+
+        app.run_schedule(SystemStage::Extract).unwrap();
+        assert_eq!(pending(), 0);
+        assert!(app
+            .world()
+            .get_component::<Handle<BrokenGpuMaterial>>(entity)
+            .is_none());
+    }
+}
@@ -1,10 +1,15 @@
-use std::{collections::HashMap, ops::Deref, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    sync::Arc,
+};
 
 use anyhow::bail;
 use weaver_app::{plugin::Plugin, system::SystemStage, App};
 use weaver_asset::{prelude::Asset, Assets, Handle, UntypedHandle};
 use weaver_ecs::{
-    prelude::{Component, Resource},
+    entity::Entity,
+    prelude::{Commands, Component, Resource},
     world::World,
 };
 
@@ -61,6 +66,14 @@ where
     }
 }
 
+// entities whose `T` was inserted or replaced since bind groups were last created
+#[derive(Resource)]
+pub struct PendingBindGroups<T: CreateComponentBindGroup> {
+    // a set, since an entity can be queued again before it's handled
+    entities: HashSet<Entity>,
+    _marker: std::marker::PhantomData<T>,
+}
+
 pub struct ComponentBindGroupPlugin<T: CreateComponentBindGroup>(std::marker::PhantomData<T>);
 
 impl<T: CreateComponentBindGroup> Default for ComponentBindGroupPlugin<T> {
@@ -71,22 +84,59 @@ impl<T: CreateComponentBindGroup> Default for ComponentBindGroupPlugin<T> {
 
 impl<T: CreateComponentBindGroup> Plugin for ComponentBindGroupPlugin<T> {
     fn build(&self, app: &mut App) -> anyhow::Result<()> {
+        // entities spawned before the plugin was added never ran the hooks
+        let existing = app.world().query::<&T>().entity_iter().collect();
+        app.insert_resource(PendingBindGroups::<T> {
+            entities: existing,
+            _marker: std::marker::PhantomData,
+        });
+        app.on_insert::<T>(queue_bind_group::<T>);
+        app.on_replace::<T>(queue_bind_group::<T>);
+
         app.add_system(create_bind_groups::<T>, SystemStage::PreRender)?;
+        app.add_system(remove_bind_groups::<T>, SystemStage::PreRender)?;
         Ok(())
     }
 }
 
-fn create_bind_groups<T: CreateComponentBindGroup>(world: &Arc<World>) -> anyhow::Result<()> {
+fn queue_bind_group<T: CreateComponentBindGroup>(world: &World, entity: Entity) {
+    if let Some(mut pending) = world.get_resource_mut::<PendingBindGroups<T>>() {
+        pending.entities.insert(entity);
+    }
+}
+
+// replacing `T` rebuilds its bind group
+fn create_bind_groups<T: CreateComponentBindGroup>(
+    world: Arc<World>,
+    mut commands: Commands,
+) -> anyhow::Result<()> {
     let renderer = world.clone().get_resource::<Renderer>().unwrap();
     let device = renderer.device();
 
-    let query = world.query::<&T>();
+    let entities = std::mem::take(
+        &mut world
+            .get_resource_mut::<PendingBindGroups<T>>()
+            .unwrap()
+            .entities,
+    );
+
+    for entity in entities {
+        // `T` may have been removed again since the entity was queued
+        let Some(data) = world.get_component::<T>(entity) else {
+            continue;
+        };
+        let bind_group = ComponentBindGroup::new(device, &*data);
+        commands.insert(entity, bind_group);
+    }
 
-    for (entity, data) in query.iter() {
-        if !world.has_component::<ComponentBindGroup<T>>(entity) {
-            let bind_group = ComponentBindGroup::new(device, &*data);
-            drop(data);
-            world.insert_component(entity, bind_group);
+    Ok(())
+}
+
+fn remove_bind_groups<T: CreateComponentBindGroup>(world: &Arc<World>) -> anyhow::Result<()> {
+    for entity in world.removed_components::<T>() {
+        if world.has_component::<ComponentBindGroup<T>>(entity) && !world.has_component::<T>(entity)
+        {
+            world.remove_component::<ComponentBindGroup<T>>(entity);
         }
     }
 
@@ -197,7 +247,8 @@ impl<T: CreateComponentBindGroup + RenderAsset> Plugin for AssetBindGroupPlugin<
 }
 
 fn create_asset_bind_group<T: CreateComponentBindGroup + RenderAsset>(
-    world: &Arc<World>,
+    world: Arc<World>,
+    mut commands: Commands,
 ) -> anyhow::Result<()> {
     let renderer = world.get_resource::<Renderer>().unwrap();
     let device = renderer.device();
@@ -218,15 +269,13 @@ fn create_asset_bind_group<T: CreateComponentBindGroup + RenderAsset>(
         if let Some(bind_group_handle) = asset_bind_groups.bind_groups.get(&handle.into_untyped()) {
             let bind_group_handle =
                 Handle::<ComponentBindGroup<T>>::try_from(*bind_group_handle).unwrap();
-            drop(handle);
-            world.insert_component(entity, bind_group_handle);
+            commands.insert(entity, bind_group_handle);
         } else {
             let asset = assets.get::<T>(*handle).unwrap();
             let bind_group = ComponentBindGroup::new(device, asset);
             let bind_group_handle = assets.insert(bind_group);
             asset_bind_groups.insert(handle.into_untyped(), bind_group_handle.into_untyped());
-            drop(handle);
-            world.insert_component(entity, bind_group_handle);
+            commands.insert(entity, bind_group_handle);
         }
     }
 
@@ -4,7 +4,7 @@ use wgpu::util::DeviceExt;
 
 use weaver_app::plugin::Plugin;
 use weaver_ecs::{
-    prelude::{Component, Entity},
+    prelude::{Changed, Component, Entity},
     world::World,
 };
 
@@ -128,6 +128,7 @@ pub struct GpuCamera {
 
 impl RenderComponent for GpuCamera {
     type ExtractQuery<'a> = &'a Camera;
+    type UpdateFilter = Changed<Camera>;
 
     fn extract_render_component(entity: Entity, world: &World, renderer: &Renderer) -> Option<Self>
     where
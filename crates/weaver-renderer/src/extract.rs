@@ -2,9 +2,10 @@ use std::{any::type_name, sync::Arc};
 
 use weaver_app::{plugin::Plugin, prelude::App, system::SystemStage};
 use weaver_ecs::{
+    commands::Commands,
     component::{Component, Resource},
     entity::Entity,
-    query::QueryFetch,
+    query::{QueryFetch, QueryFilter},
     world::World,
 };
 
@@ -12,6 +13,7 @@ use crate::Renderer;
 
 pub trait RenderComponent: Component {
     type ExtractQuery<'a>: QueryFetch + 'a;
+    type UpdateFilter: QueryFilter;
     fn extract_render_component(entity: Entity, world: &World, renderer: &Renderer) -> Option<Self>
     where
         Self: Sized;
@@ -34,12 +36,16 @@ impl<T: RenderComponent> Default for RenderComponentPlugin<T> {
 impl<T: RenderComponent> Plugin for RenderComponentPlugin<T> {
     fn build(&self, app: &mut App) -> anyhow::Result<()> {
         app.add_system(extract_render_components::<T>, SystemStage::Extract)?;
+        app.add_system(remove_render_components::<T>, SystemStage::Extract)?;
         app.add_system(update_render_components::<T>, SystemStage::PreRender)?;
         Ok(())
     }
 }
 
-fn extract_render_components<T: RenderComponent>(world: &Arc<World>) -> anyhow::Result<()> {
+fn extract_render_components<T: RenderComponent>(
+    world: Arc<World>,
+    mut commands: Commands,
+) -> anyhow::Result<()> {
     let query = world.query::<T::ExtractQuery<'_>>();
     let renderer = world
         .get_resource::<Renderer>()
@@ -47,9 +53,9 @@ fn extract_render_components<T: RenderComponent>(world: &Arc<World>) -> anyhow::
 
     for entity in query.entity_iter() {
         if !world.has_component::<T>(entity) {
-            if let Some(component) = T::extract_render_component(entity, world, &renderer) {
+            if let Some(component) = T::extract_render_component(entity, &world, &renderer) {
                 log::debug!("Extracted render component: {:?}", type_name::<T>());
-                world.insert_component(entity, component);
+                commands.insert(entity, component);
             }
         }
     }
@@ -57,8 +63,38 @@ fn extract_render_components<T: RenderComponent>(world: &Arc<World>) -> anyhow::
     Ok(())
 }
 
-fn update_render_components<T: RenderComponent>(world: &Arc<World>) -> anyhow::Result<()> {
+fn remove_render_components<T: RenderComponent>(
+    world: Arc<World>,
+    mut commands: Commands,
+) -> anyhow::Result<()> {
+    // entities that lost one of the components the render component is extracted from
+    let removed = {
+        let storage = world.storage().read();
+        T::ExtractQuery::access()
+            .iter()
+            .flat_map(|(type_id, _)| storage.removed_by_id((*type_id).into()).to_vec())
+            .collect::<Vec<_>>()
+    };
+
+    if removed.is_empty() {
+        return Ok(());
+    }
+
     let query = world.query::<T::ExtractQuery<'_>>();
+
+    for entity in removed {
+        if world.has_component::<T>(entity) && query.get(entity).is_none() {
+            log::debug!("Removed render component: {:?}", type_name::<T>());
+            commands.remove::<T>(entity);
+        }
+    }
+
+    Ok(())
+}
+
+fn update_render_components<T: RenderComponent>(world: &Arc<World>) -> anyhow::Result<()> {
+    // only update render components whose source data changed since the last update
+    let query = world.query_filtered::<T::ExtractQuery<'_>, T::UpdateFilter>();
     let renderer = world
         .get_resource::<Renderer>()
         .expect("Renderer resource not present before updating render components");
@@ -2,7 +2,11 @@ use weaver_app::{plugin::Plugin, App};
 use wgpu::util::DeviceExt;
 
 use weaver_core::transform::Transform;
-use weaver_ecs::{entity::Entity, prelude::Component, world::World};
+use weaver_ecs::{
+    entity::Entity,
+    prelude::{Changed, Component},
+    world::World,
+};
 
 use crate::{
     bind_group::{CreateComponentBindGroup, ComponentBindGroupPlugin},
@@ -18,6 +22,7 @@ pub struct GpuTransform {
 
 impl RenderComponent for GpuTransform {
     type ExtractQuery<'a> = &'a Transform;
+    type UpdateFilter = Changed<Transform>;
 
     fn extract_render_component(entity: Entity, world: &World, renderer: &Renderer) -> Option<Self>
     where
@@ -1,6 +1,6 @@
 use std::ops::Deref;
 
-use weaver_app::{plugin::Plugin, prelude::App, system::SystemStage, Runner};
+use weaver_app::{plugin::Plugin, prelude::App, schedule::LoopPhase, Runner};
 use weaver_core::input::Input;
 use weaver_ecs::prelude::Resource;
 use weaver_util::{lock::Lock, prelude::Result};
@@ -77,9 +77,7 @@ struct WinitRunner {
 
 impl Runner for WinitRunner {
     fn run(&self, app: &mut App) -> Result<()> {
-        app.run_systems(SystemStage::PreInit)?;
-        app.run_systems(SystemStage::Init)?;
-        app.run_systems(SystemStage::PostInit)?;
+        app.run_phase(LoopPhase::Startup)?;
 
         let event_loop = self.event_loop.write().take().unwrap();
 
@@ -121,30 +119,13 @@ impl Runner for WinitRunner {
                                     });
                                 }
                                 WindowEvent::CloseRequested => {
-                                    app.run_systems(SystemStage::PreShutdown).unwrap();
-                                    app.run_systems(SystemStage::Shutdown).unwrap();
-                                    app.run_systems(SystemStage::PostShutdown).unwrap();
+                                    app.run_phase(LoopPhase::Shutdown).unwrap();
                                     event_loop_window.exit();
                                 }
                                 WindowEvent::RedrawRequested => {
                                     app.world().update();
 
-                                    app.run_systems(SystemStage::PreUpdate).unwrap();
-                                    app.run_systems(SystemStage::Update).unwrap();
-                                    app.run_systems(SystemStage::PostUpdate).unwrap();
-
-                                    app.run_systems(SystemStage::PreUi).unwrap();
-                                    app.run_systems(SystemStage::Ui).unwrap();
-                                    app.run_systems(SystemStage::PostUi).unwrap();
-
-                                    app.run_systems(SystemStage::Extract).unwrap();
-                                    app.run_systems(SystemStage::PreRender).unwrap();
-                                    app.run_systems(SystemStage::Render).unwrap();
-                                    app.run_systems(SystemStage::RenderUi).unwrap();
-                                    // window.pre_present_notify();
-                                    app.run_systems(SystemStage::PostRender).unwrap();
-
-                                    app.run_systems(SystemStage::EventPump).unwrap();
+                                    app.run_phase(LoopPhase::Frame).unwrap();
                                 }
                                 _ => {}
                             }